@@ -0,0 +1,2 @@
+-- Add priority column to tasks table
+ALTER TABLE tasks ADD COLUMN priority INTEGER NOT NULL DEFAULT 2;
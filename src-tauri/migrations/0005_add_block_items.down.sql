@@ -0,0 +1,3 @@
+-- Work blocks stay on the plan as blocks without a task; their items go.
+DROP TABLE block_items;
+ALTER TABLE day_blocks DROP COLUMN kind;
@@ -0,0 +1,14 @@
+-- Distinguish atomic blocks from multi-task work blocks and store their items
+ALTER TABLE day_blocks ADD COLUMN kind TEXT NOT NULL DEFAULT 'atomic';
+
+CREATE TABLE IF NOT EXISTS block_items (
+    block_id TEXT NOT NULL,
+    position INTEGER NOT NULL,
+    task_id TEXT NOT NULL,
+    est_minutes INTEGER NOT NULL,
+    PRIMARY KEY (block_id, position),
+    FOREIGN KEY (block_id) REFERENCES day_blocks (id) ON DELETE CASCADE,
+    FOREIGN KEY (task_id) REFERENCES tasks (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_block_items_task_id ON block_items (task_id);
@@ -0,0 +1,10 @@
+-- Back to 15-minute slots, rounding the start down and the end up.
+ALTER TABLE day_blocks ADD COLUMN start_slot INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE day_blocks ADD COLUMN end_slot INTEGER NOT NULL DEFAULT 0;
+
+UPDATE day_blocks
+SET start_slot = start_min / 15,
+    end_slot = (start_min + length_min + 14) / 15;
+
+ALTER TABLE day_blocks DROP COLUMN start_min;
+ALTER TABLE day_blocks DROP COLUMN length_min;
@@ -0,0 +1,11 @@
+-- Store block times in minutes instead of slots.
+-- The frontend has always written 15-minute slots, so convert on that basis.
+ALTER TABLE day_blocks ADD COLUMN start_min INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE day_blocks ADD COLUMN length_min INTEGER NOT NULL DEFAULT 0;
+
+UPDATE day_blocks
+SET start_min = start_slot * 15,
+    length_min = (end_slot - start_slot) * 15;
+
+ALTER TABLE day_blocks DROP COLUMN start_slot;
+ALTER TABLE day_blocks DROP COLUMN end_slot;
@@ -0,0 +1,2 @@
+DROP TABLE session_tasks;
+DROP TABLE focus_sessions;
@@ -0,0 +1,19 @@
+-- Pomodoro history: one row per focus or break interval
+CREATE TABLE IF NOT EXISTS focus_sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL CHECK (kind IN ('focus', 'break')),
+    started_at TEXT NOT NULL,
+    ended_at TEXT NOT NULL,
+    minutes INTEGER NOT NULL,
+    completed INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS idx_focus_sessions_started_at ON focus_sessions (started_at);
+
+CREATE TABLE IF NOT EXISTS session_tasks (
+    session_id INTEGER NOT NULL,
+    task_id TEXT NOT NULL,
+    PRIMARY KEY (session_id, task_id),
+    FOREIGN KEY (session_id) REFERENCES focus_sessions (id) ON DELETE CASCADE,
+    FOREIGN KEY (task_id) REFERENCES tasks (id) ON DELETE CASCADE
+);
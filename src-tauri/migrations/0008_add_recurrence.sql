@@ -0,0 +1,2 @@
+-- Add RRULE-style recurrence rule to tasks table
+ALTER TABLE tasks ADD COLUMN recurrence TEXT;
@@ -0,0 +1,24 @@
+-- DROP COLUMN can't remove a column with a foreign key, so rebuild the table without it.
+-- Subtasks become top-level tasks.
+CREATE TABLE tasks_rollback (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    done INTEGER NOT NULL DEFAULT 0,
+    est_minutes INTEGER,
+    notes TEXT,
+    project TEXT,
+    tags TEXT,
+    created_at TEXT,
+    is_today INTEGER NOT NULL DEFAULT 0,
+    due TEXT,
+    priority INTEGER NOT NULL DEFAULT 2,
+    recurrence TEXT
+);
+
+INSERT INTO tasks_rollback (id, title, done, est_minutes, notes, project, tags, created_at, is_today, due, priority, recurrence)
+SELECT id, title, done, est_minutes, notes, project, tags, created_at, is_today, due, priority, recurrence
+FROM tasks;
+
+DROP INDEX idx_tasks_parent_id;
+DROP TABLE tasks;
+ALTER TABLE tasks_rollback RENAME TO tasks;
@@ -0,0 +1,31 @@
+-- DROP COLUMN can't remove a column with a foreign key, so rebuild the table, putting each
+-- task's project name back in the free-text column.
+CREATE TABLE tasks_rollback (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    done INTEGER NOT NULL DEFAULT 0,
+    est_minutes INTEGER,
+    notes TEXT,
+    project TEXT,
+    tags TEXT,
+    created_at TEXT,
+    is_today INTEGER NOT NULL DEFAULT 0,
+    due TEXT,
+    priority INTEGER NOT NULL DEFAULT 2,
+    recurrence TEXT,
+    parent_id TEXT REFERENCES tasks (id) ON DELETE CASCADE
+);
+
+INSERT INTO tasks_rollback (id, title, done, est_minutes, notes, project, tags, created_at, is_today, due, priority, recurrence, parent_id)
+SELECT t.id, t.title, t.done, t.est_minutes, t.notes, p.name, t.tags, t.created_at, t.is_today, t.due, t.priority, t.recurrence, t.parent_id
+FROM tasks t
+LEFT JOIN projects p ON p.id = t.project_id;
+
+DROP INDEX idx_tasks_project_id;
+DROP INDEX idx_tasks_parent_id;
+DROP TABLE tasks;
+ALTER TABLE tasks_rollback RENAME TO tasks;
+
+CREATE INDEX IF NOT EXISTS idx_tasks_parent_id ON tasks (parent_id);
+
+DROP TABLE projects;
@@ -0,0 +1,28 @@
+-- Replace the free-text tasks.project column with a projects table
+CREATE TABLE IF NOT EXISTS projects (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    color TEXT,
+    archived INTEGER NOT NULL DEFAULT 0,
+    sort_order INTEGER NOT NULL DEFAULT 0
+);
+
+-- One project per distinct name, ignoring case and surrounding whitespace
+INSERT INTO projects (id, name, sort_order)
+SELECT lower(hex(randomblob(16))), name, ROW_NUMBER() OVER (ORDER BY name COLLATE NOCASE) - 1
+FROM (
+    SELECT MIN(trim(project)) AS name
+    FROM tasks
+    WHERE project IS NOT NULL AND trim(project) <> ''
+    GROUP BY trim(project) COLLATE NOCASE
+);
+
+ALTER TABLE tasks ADD COLUMN project_id TEXT REFERENCES projects (id) ON DELETE SET NULL;
+
+UPDATE tasks
+SET project_id = (SELECT p.id FROM projects p WHERE p.name = trim(tasks.project) COLLATE NOCASE)
+WHERE project IS NOT NULL;
+
+ALTER TABLE tasks DROP COLUMN project;
+
+CREATE INDEX IF NOT EXISTS idx_tasks_project_id ON tasks (project_id);
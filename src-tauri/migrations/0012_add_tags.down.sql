@@ -0,0 +1,18 @@
+-- Back to a JSON array of names per task, in their original order.
+ALTER TABLE tasks ADD COLUMN tags TEXT;
+
+UPDATE tasks
+SET tags = (
+    SELECT json_group_array(name)
+    FROM (
+        SELECT g.name AS name
+        FROM task_tags tt
+        JOIN tags g ON g.id = tt.tag_id
+        WHERE tt.task_id = tasks.id
+        ORDER BY tt.position, g.name
+    )
+)
+WHERE EXISTS (SELECT 1 FROM task_tags tt WHERE tt.task_id = tasks.id);
+
+DROP TABLE task_tags;
+DROP TABLE tags;
@@ -0,0 +1,51 @@
+-- Move tags out of the tasks.tags JSON column into tags / task_tags
+CREATE TABLE IF NOT EXISTS tags (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE
+);
+
+CREATE TABLE IF NOT EXISTS task_tags (
+    task_id TEXT NOT NULL REFERENCES tasks (id) ON DELETE CASCADE,
+    tag_id TEXT NOT NULL REFERENCES tags (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (task_id, tag_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_task_tags_tag_id ON task_tags (tag_id);
+
+CREATE TEMP TABLE legacy_tags (task_id TEXT NOT NULL, name TEXT NOT NULL, position INTEGER NOT NULL);
+
+-- Well-formed JSON arrays: one tag per non-null element
+INSERT INTO legacy_tags (task_id, name, position)
+SELECT t.id, trim(CAST(j.value AS TEXT)), j.key
+FROM tasks t,
+     json_each(CASE WHEN json_valid(t.tags) AND json_type(t.tags) = 'array' THEN t.tags ELSE '[]' END) j
+WHERE j.type <> 'null' AND trim(CAST(j.value AS TEXT)) <> '';
+
+-- Anything else (a bare JSON string, a scalar, or malformed JSON) is kept verbatim as a single tag
+-- rather than dropped.
+INSERT INTO legacy_tags (task_id, name, position)
+SELECT id,
+       trim(CASE WHEN json_valid(tags) AND json_type(tags) = 'text' THEN json_extract(tags, '$') ELSE tags END),
+       0
+FROM tasks
+WHERE tags IS NOT NULL
+  AND trim(tags) <> ''
+  AND CASE WHEN json_valid(tags) THEN json_type(tags) NOT IN ('array', 'null') ELSE 1 END
+  AND trim(CASE WHEN json_valid(tags) AND json_type(tags) = 'text' THEN json_extract(tags, '$') ELSE tags END) <> '';
+
+-- One tag per distinct name, ignoring case
+INSERT INTO tags (id, name)
+SELECT lower(hex(randomblob(16))), MIN(name)
+FROM legacy_tags
+GROUP BY name COLLATE NOCASE;
+
+INSERT OR IGNORE INTO task_tags (task_id, tag_id, position)
+SELECT l.task_id, g.id, MIN(l.position)
+FROM legacy_tags l
+JOIN tags g ON g.name = l.name COLLATE NOCASE
+GROUP BY l.task_id, g.id;
+
+DROP TABLE legacy_tags;
+
+ALTER TABLE tasks DROP COLUMN tags;
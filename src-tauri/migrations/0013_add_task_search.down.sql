@@ -0,0 +1,9 @@
+DROP TRIGGER tasks_fts_after_insert;
+DROP TRIGGER tasks_fts_after_update;
+DROP TRIGGER tasks_fts_after_delete;
+DROP TRIGGER tasks_fts_after_tag_link;
+DROP TRIGGER tasks_fts_after_tag_unlink;
+DROP TRIGGER tasks_fts_after_tag_rename;
+DROP TRIGGER tasks_fts_after_project_rename;
+DROP VIEW tasks_fts_source;
+DROP TABLE tasks_fts;
@@ -0,0 +1,66 @@
+-- Full-text index over task titles, notes, project names and tags.
+-- Each row is rebuilt from the source tables whenever any of them changes.
+CREATE VIRTUAL TABLE IF NOT EXISTS tasks_fts USING fts5 (
+    task_id UNINDEXED,
+    title,
+    notes,
+    project,
+    tags,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE VIEW IF NOT EXISTS tasks_fts_source AS
+SELECT t.id AS task_id,
+       t.title AS title,
+       COALESCE(t.notes, '') AS notes,
+       COALESCE(p.name, '') AS project,
+       COALESCE(
+           (SELECT group_concat(g.name, ' ') FROM task_tags tt JOIN tags g ON g.id = tt.tag_id WHERE tt.task_id = t.id),
+           ''
+       ) AS tags
+FROM tasks t
+LEFT JOIN projects p ON p.id = t.project_id;
+
+INSERT INTO tasks_fts (task_id, title, notes, project, tags)
+SELECT task_id, title, notes, project, tags FROM tasks_fts_source;
+
+CREATE TRIGGER IF NOT EXISTS tasks_fts_after_insert AFTER INSERT ON tasks BEGIN
+    INSERT INTO tasks_fts (task_id, title, notes, project, tags)
+    SELECT task_id, title, notes, project, tags FROM tasks_fts_source WHERE task_id = new.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS tasks_fts_after_update AFTER UPDATE OF id, title, notes, project_id ON tasks BEGIN
+    DELETE FROM tasks_fts WHERE task_id = old.id;
+    INSERT INTO tasks_fts (task_id, title, notes, project, tags)
+    SELECT task_id, title, notes, project, tags FROM tasks_fts_source WHERE task_id = new.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS tasks_fts_after_delete AFTER DELETE ON tasks BEGIN
+    DELETE FROM tasks_fts WHERE task_id = old.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS tasks_fts_after_tag_link AFTER INSERT ON task_tags BEGIN
+    DELETE FROM tasks_fts WHERE task_id = new.task_id;
+    INSERT INTO tasks_fts (task_id, title, notes, project, tags)
+    SELECT task_id, title, notes, project, tags FROM tasks_fts_source WHERE task_id = new.task_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS tasks_fts_after_tag_unlink AFTER DELETE ON task_tags BEGIN
+    DELETE FROM tasks_fts WHERE task_id = old.task_id;
+    INSERT INTO tasks_fts (task_id, title, notes, project, tags)
+    SELECT task_id, title, notes, project, tags FROM tasks_fts_source WHERE task_id = old.task_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS tasks_fts_after_tag_rename AFTER UPDATE OF name ON tags BEGIN
+    DELETE FROM tasks_fts WHERE task_id IN (SELECT task_id FROM task_tags WHERE tag_id = new.id);
+    INSERT INTO tasks_fts (task_id, title, notes, project, tags)
+    SELECT task_id, title, notes, project, tags FROM tasks_fts_source
+    WHERE task_id IN (SELECT task_id FROM task_tags WHERE tag_id = new.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS tasks_fts_after_project_rename AFTER UPDATE OF name ON projects BEGIN
+    DELETE FROM tasks_fts WHERE task_id IN (SELECT id FROM tasks WHERE project_id = new.id);
+    INSERT INTO tasks_fts (task_id, title, notes, project, tags)
+    SELECT task_id, title, notes, project, tags FROM tasks_fts_source
+    WHERE task_id IN (SELECT id FROM tasks WHERE project_id = new.id);
+END;
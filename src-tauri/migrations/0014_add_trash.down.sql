@@ -0,0 +1,22 @@
+-- Versions without a trash deleted tasks outright, so finish deleting what's in it.
+-- Rollbacks run with foreign keys off, so nothing cascades; clear dependent rows by hand.
+CREATE TEMP TABLE trashed AS SELECT id FROM tasks WHERE deleted_at IS NOT NULL;
+
+UPDATE tasks SET parent_id = NULL
+WHERE deleted_at IS NULL AND parent_id IN (SELECT id FROM trashed);
+
+DELETE FROM block_items
+WHERE task_id IN (SELECT id FROM trashed)
+   OR block_id IN (SELECT id FROM day_blocks WHERE task_id IN (SELECT id FROM trashed));
+DELETE FROM day_blocks WHERE task_id IN (SELECT id FROM trashed);
+DELETE FROM session_tasks WHERE task_id IN (SELECT id FROM trashed);
+DELETE FROM task_dependencies
+WHERE task_id IN (SELECT id FROM trashed) OR depends_on_id IN (SELECT id FROM trashed);
+DELETE FROM task_tags WHERE task_id IN (SELECT id FROM trashed);
+DELETE FROM tasks WHERE id IN (SELECT id FROM trashed);
+DELETE FROM tags WHERE id NOT IN (SELECT tag_id FROM task_tags);
+
+DROP TABLE trashed;
+
+DROP INDEX idx_tasks_deleted_at;
+ALTER TABLE tasks DROP COLUMN deleted_at;
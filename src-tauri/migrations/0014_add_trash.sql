@@ -0,0 +1,8 @@
+-- Soft delete: trashed tasks keep their rows until the trash is emptied
+ALTER TABLE tasks ADD COLUMN deleted_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_tasks_deleted_at ON tasks (deleted_at);
+
+-- Blocks left pointing at tasks that were hard-deleted before this migration
+DELETE FROM day_blocks
+WHERE task_id IS NOT NULL AND NOT EXISTS (SELECT 1 FROM tasks t WHERE t.id = day_blocks.task_id);
@@ -0,0 +1,3 @@
+DROP INDEX idx_tasks_completed_at;
+ALTER TABLE tasks DROP COLUMN completed_at;
+ALTER TABLE tasks DROP COLUMN archived_at;
@@ -0,0 +1,5 @@
+-- When a task was finished, and when old finished work was archived out of the default lists
+ALTER TABLE tasks ADD COLUMN completed_at TEXT;
+ALTER TABLE tasks ADD COLUMN archived_at TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_tasks_completed_at ON tasks (completed_at);
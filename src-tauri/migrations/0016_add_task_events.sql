@@ -0,0 +1,16 @@
+-- Append-only change log behind undo/redo. Each row holds one entity's JSON before and
+-- after a command; rows written by the same command share a change_id.
+CREATE TABLE IF NOT EXISTS task_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    change_id INTEGER NOT NULL,
+    at TEXT NOT NULL,
+    command TEXT NOT NULL,
+    entity TEXT NOT NULL CHECK (entity IN ('task', 'day', 'project', 'session')),
+    entity_id TEXT NOT NULL,
+    before TEXT,
+    after TEXT,
+    undone INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS idx_task_events_change_id ON task_events (change_id);
+CREATE INDEX IF NOT EXISTS idx_task_events_entity ON task_events (entity, entity_id);
@@ -0,0 +1,8 @@
+-- Record of destructive operations. Unlike task_events this is never cleared, so it
+-- outlives the data it describes.
+CREATE TABLE IF NOT EXISTS audit_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    at TEXT NOT NULL,
+    action TEXT NOT NULL,
+    detail TEXT
+);
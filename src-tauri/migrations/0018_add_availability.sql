@@ -0,0 +1,34 @@
+-- Working hours for each day of the week, 0 = Monday. A day that isn't worked keeps its
+-- hours so switching it back on restores them.
+CREATE TABLE IF NOT EXISTS availability_days (
+    weekday INTEGER PRIMARY KEY CHECK (weekday BETWEEN 0 AND 6),
+    working INTEGER NOT NULL DEFAULT 1,
+    start_min INTEGER NOT NULL,
+    end_min INTEGER NOT NULL,
+    lunch_start_min INTEGER,
+    lunch_length_min INTEGER NOT NULL DEFAULT 0,
+    CHECK (start_min < end_min)
+);
+
+INSERT OR IGNORE INTO availability_days (weekday, working, start_min, end_min) VALUES
+    (0, 1, 540, 1020),
+    (1, 1, 540, 1020),
+    (2, 1, 540, 1020),
+    (3, 1, 540, 1020),
+    (4, 1, 540, 1020),
+    (5, 0, 540, 1020),
+    (6, 0, 540, 1020);
+
+-- Time that can't be planned: every week on `weekday`, or once on `date`.
+CREATE TABLE IF NOT EXISTS blocked_periods (
+    id TEXT PRIMARY KEY,
+    weekday INTEGER CHECK (weekday BETWEEN 0 AND 6),
+    date TEXT,
+    start_min INTEGER NOT NULL,
+    end_min INTEGER NOT NULL,
+    label TEXT,
+    CHECK ((weekday IS NULL) <> (date IS NULL)),
+    CHECK (start_min < end_min)
+);
+
+CREATE INDEX IF NOT EXISTS idx_blocked_periods_date ON blocked_periods (date);
@@ -0,0 +1 @@
+DROP INDEX idx_day_blocks_date;
@@ -0,0 +1,2 @@
+-- Every block query filters on date, either one day or a range of them.
+CREATE INDEX IF NOT EXISTS idx_day_blocks_date ON day_blocks (date, start_min);
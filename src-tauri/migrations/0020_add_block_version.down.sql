@@ -0,0 +1 @@
+ALTER TABLE day_blocks DROP COLUMN version;
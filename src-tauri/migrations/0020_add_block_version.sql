@@ -0,0 +1,3 @@
+-- Bumped on every change to a block, so a stale edit can be refused instead of
+-- overwriting a newer one.
+ALTER TABLE day_blocks ADD COLUMN version INTEGER NOT NULL DEFAULT 1;
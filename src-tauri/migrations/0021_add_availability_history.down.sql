@@ -0,0 +1,19 @@
+-- Older versions can't undo availability changes, so their events are dropped.
+CREATE TABLE task_events_old (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    change_id INTEGER NOT NULL,
+    at TEXT NOT NULL,
+    command TEXT NOT NULL,
+    entity TEXT NOT NULL CHECK (entity IN ('task', 'day', 'project', 'session')),
+    entity_id TEXT NOT NULL,
+    before TEXT,
+    after TEXT,
+    undone INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT INTO task_events_old SELECT * FROM task_events WHERE entity <> 'availability';
+DROP TABLE task_events;
+ALTER TABLE task_events_old RENAME TO task_events;
+
+CREATE INDEX IF NOT EXISTS idx_task_events_change_id ON task_events (change_id);
+CREATE INDEX IF NOT EXISTS idx_task_events_entity ON task_events (entity, entity_id);
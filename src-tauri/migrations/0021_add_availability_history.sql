@@ -0,0 +1,21 @@
+-- Availability changes go through undo/redo too. SQLite can't change a CHECK constraint
+-- in place, so the log is copied into a table that allows the new entity.
+CREATE TABLE task_events_new (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    change_id INTEGER NOT NULL,
+    at TEXT NOT NULL,
+    command TEXT NOT NULL,
+    entity TEXT NOT NULL
+        CHECK (entity IN ('task', 'day', 'project', 'session', 'availability')),
+    entity_id TEXT NOT NULL,
+    before TEXT,
+    after TEXT,
+    undone INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT INTO task_events_new SELECT * FROM task_events;
+DROP TABLE task_events;
+ALTER TABLE task_events_new RENAME TO task_events;
+
+CREATE INDEX IF NOT EXISTS idx_task_events_change_id ON task_events (change_id);
+CREATE INDEX IF NOT EXISTS idx_task_events_entity ON task_events (entity, entity_id);
@@ -1,10 +1,23 @@
-use crate::db::Database;
-use crate::models::{DayBlock, EnrichResponse, PlanWithAIResponse, RefineResponse, Task};
+use crate::db::history::{self, Entity};
+use crate::db::{
+    audit, availability, backup, blocks, dependencies, migrations, projects, purge, recovery,
+    search, sessions, tags, tasks, Database, DbError, Recovery,
+};
+use crate::models::{
+    AuditEntry, Availability, BackupInfo, ChildPolicy, DayBlock, DayPlan, EnrichResponse,
+    FocusSession, HistoryChange, HistoryEvent, MigrationReport, NewFocusSession, Occurrence,
+    PlanWithAIResponse, Project, PurgeSummary, RecoveryReport, RefineResponse, SalvageReport,
+    SaveBlocksReport, SaveMode, SearchResult, SessionRange, Tag, Task, TaskFilter, TaskNode,
+    TaskPage, TaskSort,
+};
+use crate::scheduler::{self, Schedule, ScheduleOptions};
+use crate::timer::{TimerService, TimerStatus};
+use chrono::{Local, NaiveDate, Utc};
 use rusqlite::{params, OptionalExtension};
 use serde::{Deserialize, Serialize};
 use tauri::State;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 pub struct CommandError {
     pub message: String,
 }
@@ -17,6 +30,14 @@ impl From<rusqlite::Error> for CommandError {
     }
 }
 
+impl From<DbError> for CommandError {
+    fn from(error: DbError) -> Self {
+        CommandError {
+            message: error.to_string(),
+        }
+    }
+}
+
 impl From<serde_json::Error> for CommandError {
     fn from(error: serde_json::Error) -> Self {
         CommandError {
@@ -47,145 +68,841 @@ impl<'a> From<&'a str> for CommandError {
     }
 }
 
+/// Fails while the app is in recovery mode, so nothing reads or changes a database that
+/// didn't pass the startup checks. The recovery, backup and export commands don't call this.
+pub(crate) fn ensure_ready(recovery: &Recovery) -> Result<(), CommandError> {
+    match recovery.0.lock().unwrap().as_ref() {
+        Some(report) => Err(format!(
+            "The database needs recovery: {}",
+            report.problems.join("; ")
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
+
 #[tauri::command]
-pub fn get_tasks(db: State<Database>) -> Result<Vec<Task>, CommandError> {
+pub fn get_tasks(
+    tag: Option<String>,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Vec<Task>, CommandError> {
+    ensure_ready(&recovery)?;
     let conn = db.0.lock().unwrap();
-    let mut stmt =
-        conn.prepare("SELECT id, title, done, is_today, est_minutes, notes, project, tags, due FROM tasks")?;
-    let task_iter = stmt.query_map(params![], |row| {
-        let tags_json: Option<String> = row.get(7)?;
-        let tags: Option<Vec<String>> = match tags_json {
-            Some(json) if !json.is_empty() => match serde_json::from_str(&json) {
-                Ok(tags) => Some(tags),
-                Err(_) => Some(vec![]),
-            },
-            _ => Some(vec![]),
-        };
-
-        Ok(Task {
-            id: row.get(0)?,
-            title: row.get(1)?,
-            done: row.get(2)?,
-            is_today: row.get(3)?,
-            est_minutes: row.get(4)?,
-            notes: row.get(5)?,
-            project: row.get(6)?,
-            tags,
-            due: row.get(8)?,
-        })
-    })?;
-
-    let mut tasks = Vec::new();
-    for task in task_iter {
-        tasks.push(task?);
+    match tag {
+        Some(tag) => Ok(tasks::list_tasks_tagged(&conn, &tag)?),
+        None => Ok(tasks::list_tasks(&conn)?),
     }
+}
 
-    Ok(tasks)
+#[tauri::command]
+pub fn query_tasks(
+    filter: Option<TaskFilter>,
+    sort: Option<TaskSort>,
+    cursor: Option<String>,
+    limit: Option<u32>,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<TaskPage, CommandError> {
+    ensure_ready(&recovery)?;
+    let conn = db.0.lock().unwrap();
+    Ok(tasks::query_tasks(
+        &conn,
+        &filter.unwrap_or_default(),
+        sort.unwrap_or_default(),
+        cursor.as_deref(),
+        limit.unwrap_or(tasks::DEFAULT_PAGE_SIZE),
+    )?)
 }
 
 #[tauri::command]
-pub fn add_task(task: Task, db: State<Database>) -> Result<(), CommandError> {
+pub fn search_tasks(
+    query: String,
+    limit: Option<u32>,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Vec<SearchResult>, CommandError> {
+    ensure_ready(&recovery)?;
     let conn = db.0.lock().unwrap();
-    let tags_json = serde_json::to_string(&task.tags)?;
-    conn.execute(
-        "INSERT INTO tasks (id, title, done, is_today, est_minutes, notes, project, tags, due) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-        params![
-            task.id,
-            task.title,
-            task.done,
-            task.is_today,
-            task.est_minutes,
-            task.notes,
-            task.project,
-            tags_json,
-            task.due,
-        ],
+    Ok(search::search_tasks(
+        &conn,
+        &query,
+        limit.unwrap_or(search::DEFAULT_LIMIT),
+    )?)
+}
+
+#[tauri::command]
+pub fn add_task(
+    task: Task,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<(), CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    history::record(
+        &mut conn,
+        "add_task",
+        |_| Ok(Vec::new()),
+        |conn| tasks::insert_task(conn, &task),
+        |_| vec![Entity::Task(task.id.clone())],
+    )?;
+    Ok(())
+}
+
+/// Returns the next instance when this update completed a recurring task.
+#[tauri::command]
+pub fn update_task(
+    task: Task,
+    complete_children: Option<bool>,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Option<Task>, CommandError> {
+    ensure_ready(&recovery)?;
+    let complete_children = complete_children.unwrap_or(false);
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "update_task",
+        |conn| {
+            if complete_children {
+                history::task_subtree(conn, &task.id)
+            } else {
+                Ok(vec![Entity::Task(task.id.clone())])
+            }
+        },
+        |conn| tasks::update_task(conn, &task, complete_children),
+        |next| next.iter().map(|t| Entity::Task(t.id.clone())).collect(),
+    )?)
+}
+
+#[tauri::command]
+pub fn delete_task(
+    id: String,
+    children: Option<ChildPolicy>,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<(), CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    history::record(
+        &mut conn,
+        "delete_task",
+        |conn| history::task_subtree(conn, &id),
+        |conn| tasks::delete_task(conn, &id, children.unwrap_or_default()),
+        |_| Vec::new(),
     )?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn update_task(task: Task, db: State<Database>) -> Result<(), CommandError> {
+pub fn archive_completed(
+    older_than: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<usize, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "archive_completed",
+        |conn| {
+            history::tasks_where(
+                conn,
+                "done = 1 AND archived_at IS NULL AND deleted_at IS NULL",
+                &[],
+            )
+        },
+        |conn| tasks::archive_completed(conn, &older_than),
+        |_| Vec::new(),
+    )?)
+}
+
+#[tauri::command]
+pub fn list_trash(
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Vec<Task>, CommandError> {
+    ensure_ready(&recovery)?;
     let conn = db.0.lock().unwrap();
-    let tags_json = serde_json::to_string(&task.tags)?;
-    conn.execute(
-        "UPDATE tasks SET title = ?2, done = ?3, is_today = ?4, est_minutes = ?5, notes = ?6, project = ?7, tags = ?8, due = ?9 WHERE id = ?1",
-        params![
-            task.id,
-            task.title,
-            task.done,
-            task.is_today,
-            task.est_minutes,
-            task.notes,
-            task.project,
-            tags_json,
-            task.due
-        ],
+    Ok(tasks::list_trash(&conn)?)
+}
+
+#[tauri::command]
+pub fn restore_task(
+    id: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Vec<Task>, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "restore_task",
+        |conn| history::task_subtree(conn, &id),
+        |conn| tasks::restore_task(conn, &id),
+        |_| Vec::new(),
+    )?)
+}
+
+#[tauri::command]
+pub fn empty_trash(db: State<Database>, recovery: State<Recovery>) -> Result<usize, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "empty_trash",
+        |conn| {
+            let trashed = "deleted_at IS NOT NULL";
+            let mut touched = history::tasks_where(conn, trashed, &[])?;
+            touched.extend(history::tasks_where(
+                conn,
+                "parent_id IN (SELECT id FROM tasks WHERE deleted_at IS NOT NULL)",
+                &[],
+            )?);
+            touched.extend(history::days_planning(conn, trashed, &[])?);
+            Ok(touched)
+        },
+        tasks::empty_trash,
+        |_| Vec::new(),
+    )?)
+}
+
+#[tauri::command]
+pub fn get_task_tree(
+    root_id: Option<String>,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Vec<TaskNode>, CommandError> {
+    ensure_ready(&recovery)?;
+    let conn = db.0.lock().unwrap();
+    Ok(tasks::task_tree(&conn, root_id.as_deref())?)
+}
+
+#[tauri::command]
+pub fn add_dependency(
+    task_id: String,
+    depends_on_id: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<(), CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    history::record(
+        &mut conn,
+        "add_dependency",
+        |_| Ok(vec![Entity::Task(task_id.clone())]),
+        |conn| dependencies::add_dependency(conn, &task_id, &depends_on_id),
+        |_| Vec::new(),
     )?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn delete_task(id: String, db: State<Database>) -> Result<(), CommandError> {
+pub fn remove_dependency(
+    task_id: String,
+    depends_on_id: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<(), CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    history::record(
+        &mut conn,
+        "remove_dependency",
+        |_| Ok(vec![Entity::Task(task_id.clone())]),
+        |conn| dependencies::remove_dependency(conn, &task_id, &depends_on_id),
+        |_| Vec::new(),
+    )?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_dependencies(
+    task_id: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Vec<String>, CommandError> {
+    ensure_ready(&recovery)?;
+    let conn = db.0.lock().unwrap();
+    Ok(dependencies::list_dependencies(&conn, &task_id)?)
+}
+
+#[tauri::command]
+pub fn expand_recurrences(
+    start: String,
+    end: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Vec<Occurrence>, CommandError> {
+    ensure_ready(&recovery)?;
+    let start = NaiveDate::parse_from_str(&start, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid start date: {}", start))?;
+    let end = NaiveDate::parse_from_str(&end, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid end date: {}", end))?;
     let conn = db.0.lock().unwrap();
-    conn.execute("DELETE FROM tasks WHERE id = ?1", params![id])?;
+    Ok(tasks::expand_occurrences(&conn, start, end)?)
+}
+
+#[tauri::command]
+pub fn get_projects(
+    include_archived: Option<bool>,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Vec<Project>, CommandError> {
+    ensure_ready(&recovery)?;
+    let conn = db.0.lock().unwrap();
+    Ok(projects::list_projects(&conn, include_archived.unwrap_or(false))?)
+}
+
+#[tauri::command]
+pub fn create_project(
+    name: String,
+    color: Option<String>,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Project, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "create_project",
+        |_| Ok(Vec::new()),
+        |conn| projects::create_project(conn, &name, color),
+        |project| vec![Entity::Project(project.id.clone())],
+    )?)
+}
+
+#[tauri::command]
+pub fn update_project(
+    project: Project,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Project, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "update_project",
+        |_| Ok(vec![Entity::Project(project.id.clone())]),
+        |conn| projects::update_project(conn, &project),
+        |_| Vec::new(),
+    )?)
+}
+
+#[tauri::command]
+pub fn rename_project(
+    id: String,
+    name: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Project, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "rename_project",
+        |_| Ok(vec![Entity::Project(id.clone())]),
+        |conn| projects::rename_project(conn, &id, &name),
+        |_| Vec::new(),
+    )?)
+}
+
+#[tauri::command]
+pub fn delete_project(
+    id: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<(), CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    history::record(
+        &mut conn,
+        "delete_project",
+        |conn| {
+            // Tasks first: undo walks the change backwards, so the project is back before them.
+            let mut touched = history::tasks_where(conn, "project_id = ?1", &[&id])?;
+            touched.push(Entity::Project(id.clone()));
+            Ok(touched)
+        },
+        |conn| projects::delete_project(conn, &id),
+        |_| Vec::new(),
+    )?;
     Ok(())
 }
 
 #[tauri::command]
-pub fn get_blocks_for_date(date: String, db: State<Database>) -> Result<Vec<DayBlock>, CommandError> {
+pub fn list_tags(db: State<Database>, recovery: State<Recovery>) -> Result<Vec<Tag>, CommandError> {
+    ensure_ready(&recovery)?;
     let conn = db.0.lock().unwrap();
-    let mut stmt = conn.prepare("SELECT id, task_id, date, start_slot, end_slot FROM day_blocks WHERE date = ?1")?;
-    let block_iter = stmt.query_map(params![date], |row| {
-        Ok(DayBlock {
-            id: row.get(0)?,
-            task_id: row.get(1)?,
-            date: row.get(2)?,
-            start_slot: row.get(3)?,
-            end_slot: row.get(4)?,
-        })
-    })?;
+    Ok(tags::list_tags(&conn)?)
+}
 
-    let mut blocks = Vec::new();
-    for block in block_iter {
-        blocks.push(block?);
-    }
-    Ok(blocks)
+#[tauri::command]
+pub fn rename_tag(
+    id: String,
+    name: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Tag, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "rename_tag",
+        |conn| {
+            history::tasks_where(
+                conn,
+                "id IN (SELECT task_id FROM task_tags WHERE tag_id = ?1)",
+                &[&id],
+            )
+        },
+        |conn| tags::rename_tag(conn, &id, &name),
+        |_| Vec::new(),
+    )?)
+}
+
+#[tauri::command]
+pub fn merge_tags(
+    source_ids: Vec<String>,
+    target_id: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Tag, CommandError> {
+    ensure_ready(&recovery)?;
+    let sources = serde_json::to_string(&source_ids)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "merge_tags",
+        |conn| {
+            history::tasks_where(
+                conn,
+                "id IN (SELECT task_id FROM task_tags WHERE tag_id IN (SELECT value FROM json_each(?1)))",
+                &[&sources],
+            )
+        },
+        |conn| tags::merge_tags(conn, &source_ids, &target_id),
+        |_| Vec::new(),
+    )?)
+}
+
+#[tauri::command]
+pub fn get_blocks_for_date(
+    date: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Vec<DayBlock>, CommandError> {
+    ensure_ready(&recovery)?;
+    let conn = db.0.lock().unwrap();
+    Ok(blocks::list_blocks_for_date(&conn, &date)?)
+}
+
+/// Blocks for every day from `start` to `end` inclusive, for week and month views.
+#[tauri::command]
+pub fn get_blocks_in_range(
+    start: String,
+    end: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Vec<DayPlan>, CommandError> {
+    ensure_ready(&recovery)?;
+    let conn = db.0.lock().unwrap();
+    Ok(blocks::blocks_in_range(&conn, &start, &end)?)
 }
 
+/// Saves `date`'s whole plan at once, for bulk changes, and reports any conflicts in it. In
+/// strict mode nothing is saved if there are conflicts; in lenient mode, the default,
+/// whatever can be stored is saved. Single-block edits should use the block commands below.
 #[tauri::command]
 pub fn save_blocks_for_date(
     date: String,
     blocks: Vec<DayBlock>,
+    mode: Option<SaveMode>,
     db: State<Database>,
-) -> Result<(), CommandError> {
+    recovery: State<Recovery>,
+) -> Result<SaveBlocksReport, CommandError> {
+    ensure_ready(&recovery)?;
     let mut conn = db.0.lock().unwrap();
-    let tx = conn.transaction()?;
-    tx.execute("DELETE FROM day_blocks WHERE date = ?1", params![date])?;
-    for block in blocks {
-        tx.execute(
-            "INSERT INTO day_blocks (id, task_id, date, start_slot, end_slot) VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![block.id, block.task_id, block.date, block.start_slot, block.end_slot],
-        )?;
+    let conflicts = blocks::find_conflicts(&conn, &date, &blocks)?;
+    if mode.unwrap_or_default() == SaveMode::Strict && !conflicts.is_empty() {
+        return Ok(SaveBlocksReport {
+            saved: false,
+            conflicts,
+        });
     }
-    tx.commit()?;
-    Ok(())
+    let kept = blocks::without_conflicts(&blocks, &conflicts);
+    history::record(
+        &mut conn,
+        "save_blocks_for_date",
+        |_| Ok(vec![Entity::Day(date.clone())]),
+        |conn| Ok(blocks::replace_blocks_for_date(conn, &date, &kept)?),
+        |_| Vec::new(),
+    )?;
+    Ok(SaveBlocksReport {
+        saved: true,
+        conflicts,
+    })
+}
+
+/// Proposes blocks for `task_ids` on `date` around what's already planned. Nothing is saved;
+/// the frontend adds the blocks it keeps and saves the day as usual.
+#[tauri::command]
+pub fn auto_schedule(
+    date: String,
+    task_ids: Vec<String>,
+    options: Option<ScheduleOptions>,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Schedule, CommandError> {
+    ensure_ready(&recovery)?;
+    let conn = db.0.lock().unwrap();
+    Ok(scheduler::auto_schedule(&conn, &date, &task_ids, &options.unwrap_or_default())?)
+}
+
+#[tauri::command]
+pub fn create_block(
+    block: DayBlock,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<DayBlock, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "create_block",
+        |_| Ok(vec![Entity::Day(block.date.clone())]),
+        |conn| blocks::create_block(conn, &block),
+        |_| Vec::new(),
+    )?)
+}
+
+/// Moves a block, possibly to another day. `version` is the one the caller last saw; the
+/// move is refused if the block has changed since.
+#[tauri::command]
+pub fn move_block(
+    id: String,
+    version: i64,
+    date: String,
+    start_min: i32,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<DayBlock, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "move_block",
+        |conn| {
+            let from = blocks::get_block(conn, &id)?.date;
+            Ok(vec![Entity::Day(from), Entity::Day(date.clone())])
+        },
+        |conn| blocks::move_block(conn, &id, version, &date, start_min),
+        |_| Vec::new(),
+    )?)
+}
+
+#[tauri::command]
+pub fn resize_block(
+    id: String,
+    version: i64,
+    length_min: i32,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<DayBlock, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "resize_block",
+        |conn| Ok(vec![Entity::Day(blocks::get_block(conn, &id)?.date)]),
+        |conn| blocks::resize_block(conn, &id, version, length_min),
+        |_| Vec::new(),
+    )?)
+}
+
+#[tauri::command]
+pub fn delete_block(
+    id: String,
+    version: i64,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<DayBlock, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "delete_block",
+        |conn| Ok(vec![Entity::Day(blocks::get_block(conn, &id)?.date)]),
+        |conn| blocks::delete_block(conn, &id, version),
+        |_| Vec::new(),
+    )?)
+}
+
+#[tauri::command]
+pub fn log_session(
+    session: NewFocusSession,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<FocusSession, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "log_session",
+        |_| Ok(Vec::new()),
+        |conn| sessions::insert_session(conn, &session),
+        |logged| vec![Entity::Session(logged.id)],
+    )?)
 }
 
 #[tauri::command]
-pub fn purge_all_data(db: State<Database>) -> Result<(), CommandError> {
+pub fn get_sessions(
+    range: SessionRange,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Vec<FocusSession>, CommandError> {
+    ensure_ready(&recovery)?;
     let conn = db.0.lock().unwrap();
-    conn.execute_batch("
-        PRAGMA foreign_keys = ON;
-        DROP TABLE IF EXISTS day_blocks;
-        DROP TABLE IF EXISTS tasks;
-    ")?;
+    Ok(sessions::list_sessions(&conn, &range)?)
+}
+
+#[tauri::command]
+pub fn delete_session(
+    id: i64,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<(), CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    history::record(
+        &mut conn,
+        "delete_session",
+        |_| Ok(vec![Entity::Session(id)]),
+        |conn| sessions::delete_session(conn, id),
+        |_| Vec::new(),
+    )?;
     Ok(())
 }
 
+/// Reverts the most recent change; `None` when there's nothing to undo.
+#[tauri::command]
+pub fn undo_last(
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Option<HistoryChange>, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::undo_last(&mut conn)?)
+}
 
+/// Re-applies the most recently undone change; `None` when there's nothing to redo.
 #[tauri::command]
-pub fn get_settings(db: State<Database>) -> Result<std::collections::HashMap<String, String>, CommandError> {
+pub fn redo(
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Option<HistoryChange>, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::redo(&mut conn)?)
+}
+
+#[tauri::command]
+pub fn task_history(
+    id: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Vec<HistoryEvent>, CommandError> {
+    ensure_ready(&recovery)?;
+    let conn = db.0.lock().unwrap();
+    Ok(history::task_history(&conn, &id)?)
+}
+
+#[tauri::command]
+pub fn timer_start(
+    kind: String,
+    minutes: i32,
+    task_ids: Option<Vec<String>>,
+    timer: State<TimerService>,
+    recovery: State<Recovery>,
+) -> Result<TimerStatus, CommandError> {
+    ensure_ready(&recovery)?;
+    let now = Utc::now();
+    let mut timer = timer.0.lock().unwrap();
+    timer.start(now, &kind, minutes, task_ids.unwrap_or_default())?;
+    Ok(timer.status(now))
+}
+
+#[tauri::command]
+pub fn timer_pause(timer: State<TimerService>) -> Result<TimerStatus, CommandError> {
+    let now = Utc::now();
+    let mut timer = timer.0.lock().unwrap();
+    timer.pause(now)?;
+    Ok(timer.status(now))
+}
+
+#[tauri::command]
+pub fn timer_resume(timer: State<TimerService>) -> Result<TimerStatus, CommandError> {
+    let now = Utc::now();
+    let mut timer = timer.0.lock().unwrap();
+    timer.resume(now)?;
+    Ok(timer.status(now))
+}
+
+#[tauri::command]
+pub fn timer_stop(timer: State<TimerService>) -> Result<TimerStatus, CommandError> {
+    let now = Utc::now();
+    let mut timer = timer.0.lock().unwrap();
+    timer.stop();
+    Ok(timer.status(now))
+}
+
+#[tauri::command]
+pub fn timer_status(timer: State<TimerService>) -> Result<TimerStatus, CommandError> {
+    let timer = timer.0.lock().unwrap();
+    Ok(timer.status(Utc::now()))
+}
+
+/// Backs up the database, then deletes all user data. Settings and the audit log stay.
+#[tauri::command]
+pub fn purge_all_data(
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<PurgeSummary, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(purge::purge_all_data(&mut conn)?)
+}
+
+#[tauri::command]
+pub fn list_backups(db: State<Database>) -> Result<Vec<BackupInfo>, CommandError> {
+    let conn = db.0.lock().unwrap();
+    Ok(backup::list_backups(&conn)?)
+}
+
+#[tauri::command]
+pub fn create_backup(db: State<Database>) -> Result<BackupInfo, CommandError> {
+    let conn = db.0.lock().unwrap();
+    Ok(backup::create_backup(&conn, "manual")?)
+}
+
+/// Replaces all data with backup `id`. Returns the backup of the data it replaced. In
+/// recovery mode, leaves it once the restored data passes the startup checks.
+#[tauri::command]
+pub fn restore_backup(
+    id: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<BackupInfo, CommandError> {
+    let mut conn = db.0.lock().unwrap();
+    let previous = backup::restore_backup(&mut conn, &id)?;
+    if recovery::integrity_problems(&conn).is_empty()
+        && recovery::foreign_key_problems(&conn).is_empty()
+    {
+        *recovery.0.lock().unwrap() = None;
+    }
+    Ok(previous)
+}
+
+/// Moves the schema to migration `version`, or with `dry_run` only reports what that would
+/// do. A real run backs up first. Rolling back is for downgrading: the running build
+/// expects the latest schema, so quit and install the older one afterwards.
+#[tauri::command]
+pub fn migrate_database(
+    version: i64,
+    dry_run: Option<bool>,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<MigrationReport, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    if dry_run.unwrap_or(false) {
+        return Ok(migrations::dry_run(&conn, version)?);
+    }
+    backup::create_backup(&conn, "premigrate")?;
+    Ok(migrations::migrate_to(&mut conn, version)?)
+}
+
+/// Why the app is in recovery mode, or `None` when the database is healthy.
+#[tauri::command]
+pub fn get_recovery_status(recovery: State<Recovery>) -> Option<RecoveryReport> {
+    recovery.0.lock().unwrap().clone()
+}
+
+/// Replaces the damaged database with the newest backup that passes the startup checks,
+/// and leaves recovery mode.
+#[tauri::command]
+pub fn recover_from_backup(
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<BackupInfo, CommandError> {
+    let mut conn = db.0.lock().unwrap();
+    let restored = recovery::restore_latest_backup(&mut conn)?;
+    *recovery.0.lock().unwrap() = None;
+    Ok(restored)
+}
+
+#[tauri::command]
+pub fn export_salvage(db: State<Database>) -> Result<SalvageReport, CommandError> {
+    let conn = db.0.lock().unwrap();
+    Ok(recovery::export_salvage(&conn)?)
+}
+
+#[tauri::command]
+pub fn get_audit_log(
+    limit: Option<u32>,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Vec<AuditEntry>, CommandError> {
+    ensure_ready(&recovery)?;
+    let conn = db.0.lock().unwrap();
+    Ok(audit::list_entries(&conn, limit.unwrap_or(100))?)
+}
+
+#[tauri::command]
+pub fn get_availability(
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Availability, CommandError> {
+    ensure_ready(&recovery)?;
+    let conn = db.0.lock().unwrap();
+    Ok(availability::get_availability(&conn)?)
+}
+
+#[tauri::command]
+pub fn set_availability(
+    availability: Availability,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<Availability, CommandError> {
+    ensure_ready(&recovery)?;
+    let mut conn = db.0.lock().unwrap();
+    Ok(history::record(
+        &mut conn,
+        "set_availability",
+        |_| Ok(vec![Entity::Availability]),
+        |conn| availability::set_availability(conn, &availability),
+        |_| Vec::new(),
+    )?)
+}
+
+/// The availability on `date` (today if not given), described for an LLM prompt.
+fn availability_prompt(db: &Database, date: Option<&str>) -> Result<String, CommandError> {
+    let date = match date {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|_| format!("Invalid date: {}", date))?,
+        None => Local::now().date_naive(),
+    };
+    let conn = db.0.lock().unwrap();
+    let day = availability::working_day(&conn, date)?;
+    Ok(availability::describe(date, day.as_ref()))
+}
+
+#[tauri::command]
+pub fn get_settings(
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<std::collections::HashMap<String, String>, CommandError> {
+    ensure_ready(&recovery)?;
     let conn = db.0.lock().unwrap();
     let mut stmt = conn.prepare("SELECT key, value FROM settings")?;
     let setting_iter = stmt.query_map(params![], |row| {
@@ -200,7 +917,13 @@ pub fn get_settings(db: State<Database>) -> Result<std::collections::HashMap<Str
 }
 
 #[tauri::command]
-pub fn update_setting(key: String, value: String, db: State<Database>) -> Result<(), CommandError> {
+pub fn update_setting(
+    key: String,
+    value: String,
+    db: State<Database>,
+    recovery: State<Recovery>,
+) -> Result<(), CommandError> {
+    ensure_ready(&recovery)?;
     let conn = db.0.lock().unwrap();
     conn.execute(
         "INSERT OR REPLACE INTO settings (key, value) VALUES (?1, ?2)",
@@ -225,7 +948,9 @@ struct Message {
 pub async fn llm_enrich(
     task_title: String,
     db: State<'_, Database>,
+    recovery: State<'_, Recovery>,
 ) -> Result<EnrichResponse, CommandError> {
+    ensure_ready(&recovery)?;
     let api_key: Option<String> = {
         let conn = db.0.lock().unwrap();
         conn.query_row("SELECT value FROM settings WHERE key = 'apiKey'", [], |row| {
@@ -281,8 +1006,12 @@ struct PlanRequest {
 #[tauri::command]
 pub async fn llm_plan(
     tasks: Vec<Task>,
+    date: Option<String>,
     db: State<'_, Database>,
+    recovery: State<'_, Recovery>,
 ) -> Result<PlanWithAIResponse, CommandError> {
+    ensure_ready(&recovery)?;
+    let availability = availability_prompt(&db, date.as_deref())?;
     let api_key: Option<String> = {
         let conn = db.0.lock().unwrap();
         conn.query_row("SELECT value FROM settings WHERE key = 'apiKey'", [], |row| {
@@ -300,7 +1029,7 @@ pub async fn llm_plan(
         messages: vec![
             Message {
                 role: "system".to_string(),
-                content: "You are a helpful assistant. The user will provide a list of tasks. Your job is to suggest a plausible schedule by assigning a 'start_slot' and 'end_slot' for each task. Today is a normal workday. The user wants to start work at 9am. Slots are 30-minute intervals, so 9am is slot 18, 9:30am is 19, etc. The output should be a JSON object with a 'blocks' key, containing a list of objects, each with 'task_id', 'start_slot', and 'end_slot'.".to_string(),
+                content: format!("You are a helpful assistant. The user will provide a list of tasks. Your job is to suggest a plausible schedule by assigning a 'start_min' and 'length_min' for each task. {} Only place blocks inside working hours and never during unavailable periods. 'start_min' is minutes from midnight, so 9am is 540 and 9:15am is 555; 'length_min' is the block length in minutes, in multiples of 5. The output should be a JSON object with a 'blocks' key, containing a list of objects, each with 'task_id', 'start_min', and 'length_min'.", availability),
             },
             Message {
                 role: "user".to_string(),
@@ -340,8 +1069,12 @@ struct RefineRequest {
 pub async fn llm_refine(
     existing: String,
     instruction: String,
+    date: Option<String>,
     db: State<'_, Database>,
+    recovery: State<'_, Recovery>,
 ) -> Result<RefineResponse, CommandError> {
+    ensure_ready(&recovery)?;
+    let availability = availability_prompt(&db, date.as_deref())?;
     let api_key: Option<String> = {
         let conn = db.0.lock().unwrap();
         conn.query_row("SELECT value FROM settings WHERE key = 'apiKey'", [], |row| {
@@ -359,7 +1092,7 @@ pub async fn llm_refine(
         messages: vec![
             Message {
                 role: "system".to_string(),
-                content: "You are a helpful assistant. The user will provide an existing schedule and an instruction. You will refine the schedule based on the instruction. The output should be a JSON object with a 'blocks' key, containing the new list of blocks.".to_string(),
+                content: format!("You are a helpful assistant. The user will provide an existing schedule and an instruction. You will refine the schedule based on the instruction. {} Keep blocks inside working hours and out of unavailable periods. The output should be a JSON object with a 'blocks' key, containing the new list of blocks.", availability),
             },
             Message {
                 role: "user".to_string(),
@@ -0,0 +1,36 @@
+//! Append-only log of destructive operations.
+//!
+//! Entries are written in the same transaction as the operation they describe, and nothing
+//! deletes from `audit_log`, so the record survives the data it's about.
+
+use super::{tasks, DbError};
+use crate::models::AuditEntry;
+use rusqlite::{params, Connection};
+use serde::Serialize;
+
+pub fn record<T: Serialize>(conn: &Connection, action: &str, detail: &T) -> Result<(), DbError> {
+    let detail = serde_json::to_string(detail).map_err(|e| DbError::Invalid(e.to_string()))?;
+    conn.execute(
+        "INSERT INTO audit_log (at, action, detail) VALUES (?1, ?2, ?3)",
+        params![tasks::now_iso(), action, detail],
+    )?;
+    Ok(())
+}
+
+/// Newest entries first.
+pub fn list_entries(conn: &Connection, limit: u32) -> Result<Vec<AuditEntry>, DbError> {
+    let mut stmt =
+        conn.prepare("SELECT id, at, action, detail FROM audit_log ORDER BY id DESC LIMIT ?1")?;
+    let entries = stmt
+        .query_map(params![limit], |row| {
+            let detail: Option<String> = row.get(3)?;
+            Ok(AuditEntry {
+                id: row.get(0)?,
+                at: row.get(1)?,
+                action: row.get(2)?,
+                detail: detail.and_then(|d| serde_json::from_str(&d).ok()),
+            })
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(entries)
+}
@@ -0,0 +1,357 @@
+use super::DbError;
+use crate::models::{Availability, BlockedPeriod, DayHours};
+use chrono::{Datelike, NaiveDate};
+use rusqlite::{params, Connection};
+use uuid::Uuid;
+
+const DAY_MIN: i32 = 24 * 60;
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+/// The plannable part of one date: its working hours, and the lunch break and blocked
+/// periods inside them as (start, end) minutes, sorted by start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkingDay {
+    pub start_min: i32,
+    pub end_min: i32,
+    pub unavailable: Vec<(i32, i32)>,
+}
+
+impl Default for WorkingDay {
+    fn default() -> Self {
+        WorkingDay {
+            start_min: 9 * 60,
+            end_min: 17 * 60,
+            unavailable: Vec::new(),
+        }
+    }
+}
+
+fn format_time(min: i32) -> String {
+    format!("{:02}:{:02}", min / 60, min % 60)
+}
+
+/// Plain-English summary of `date`'s availability, for LLM prompts.
+pub fn describe(date: NaiveDate, day: Option<&WorkingDay>) -> String {
+    let weekday = WEEKDAYS[date.weekday().num_days_from_monday() as usize];
+    let Some(day) = day else {
+        return format!("{} ({}) is not a working day.", date, weekday);
+    };
+    let mut text = format!(
+        "On {} ({}) the user works from {} to {} (start_min {} to {}).",
+        date,
+        weekday,
+        format_time(day.start_min),
+        format_time(day.end_min),
+        day.start_min,
+        day.end_min
+    );
+    if !day.unavailable.is_empty() {
+        let periods: Vec<String> = day
+            .unavailable
+            .iter()
+            .map(|&(start, end)| format!("{}-{}", format_time(start), format_time(end)))
+            .collect();
+        text.push_str(&format!(" They are unavailable {}.", periods.join(", ")));
+    }
+    text
+}
+
+fn validate_range(start_min: i32, end_min: i32, what: &str) -> Result<(), DbError> {
+    if start_min < 0 || end_min > DAY_MIN || start_min >= end_min {
+        return Err(DbError::Invalid(format!(
+            "{} must be within the day and end after it starts, got {}..{}",
+            what, start_min, end_min
+        )));
+    }
+    Ok(())
+}
+
+fn validate(availability: &Availability) -> Result<(), DbError> {
+    let mut weekdays: Vec<u32> = availability.days.iter().map(|d| d.weekday).collect();
+    weekdays.sort_unstable();
+    if weekdays != (0..7).collect::<Vec<u32>>() {
+        return Err(DbError::Invalid(
+            "Availability needs exactly one entry for each weekday".to_string(),
+        ));
+    }
+    for day in &availability.days {
+        let name = WEEKDAYS[day.weekday as usize];
+        validate_range(day.start_min, day.end_min, &format!("{}'s hours", name))?;
+        if day.lunch_length_min < 0 {
+            return Err(DbError::Invalid(format!(
+                "{}'s lunch break can't be negative",
+                name
+            )));
+        }
+        if let Some(lunch_start) = day.lunch_start_min {
+            let lunch_end = lunch_start + day.lunch_length_min;
+            if lunch_start < day.start_min || lunch_end > day.end_min {
+                return Err(DbError::Invalid(format!(
+                    "{}'s lunch break must be within working hours",
+                    name
+                )));
+            }
+        }
+    }
+    for period in &availability.blocked {
+        match (period.weekday, &period.date) {
+            (Some(weekday), None) if weekday < 7 => {}
+            (None, Some(date)) => {
+                NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                    .map_err(|_| DbError::Invalid(format!("Invalid date: {}", date)))?;
+            }
+            _ => {
+                return Err(DbError::Invalid(
+                    "A blocked period needs either a weekday or a date".to_string(),
+                ))
+            }
+        }
+        validate_range(period.start_min, period.end_min, "A blocked period")?;
+    }
+    Ok(())
+}
+
+pub fn get_availability(conn: &Connection) -> Result<Availability, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT weekday, working, start_min, end_min, lunch_start_min, lunch_length_min
+         FROM availability_days ORDER BY weekday",
+    )?;
+    let days = stmt
+        .query_map([], |row| {
+            Ok(DayHours {
+                weekday: row.get(0)?,
+                working: row.get(1)?,
+                start_min: row.get(2)?,
+                end_min: row.get(3)?,
+                lunch_start_min: row.get(4)?,
+                lunch_length_min: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stmt = conn.prepare(
+        "SELECT id, weekday, date, start_min, end_min, label FROM blocked_periods
+         ORDER BY date IS NULL, date, weekday, start_min",
+    )?;
+    let blocked = stmt
+        .query_map([], |row| {
+            Ok(BlockedPeriod {
+                id: row.get(0)?,
+                weekday: row.get(1)?,
+                date: row.get(2)?,
+                start_min: row.get(3)?,
+                end_min: row.get(4)?,
+                label: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Availability { days, blocked })
+}
+
+/// Writes `availability` over what's stored, unchecked. Periods without an id get one.
+pub fn write_availability(conn: &Connection, availability: &Availability) -> Result<(), DbError> {
+    conn.execute("DELETE FROM availability_days", [])?;
+    for day in &availability.days {
+        conn.execute(
+            "INSERT INTO availability_days
+                (weekday, working, start_min, end_min, lunch_start_min, lunch_length_min)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                day.weekday,
+                day.working,
+                day.start_min,
+                day.end_min,
+                day.lunch_start_min,
+                day.lunch_length_min
+            ],
+        )?;
+    }
+    conn.execute("DELETE FROM blocked_periods", [])?;
+    for period in &availability.blocked {
+        let id = if period.id.is_empty() {
+            Uuid::new_v4().to_string()
+        } else {
+            period.id.clone()
+        };
+        conn.execute(
+            "INSERT INTO blocked_periods (id, weekday, date, start_min, end_min, label)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                id,
+                period.weekday,
+                period.date,
+                period.start_min,
+                period.end_min,
+                period.label
+            ],
+        )?;
+    }
+    Ok(())
+}
+
+/// Replaces the weekly hours and all blocked periods. Periods without an id get one.
+pub fn set_availability(
+    conn: &mut Connection,
+    availability: &Availability,
+) -> Result<Availability, DbError> {
+    validate(availability)?;
+    let sp = conn.savepoint()?;
+    write_availability(&sp, availability)?;
+    sp.commit()?;
+    get_availability(conn)
+}
+
+/// Puts back the weekly hours a new database starts with (weekdays, 9 to 5) and removes
+/// every blocked period. Returns how many blocked periods were removed.
+pub fn reset_availability(conn: &Connection) -> Result<usize, DbError> {
+    let default = WorkingDay::default();
+    conn.execute(
+        "UPDATE availability_days
+         SET working = weekday < 5, start_min = ?1, end_min = ?2,
+             lunch_start_min = NULL, lunch_length_min = 0",
+        params![default.start_min, default.end_min],
+    )?;
+    Ok(conn.execute("DELETE FROM blocked_periods", [])?)
+}
+
+/// Hours and unavailable time on `date`, or `None` if it isn't a working day.
+pub fn working_day(conn: &Connection, date: NaiveDate) -> Result<Option<WorkingDay>, DbError> {
+    let weekday = date.weekday().num_days_from_monday();
+    let availability = get_availability(conn)?;
+    let Some(hours) = availability
+        .days
+        .iter()
+        .find(|d| d.weekday == weekday && d.working)
+    else {
+        return Ok(None);
+    };
+
+    let date = date.format("%Y-%m-%d").to_string();
+    let mut unavailable: Vec<(i32, i32)> = availability
+        .blocked
+        .iter()
+        .filter(|p| p.weekday == Some(weekday) || p.date.as_deref() == Some(date.as_str()))
+        .map(|p| (p.start_min, p.end_min))
+        .collect();
+    if let Some(lunch_start) = hours.lunch_start_min {
+        if hours.lunch_length_min > 0 {
+            unavailable.push((lunch_start, lunch_start + hours.lunch_length_min));
+        }
+    }
+    unavailable.sort_unstable();
+    Ok(Some(WorkingDay {
+        start_min: hours.start_min,
+        end_min: hours.end_min,
+        unavailable,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations::run_migrations;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_defaults_to_weekdays_nine_to_five() {
+        let conn = setup();
+        // 2024-05-01 is a Wednesday, 2024-05-04 a Saturday.
+        assert_eq!(
+            working_day(&conn, date("2024-05-01")).unwrap(),
+            Some(WorkingDay::default())
+        );
+        assert_eq!(working_day(&conn, date("2024-05-04")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_working_day_includes_lunch_and_blocked_periods() {
+        let mut conn = setup();
+        let mut availability = get_availability(&conn).unwrap();
+        availability.days[2].start_min = 8 * 60;
+        availability.days[2].lunch_start_min = Some(12 * 60);
+        availability.days[2].lunch_length_min = 45;
+        availability.blocked = vec![
+            BlockedPeriod {
+                id: String::new(),
+                weekday: Some(2),
+                date: None,
+                start_min: 9 * 60,
+                end_min: 9 * 60 + 15,
+                label: Some("Standup".to_string()),
+            },
+            BlockedPeriod {
+                id: "dentist".to_string(),
+                weekday: None,
+                date: Some("2024-05-01".to_string()),
+                start_min: 15 * 60,
+                end_min: 16 * 60,
+                label: None,
+            },
+        ];
+        let saved = set_availability(&mut conn, &availability).unwrap();
+        assert!(saved.blocked.iter().all(|p| !p.id.is_empty()));
+
+        let day = working_day(&conn, date("2024-05-01")).unwrap().unwrap();
+        assert_eq!(
+            day,
+            WorkingDay {
+                start_min: 480,
+                end_min: 1020,
+                unavailable: vec![(540, 555), (720, 765), (900, 960)],
+            }
+        );
+        // The one-off period doesn't carry over to the next Wednesday.
+        let next = working_day(&conn, date("2024-05-08")).unwrap().unwrap();
+        assert_eq!(next.unavailable, vec![(540, 555), (720, 765)]);
+        assert_eq!(
+            describe(date("2024-05-01"), Some(&day)),
+            "On 2024-05-01 (Wednesday) the user works from 08:00 to 17:00 (start_min 480 to \
+             1020). They are unavailable 09:00-09:15, 12:00-12:45, 15:00-16:00."
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_availability() {
+        let mut conn = setup();
+        let valid = get_availability(&conn).unwrap();
+
+        let mut missing_day = valid.clone();
+        missing_day.days.pop();
+        let mut backwards = valid.clone();
+        backwards.days[0].end_min = backwards.days[0].start_min;
+        let mut lunch_outside = valid.clone();
+        lunch_outside.days[0].lunch_start_min = Some(16 * 60 + 30);
+        lunch_outside.days[0].lunch_length_min = 60;
+        let mut ambiguous = valid.clone();
+        ambiguous.blocked.push(BlockedPeriod {
+            id: String::new(),
+            weekday: Some(1),
+            date: Some("2024-05-01".to_string()),
+            start_min: 600,
+            end_min: 660,
+            label: None,
+        });
+
+        for invalid in [missing_day, backwards, lunch_outside, ambiguous] {
+            assert!(set_availability(&mut conn, &invalid).is_err());
+        }
+        assert_eq!(get_availability(&conn).unwrap(), valid);
+    }
+}
@@ -0,0 +1,488 @@
+//! Backups of the database, taken with SQLite's online backup API so pages still in the
+//! WAL are included.
+//!
+//! Backups sit next to the database as `cadence.<kind>.<timestamp>.db`; the file name is the
+//! backup's id. Launch backups (kind `backup`, the name they've always had) are rotated at
+//! startup: only the newest `backupRetentionCount` of them are kept. Backups taken by hand
+//! or before a purge or restore are kept until removed by hand.
+
+use super::{audit, migrations, DbError};
+use crate::models::BackupInfo;
+use chrono::{Local, NaiveDateTime, TimeZone};
+use rusqlite::{Connection, DatabaseName, OpenFlags, OptionalExtension};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const LAUNCH_BACKUP: &str = "backup";
+pub const BACKUP_RETENTION_SETTING: &str = "backupRetentionCount";
+const DEFAULT_BACKUP_RETENTION: usize = 10;
+
+/// Tables compared between the live database and a fresh copy.
+const VERIFIED_TABLES: &[&str] = &["tasks", "day_blocks", "projects", "focus_sessions"];
+
+/// File the open database is stored in; in-memory databases have none.
+pub fn database_path(conn: &Connection) -> Result<PathBuf, DbError> {
+    match conn.path() {
+        Some(path) if !path.is_empty() => Ok(PathBuf::from(path)),
+        _ => Err(DbError::Invalid(
+            "The database isn't stored in a file, so it can't be backed up".to_string(),
+        )),
+    }
+}
+
+fn stem(db_path: &Path) -> String {
+    db_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Splits `cadence.<kind>.<timestamp>.db` into its kind and time. Launch backups from
+/// before rotation have minute timestamps; newer ones go down to the millisecond.
+fn parse_name(db_stem: &str, name: &str) -> Option<(String, NaiveDateTime)> {
+    let rest = name
+        .strip_prefix(db_stem)?
+        .strip_prefix('.')?
+        .strip_suffix(".db")?;
+    let (kind, timestamp) = rest.split_once('.')?;
+    if kind.is_empty() || !timestamp.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let created = match timestamp.len() {
+        12 => NaiveDateTime::parse_from_str(timestamp, "%Y%m%d%H%M").ok()?,
+        17 => NaiveDateTime::parse_from_str(
+            &format!("{}.{}", &timestamp[..14], &timestamp[14..]),
+            "%Y%m%d%H%M%S%.3f",
+        )
+        .ok()?,
+        _ => return None,
+    };
+    Some((kind.to_string(), created))
+}
+
+/// Row counts of the verified tables, skipping any an older schema doesn't have yet.
+fn row_counts(conn: &Connection) -> rusqlite::Result<Vec<(&'static str, i64)>> {
+    let mut counts = Vec::new();
+    for table in VERIFIED_TABLES {
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+            [table],
+            |r| r.get(0),
+        )?;
+        if exists {
+            let count =
+                conn.query_row(&format!("SELECT count(*) FROM {}", table), [], |r| r.get(0))?;
+            counts.push((*table, count));
+        }
+    }
+    Ok(counts)
+}
+
+/// `path` with `suffix` added to the file name, e.g. its `-wal` file.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Removes a database file along with any journal, WAL or shared-memory file beside it.
+fn remove_database_files(path: &Path) -> std::io::Result<()> {
+    for suffix in ["-wal", "-shm", "-journal"] {
+        match fs::remove_file(with_suffix(path, suffix)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    fs::remove_file(path)
+}
+
+/// A throwaway copy of a database file, deleted along with its sidecar files when dropped.
+/// Checks that have to open a file read-write run on one of these so the original is
+/// left exactly as it was.
+pub(crate) struct ScratchCopy(PathBuf);
+
+impl ScratchCopy {
+    pub fn new(path: &Path) -> Result<Self, DbError> {
+        let scratch = with_suffix(path, ".scratch");
+        fs::copy(path, &scratch).map_err(|e| DbError::Invalid(e.to_string()))?;
+        Ok(ScratchCopy(scratch))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for ScratchCopy {
+    fn drop(&mut self) {
+        let _ = remove_database_files(&self.0);
+    }
+}
+
+/// Fails unless `db` passes `PRAGMA integrity_check`. `path` is only for the message.
+fn check_open_integrity(db: &Connection, path: &Path) -> Result<(), DbError> {
+    let integrity: String = db.query_row("PRAGMA integrity_check", [], |r| r.get(0))?;
+    if integrity != "ok" {
+        return Err(DbError::Invalid(format!(
+            "{} failed its integrity check: {}",
+            path.display(),
+            integrity
+        )));
+    }
+    Ok(())
+}
+
+/// Fails unless the database at `path` passes `PRAGMA integrity_check`. The check runs on a
+/// scratch copy, since checking the FTS5 index writes to the database.
+pub fn check_integrity(path: &Path) -> Result<(), DbError> {
+    let scratch = ScratchCopy::new(path)?;
+    let db = Connection::open_with_flags(scratch.path(), OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+    check_open_integrity(&db, path)
+}
+
+/// Copies the open database to `path`, then checks the copy's integrity and that it holds
+/// the same number of rows. The copy is written under a temporary name and only moved into
+/// place once it checks out.
+pub fn write_verified_backup(conn: &Connection, path: &Path) -> Result<(), DbError> {
+    if path.exists() {
+        return Err(DbError::Invalid(format!(
+            "Backup file already exists: {}",
+            path.display()
+        )));
+    }
+    let partial = path.with_extension("db.partial");
+    let result = (|| {
+        conn.backup(DatabaseName::Main, &partial, None)?;
+        // The copy takes the live database's WAL mode; a backup is only ever read whole, so
+        // switch it back to a rollback journal and nothing leaves files beside it.
+        let copy = Connection::open(&partial)?;
+        copy.query_row("PRAGMA journal_mode = DELETE", [], |_| Ok(()))?;
+        drop(copy);
+
+        let scratch = ScratchCopy::new(&partial)?;
+        let copy = Connection::open_with_flags(scratch.path(), OpenFlags::SQLITE_OPEN_READ_WRITE)?;
+        check_open_integrity(&copy, &partial)?;
+        if row_counts(&copy)? != row_counts(conn)? {
+            return Err(DbError::Invalid(
+                "Backup doesn't hold the same rows as the database".to_string(),
+            ));
+        }
+        fs::rename(&partial, path).map_err(|e| DbError::Invalid(e.to_string()))
+    })();
+    if result.is_err() {
+        let _ = remove_database_files(&partial);
+    }
+    result
+}
+
+fn info(db_stem: &str, path: &Path) -> Option<BackupInfo> {
+    let name = path.file_name()?.to_str()?;
+    let (kind, created) = parse_name(db_stem, name)?;
+    let created = Local.from_local_datetime(&created).earliest()?;
+    Some(BackupInfo {
+        id: name.to_string(),
+        kind,
+        created_at: created.to_rfc3339(),
+        size_bytes: fs::metadata(path).ok()?.len(),
+    })
+}
+
+/// Where a new backup of kind `kind` of the database at `db_path` goes.
+pub fn new_backup_path(db_path: &Path, kind: &str) -> PathBuf {
+    let timestamp = Local::now().format("%Y%m%d%H%M%S%3f");
+    db_path.with_extension(format!("{}.{}.db", kind, timestamp))
+}
+
+/// Takes a verified backup of kind `kind`.
+pub fn create_backup(conn: &Connection, kind: &str) -> Result<BackupInfo, DbError> {
+    if kind.is_empty() || !kind.chars().all(|c| c.is_ascii_lowercase()) {
+        return Err(DbError::Invalid(format!("Invalid backup kind: {}", kind)));
+    }
+    let db_path = database_path(conn)?;
+    let path = new_backup_path(&db_path, kind);
+    write_verified_backup(conn, &path)?;
+    info(&stem(&db_path), &path)
+        .ok_or_else(|| DbError::Invalid(format!("Backup went missing: {}", path.display())))
+}
+
+/// All backups of the open database, newest first.
+pub fn list_backups(conn: &Connection) -> Result<Vec<BackupInfo>, DbError> {
+    let db_path = database_path(conn)?;
+    let dir = db_path.parent().unwrap_or(Path::new("."));
+    let db_stem = stem(&db_path);
+    let entries = fs::read_dir(dir).map_err(|e| DbError::Invalid(e.to_string()))?;
+    let mut backups: Vec<BackupInfo> = entries
+        .filter_map(|entry| info(&db_stem, &entry.ok()?.path()))
+        .collect();
+    backups.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+    Ok(backups)
+}
+
+fn retention(conn: &Connection) -> Result<usize, DbError> {
+    let setting: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            [BACKUP_RETENTION_SETTING],
+            |r| r.get(0),
+        )
+        .optional()?;
+    match setting {
+        None => Ok(DEFAULT_BACKUP_RETENTION),
+        Some(value) => value.trim().parse().map_err(|_| {
+            DbError::Invalid(format!(
+                "{} must be a whole number, got {:?}",
+                BACKUP_RETENTION_SETTING, value
+            ))
+        }),
+    }
+}
+
+/// Deletes launch backups beyond the newest `backupRetentionCount` (10 unless set).
+/// Returns how many were removed.
+pub fn prune_backups(conn: &Connection) -> Result<usize, DbError> {
+    let keep = retention(conn)?;
+    let dir = database_path(conn)?
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    let mut removed = 0;
+    for backup in list_backups(conn)?
+        .into_iter()
+        .filter(|b| b.kind == LAUNCH_BACKUP)
+        .skip(keep)
+    {
+        remove_database_files(&dir.join(&backup.id))
+            .map_err(|e| DbError::Invalid(e.to_string()))?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+fn restore_from(conn: &mut Connection, path: &Path) -> rusqlite::Result<()> {
+    conn.restore(
+        DatabaseName::Main,
+        path,
+        None::<fn(rusqlite::backup::Progress)>,
+    )
+}
+
+/// Replaces the open database's contents with backup `id`.
+///
+/// The backup is checked and migrated to the current schema on a scratch copy first, so a
+/// damaged backup or one this build can't migrate (say, written by a newer version) is
+/// refused before anything changes. The current data is then backed up (kind `prerestore`)
+/// and replaced, and audit entries written since the backup was taken are carried over. If
+/// anything fails after the data is replaced, the pre-restore backup is put back.
+/// Returns the pre-restore backup.
+pub fn restore_backup(conn: &mut Connection, id: &str) -> Result<BackupInfo, DbError> {
+    let backup = list_backups(conn)?
+        .into_iter()
+        .find(|b| b.id == id)
+        .ok_or_else(|| DbError::Invalid(format!("Backup not found: {}", id)))?;
+    let db_path = database_path(conn)?;
+    let source = db_path.with_file_name(&backup.id);
+    let staged = ScratchCopy::new(&source)?;
+    {
+        let mut db = Connection::open(staged.path())?;
+        check_open_integrity(&db, &source)?;
+        migrations::run_migrations(&mut db)?;
+    }
+
+    let safety = create_backup(conn, "prerestore")?;
+    let safety_path = db_path.with_file_name(&safety.id);
+    let result = (|| {
+        restore_from(conn, staged.path())?;
+        migrations::run_migrations(conn)?;
+
+        let previous = safety_path.to_str().ok_or_else(|| {
+            DbError::Invalid(format!("Unusable path: {}", safety_path.display()))
+        })?;
+        conn.execute("ATTACH DATABASE ?1 AS previous", [previous])?;
+        // The log is append-only, so the restored one is a prefix of the one we just replaced.
+        let carried = conn.execute(
+            "INSERT OR IGNORE INTO audit_log (id, at, action, detail)
+             SELECT id, at, action, detail FROM previous.audit_log",
+            [],
+        );
+        conn.execute("DETACH DATABASE previous", [])?;
+        carried?;
+        audit::record(
+            conn,
+            "restore_backup",
+            &serde_json::json!({ "restored": backup.id, "previous": safety.id }),
+        )
+    })();
+    if let Err(e) = result {
+        let _ = conn.execute("DETACH DATABASE previous", []);
+        return match restore_from(conn, &safety_path) {
+            Ok(()) => Err(e),
+            Err(undo) => Err(DbError::Invalid(format!(
+                "{}; putting the previous data back from {} also failed: {}",
+                e, safety.id, undo
+            ))),
+        };
+    }
+    Ok(safety)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_util::{add_project, temp_db};
+    use std::time::Duration;
+
+    fn project_names(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare("SELECT name FROM projects ORDER BY name")
+            .unwrap();
+        stmt.query_map([], |r| r.get(0))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_name() {
+        let (kind, at) = parse_name("cadence", "cadence.backup.202405010930.db").unwrap();
+        assert_eq!(kind, "backup");
+        assert_eq!(at.format("%H:%M").to_string(), "09:30");
+        let (kind, at) = parse_name("cadence", "cadence.manual.20240501093015250.db").unwrap();
+        assert_eq!(kind, "manual");
+        assert_eq!(at.format("%S%.3f").to_string(), "15.250");
+        assert!(parse_name("cadence", "cadence.db").is_none());
+        assert!(parse_name("cadence", "cadence.manual.2024.db").is_none());
+        assert!(parse_name("cadence", "cadence.db-wal").is_none());
+    }
+
+    #[test]
+    fn test_launch_backups_rotate() {
+        let (dir, conn) = temp_db("backup");
+        conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, '2')",
+            [BACKUP_RETENTION_SETTING],
+        )
+        .unwrap();
+        // A legacy launch backup, the oldest of all.
+        fs::write(dir.join("cadence.backup.202001010000.db"), b"").unwrap();
+        let manual = create_backup(&conn, "manual").unwrap();
+        for _ in 0..3 {
+            std::thread::sleep(Duration::from_millis(2));
+            create_backup(&conn, LAUNCH_BACKUP).unwrap();
+        }
+        assert_eq!(prune_backups(&conn).unwrap(), 2);
+
+        let backups = list_backups(&conn).unwrap();
+        let launch: Vec<_> = backups.iter().filter(|b| b.kind == LAUNCH_BACKUP).collect();
+        assert_eq!(launch.len(), 2);
+        assert!(backups.iter().any(|b| b.id == manual.id));
+        assert!(!dir.join("cadence.backup.202001010000.db").exists());
+        assert!(backups
+            .windows(2)
+            .all(|w| w[0].created_at >= w[1].created_at));
+
+        drop(conn);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_backup() {
+        let (dir, mut conn) = temp_db("backup");
+        add_project(&conn, "Home");
+        let backup = create_backup(&conn, "manual").unwrap();
+        add_project(&conn, "Work");
+        audit::record(&conn, "purge_all_data", &"later").unwrap();
+
+        let safety = restore_backup(&mut conn, &backup.id).unwrap();
+        assert_eq!(safety.kind, "prerestore");
+        assert_eq!(project_names(&conn), vec!["Home"]);
+        let log = audit::list_entries(&conn, 10).unwrap();
+        let actions: Vec<_> = log.iter().map(|e| e.action.as_str()).collect();
+        assert_eq!(actions, vec!["restore_backup", "purge_all_data"]);
+
+        // And back again.
+        restore_backup(&mut conn, &safety.id).unwrap();
+        assert_eq!(project_names(&conn), vec!["Home", "Work"]);
+
+        drop(conn);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_refuses_backup_from_newer_version() {
+        let (dir, mut conn) = temp_db("backup");
+        add_project(&conn, "Home");
+        let backup = create_backup(&conn, "manual").unwrap();
+        let newer = Connection::open(dir.join(&backup.id)).unwrap();
+        newer
+            .execute(
+                "INSERT INTO schema_migrations (id, name, applied_at) VALUES (999, 'future', 0)",
+                [],
+            )
+            .unwrap();
+        drop(newer);
+        add_project(&conn, "Work");
+
+        assert!(restore_backup(&mut conn, &backup.id).is_err());
+        assert_eq!(project_names(&conn), vec!["Home", "Work"]);
+        assert!(list_backups(&conn)
+            .unwrap()
+            .iter()
+            .all(|b| b.kind != "prerestore"));
+
+        drop(conn);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_backups_leave_no_files_behind() {
+        let (dir, conn) = temp_db("backup");
+        conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, '0')",
+            [BACKUP_RETENTION_SETTING],
+        )
+        .unwrap();
+        let backup = create_backup(&conn, LAUNCH_BACKUP).unwrap();
+        check_integrity(&dir.join(&backup.id)).unwrap();
+        let mut names: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| !name.starts_with("cadence.db"))
+            .collect();
+        assert_eq!(names, vec![backup.id.clone()]);
+
+        // Rotation takes any sidecar files with the backup.
+        fs::write(dir.join(format!("{}-wal", backup.id)), b"").unwrap();
+        assert_eq!(prune_backups(&conn).unwrap(), 1);
+        names = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| !name.starts_with("cadence.db"))
+            .collect();
+        assert!(names.is_empty(), "left behind: {:?}", names);
+
+        drop(conn);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_restore_rejects_bad_backup() {
+        let (dir, mut conn) = temp_db("backup");
+        add_project(&conn, "Home");
+        fs::write(
+            dir.join("cadence.manual.20240501093015250.db"),
+            b"not a database",
+        )
+        .unwrap();
+
+        assert!(restore_backup(&mut conn, "cadence.manual.20240501093015250.db").is_err());
+        assert!(restore_backup(&mut conn, "../cadence.db").is_err());
+        assert_eq!(project_names(&conn), vec!["Home"]);
+        assert!(list_backups(&conn)
+            .unwrap()
+            .iter()
+            .all(|b| b.kind != "prerestore"));
+
+        drop(conn);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
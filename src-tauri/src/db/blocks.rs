@@ -0,0 +1,971 @@
+use super::DbError;
+use crate::models::{BlockConflict, DayBlock, DayPlan, WorkItem};
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+const DAY_MIN: i32 = 24 * 60;
+
+/// Longest range `blocks_in_range` returns, in days.
+const MAX_RANGE_DAYS: i64 = 366;
+
+fn block_from_row(row: &Row) -> rusqlite::Result<DayBlock> {
+    Ok(DayBlock {
+        id: row.get(0)?,
+        task_id: row.get(1)?,
+        date: row.get(2)?,
+        start_min: row.get(3)?,
+        length_min: row.get(4)?,
+        kind: row.get(5)?,
+        items: Vec::new(),
+        version: row.get(6)?,
+    })
+}
+
+/// Blocks dated `start` to `end` inclusive, by date and start time.
+fn load_blocks(
+    conn: &Connection,
+    start: &str,
+    end: &str,
+    include_trashed: bool,
+) -> rusqlite::Result<Vec<DayBlock>> {
+    let mut stmt = conn.prepare(
+        "SELECT b.block_id, b.task_id, b.est_minutes
+         FROM block_items b
+         JOIN day_blocks d ON d.id = b.block_id
+         JOIN tasks t ON t.id = b.task_id
+         WHERE d.date BETWEEN ?1 AND ?2 AND (?3 OR t.deleted_at IS NULL)
+         ORDER BY b.block_id, b.position",
+    )?;
+    let item_iter = stmt.query_map(params![start, end, include_trashed], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            WorkItem {
+                task_id: row.get(1)?,
+                est_minutes: row.get(2)?,
+            },
+        ))
+    })?;
+
+    let mut items: HashMap<String, Vec<WorkItem>> = HashMap::new();
+    for item in item_iter {
+        let (block_id, item) = item?;
+        items.entry(block_id).or_default().push(item);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT id, task_id, date, start_min, length_min, kind, version FROM day_blocks
+         WHERE date BETWEEN ?1 AND ?2
+           AND (?3 OR NOT EXISTS (SELECT 1 FROM tasks t WHERE t.id = day_blocks.task_id AND t.deleted_at IS NOT NULL))
+         ORDER BY date, start_min",
+    )?;
+    let block_iter = stmt.query_map(params![start, end, include_trashed], block_from_row)?;
+
+    let mut blocks = Vec::new();
+    for block in block_iter {
+        let mut block = block?;
+        block.items = items.remove(&block.id).unwrap_or_default();
+        blocks.push(block);
+    }
+    Ok(blocks)
+}
+
+/// Blocks for one day, each with its work items in order. Blocks and items for trashed
+/// tasks are left out until the task is restored.
+pub fn list_blocks_for_date(conn: &Connection, date: &str) -> rusqlite::Result<Vec<DayBlock>> {
+    load_blocks(conn, date, date, false)
+}
+
+/// Every day from `start` to `end` inclusive (YYYY-MM-DD), empty ones included, with its
+/// visible blocks and their total length.
+pub fn blocks_in_range(conn: &Connection, start: &str, end: &str) -> Result<Vec<DayPlan>, DbError> {
+    let parse = |date: &str| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .map_err(|_| DbError::Invalid(format!("Invalid date: {}", date)))
+    };
+    let (first, last) = (parse(start)?, parse(end)?);
+    let days = (last - first).num_days() + 1;
+    if days < 1 {
+        return Err(DbError::Invalid(format!(
+            "Range ends before it starts: {} to {}",
+            start, end
+        )));
+    }
+    if days > MAX_RANGE_DAYS {
+        return Err(DbError::Invalid(format!(
+            "Range is {} days long; at most {} can be loaded at once",
+            days, MAX_RANGE_DAYS
+        )));
+    }
+
+    let mut by_date: HashMap<String, Vec<DayBlock>> = HashMap::new();
+    for block in load_blocks(conn, start, end, false)? {
+        by_date.entry(block.date.clone()).or_default().push(block);
+    }
+    Ok(first
+        .iter_days()
+        .take(days as usize)
+        .map(|day| {
+            let date = day.format("%Y-%m-%d").to_string();
+            let blocks = by_date.remove(&date).unwrap_or_default();
+            let planned_min = blocks.iter().map(|b| b.length_min).sum();
+            DayPlan {
+                date,
+                blocks,
+                planned_min,
+            }
+        })
+        .collect())
+}
+
+/// Every block stored for a day, including those hidden because their task is trashed.
+pub fn all_blocks_for_date(conn: &Connection, date: &str) -> rusqlite::Result<Vec<DayBlock>> {
+    load_blocks(conn, date, date, true)
+}
+
+/// Current version of each block stored for `date`.
+fn versions_for_date(conn: &Connection, date: &str) -> rusqlite::Result<HashMap<String, i64>> {
+    let mut stmt = conn.prepare("SELECT id, version FROM day_blocks WHERE date = ?1")?;
+    let versions = stmt
+        .query_map(params![date], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect();
+    versions
+}
+
+/// Version for a block being rewritten: past both the stored one and the caller's, so no
+/// edit based on an older copy can still apply.
+fn next_version(versions: &HashMap<String, i64>, block: &DayBlock) -> i64 {
+    versions.get(&block.id).copied().unwrap_or(0).max(block.version) + 1
+}
+
+/// Puts a day back exactly as `blocks` describes it, hidden blocks included. Used to
+/// restore history, so blocks and items for tasks that no longer exist are skipped.
+pub fn overwrite_blocks_for_date(
+    conn: &Connection,
+    date: &str,
+    blocks: &[DayBlock],
+) -> rusqlite::Result<()> {
+    let versions = versions_for_date(conn, date)?;
+    conn.execute("DELETE FROM day_blocks WHERE date = ?1", params![date])?;
+    for block in blocks {
+        conn.execute(
+            "INSERT INTO day_blocks (id, task_id, date, start_min, length_min, kind, version)
+             SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7
+             WHERE ?2 IS NULL OR EXISTS (SELECT 1 FROM tasks WHERE id = ?2)",
+            params![
+                block.id,
+                block.task_id,
+                block.date,
+                block.start_min,
+                block.length_min,
+                block.kind,
+                next_version(&versions, block)
+            ],
+        )?;
+        for (position, item) in block.items.iter().enumerate() {
+            conn.execute(
+                "INSERT INTO block_items (block_id, position, task_id, est_minutes)
+                 SELECT ?1, ?2, ?3, ?4
+                 WHERE EXISTS (SELECT 1 FROM day_blocks WHERE id = ?1)
+                   AND EXISTS (SELECT 1 FROM tasks WHERE id = ?3)",
+                params![block.id, position as i64, item.task_id, item.est_minutes],
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks `blocks` before they're saved as `date`'s plan. Of two blocks with the same id,
+/// the second is the duplicate. An id is taken if a stored block that saving `date` leaves
+/// in place has it: one on another date, or one hidden because its task is trashed.
+pub fn find_conflicts(
+    conn: &Connection,
+    date: &str,
+    blocks: &[DayBlock],
+) -> rusqlite::Result<Vec<BlockConflict>> {
+    let mut stmt = conn.prepare("SELECT EXISTS(SELECT 1 FROM tasks WHERE id = ?1)")?;
+    let mut taken = conn.prepare(
+        "SELECT d.date FROM day_blocks d
+         WHERE d.id = ?1
+           AND (d.date <> ?2
+                OR EXISTS (SELECT 1 FROM tasks t
+                           WHERE t.id = d.task_id AND t.deleted_at IS NOT NULL))",
+    )?;
+    let mut conflicts = Vec::new();
+    let mut seen = HashSet::new();
+    let mut placed = Vec::new();
+    for block in blocks {
+        if !seen.insert(block.id.as_str()) {
+            conflicts.push(BlockConflict::DuplicateId {
+                block_id: block.id.clone(),
+            });
+            continue;
+        }
+        let stored_on: Option<String> = taken
+            .query_row(params![block.id, date], |r| r.get(0))
+            .optional()?;
+        if let Some(stored_on) = stored_on {
+            conflicts.push(BlockConflict::IdTaken {
+                block_id: block.id.clone(),
+                date: stored_on,
+            });
+            continue;
+        }
+        let mut fits = true;
+        if block.date != date {
+            conflicts.push(BlockConflict::WrongDate {
+                block_id: block.id.clone(),
+                date: block.date.clone(),
+            });
+            fits = false;
+        }
+        if block.length_min <= 0 {
+            conflicts.push(BlockConflict::InvalidLength {
+                block_id: block.id.clone(),
+                length_min: block.length_min,
+            });
+            fits = false;
+        } else if block.start_min < 0 || block.start_min + block.length_min > DAY_MIN {
+            conflicts.push(BlockConflict::OutsideDay {
+                block_id: block.id.clone(),
+            });
+        }
+        let task_ids = block.task_id.iter().chain(block.items.iter().map(|i| &i.task_id));
+        for task_id in task_ids {
+            if !stmt.query_row(params![task_id], |r| r.get::<_, bool>(0))? {
+                conflicts.push(BlockConflict::UnknownTask {
+                    block_id: block.id.clone(),
+                    task_id: task_id.clone(),
+                });
+            }
+        }
+        if fits {
+            placed.push(block);
+        }
+    }
+
+    // Each block is compared with whichever earlier one runs latest.
+    placed.sort_by(|a, b| (a.start_min, &a.id).cmp(&(b.start_min, &b.id)));
+    let end = |b: &DayBlock| b.start_min + b.length_min;
+    let mut latest: Option<&DayBlock> = None;
+    for block in placed {
+        if let Some(previous) = latest {
+            if block.start_min < end(previous) {
+                conflicts.push(BlockConflict::Overlap {
+                    block_id: block.id.clone(),
+                    other_block_id: previous.id.clone(),
+                });
+            }
+        }
+        let runs_later = match latest {
+            Some(previous) => end(block) > end(previous),
+            None => true,
+        };
+        if runs_later {
+            latest = Some(block);
+        }
+    }
+    Ok(conflicts)
+}
+
+/// What's left of `blocks` once everything that can't be stored is dropped: duplicates,
+/// blocks whose id is taken, blocks for another date or with no length, and blocks and
+/// items for unknown tasks.
+/// Overlapping blocks and blocks outside the day are kept.
+pub fn without_conflicts(blocks: &[DayBlock], conflicts: &[BlockConflict]) -> Vec<DayBlock> {
+    let mut dropped = HashSet::new();
+    let mut unknown_tasks = HashSet::new();
+    for conflict in conflicts {
+        match conflict {
+            BlockConflict::IdTaken { block_id, .. }
+            | BlockConflict::WrongDate { block_id, .. }
+            | BlockConflict::InvalidLength { block_id, .. } => {
+                dropped.insert(block_id.as_str());
+            }
+            BlockConflict::UnknownTask { task_id, .. } => {
+                unknown_tasks.insert(task_id.as_str());
+            }
+            _ => {}
+        }
+    }
+
+    let mut seen = HashSet::new();
+    blocks
+        .iter()
+        .filter(|b| seen.insert(b.id.as_str()) && !dropped.contains(b.id.as_str()))
+        .filter(|b| !b.task_id.as_deref().is_some_and(|t| unknown_tasks.contains(t)))
+        .map(|b| {
+            let mut block = b.clone();
+            block.items.retain(|i| !unknown_tasks.contains(i.task_id.as_str()));
+            block
+        })
+        .collect()
+}
+
+/// Replaces every block on `date`, items included, in a single transaction. Meant for bulk
+/// changes; every block saved gets a new version, changed or not.
+///
+/// Blocks and items hidden because their task is trashed aren't part of what the caller
+/// sees, so they're kept: hidden blocks stay as they are, and hidden items go back on the
+/// end of their block if it still exists.
+pub fn replace_blocks_for_date(
+    conn: &mut Connection,
+    date: &str,
+    blocks: &[DayBlock],
+) -> rusqlite::Result<()> {
+    let tx = conn.savepoint()?;
+    let versions = versions_for_date(&tx, date)?;
+    let mut stmt = tx.prepare(
+        "SELECT b.block_id, b.task_id, b.est_minutes
+         FROM block_items b
+         JOIN day_blocks d ON d.id = b.block_id
+         JOIN tasks t ON t.id = b.task_id
+         WHERE d.date = ?1 AND t.deleted_at IS NOT NULL
+         ORDER BY b.block_id, b.position",
+    )?;
+    let hidden_items = stmt
+        .query_map(params![date], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i32>(2)?,
+            ))
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    drop(stmt);
+
+    // block_items rows go with their blocks via ON DELETE CASCADE.
+    tx.execute(
+        "DELETE FROM day_blocks
+         WHERE date = ?1
+           AND NOT EXISTS (SELECT 1 FROM tasks t WHERE t.id = day_blocks.task_id AND t.deleted_at IS NOT NULL)",
+        params![date],
+    )?;
+    for block in blocks {
+        tx.execute(
+            "INSERT INTO day_blocks (id, task_id, date, start_min, length_min, kind, version)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                block.id,
+                block.task_id,
+                block.date,
+                block.start_min,
+                block.length_min,
+                block.kind,
+                next_version(&versions, block)
+            ],
+        )?;
+        for (position, item) in block.items.iter().enumerate() {
+            tx.execute(
+                "INSERT INTO block_items (block_id, position, task_id, est_minutes) VALUES (?1, ?2, ?3, ?4)",
+                params![block.id, position as i64, item.task_id, item.est_minutes],
+            )?;
+        }
+    }
+    for (block_id, task_id, est_minutes) in hidden_items {
+        tx.execute(
+            "INSERT INTO block_items (block_id, position, task_id, est_minutes)
+             SELECT ?1, (SELECT COALESCE(MAX(position) + 1, 0) FROM block_items WHERE block_id = ?1), ?2, ?3
+             WHERE EXISTS (SELECT 1 FROM day_blocks WHERE id = ?1)",
+            params![block_id, task_id, est_minutes],
+        )?;
+    }
+    tx.commit()
+}
+
+fn describe_conflict(conflict: &BlockConflict) -> String {
+    match conflict {
+        BlockConflict::DuplicateId { block_id } => format!("Block {} is listed twice", block_id),
+        BlockConflict::IdTaken { block_id, date } => {
+            format!("A block with id {} already exists on {}", block_id, date)
+        }
+        BlockConflict::WrongDate { block_id, date } => {
+            format!("Block {} is dated {}, not the day being saved", block_id, date)
+        }
+        BlockConflict::InvalidLength {
+            block_id,
+            length_min,
+        } => format!("Block {} can't be {} minutes long", block_id, length_min),
+        BlockConflict::OutsideDay { block_id } => {
+            format!("Block {} doesn't fit within its day", block_id)
+        }
+        BlockConflict::Overlap {
+            block_id,
+            other_block_id,
+        } => format!("Block {} overlaps {}", block_id, other_block_id),
+        BlockConflict::UnknownTask { block_id, task_id } => {
+            format!("Block {} refers to a task that doesn't exist: {}", block_id, task_id)
+        }
+    }
+}
+
+/// Rejects `block` if it would be stored with any conflict other than an overlap, which
+/// only the caller can judge. Its id isn't checked: an edit is to the stored block with
+/// that id, and `create_block` looks for it itself.
+fn check_block(conn: &Connection, block: &DayBlock) -> Result<(), DbError> {
+    NaiveDate::parse_from_str(&block.date, "%Y-%m-%d")
+        .map_err(|_| DbError::Invalid(format!("Invalid date: {}", block.date)))?;
+    let conflicts = find_conflicts(conn, &block.date, std::slice::from_ref(block))?;
+    match conflicts
+        .iter()
+        .find(|c| !matches!(c, BlockConflict::IdTaken { .. }))
+    {
+        Some(conflict) => Err(DbError::Invalid(describe_conflict(conflict))),
+        None => Ok(()),
+    }
+}
+
+/// One block by id with its items, whether or not its task is trashed.
+pub fn get_block(conn: &Connection, id: &str) -> Result<DayBlock, DbError> {
+    let mut block = conn
+        .query_row(
+            "SELECT id, task_id, date, start_min, length_min, kind, version FROM day_blocks
+             WHERE id = ?1",
+            params![id],
+            block_from_row,
+        )
+        .optional()?
+        .ok_or_else(|| DbError::Invalid(format!("Block not found: {}", id)))?;
+    let mut stmt = conn.prepare(
+        "SELECT task_id, est_minutes FROM block_items WHERE block_id = ?1 ORDER BY position",
+    )?;
+    block.items = stmt
+        .query_map(params![id], |row| {
+            Ok(WorkItem {
+                task_id: row.get(0)?,
+                est_minutes: row.get(1)?,
+            })
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(block)
+}
+
+/// Loads a block for an edit made against `expected_version`, refusing if it has changed
+/// since.
+fn get_block_at(conn: &Connection, id: &str, expected_version: i64) -> Result<DayBlock, DbError> {
+    let block = get_block(conn, id)?;
+    if block.version != expected_version {
+        return Err(DbError::Invalid(format!(
+            "Block {} was changed elsewhere (now version {}, expected {}); reload and try again",
+            id, block.version, expected_version
+        )));
+    }
+    Ok(block)
+}
+
+/// Writes `block`'s new date and times over `expected_version`.
+fn update_block(
+    conn: &mut Connection,
+    block: &DayBlock,
+    expected_version: i64,
+) -> Result<DayBlock, DbError> {
+    check_block(conn, block)?;
+    let updated = conn.execute(
+        "UPDATE day_blocks SET date = ?2, start_min = ?3, length_min = ?4, version = version + 1
+         WHERE id = ?1 AND version = ?5",
+        params![
+            block.id,
+            block.date,
+            block.start_min,
+            block.length_min,
+            expected_version
+        ],
+    )?;
+    if updated == 0 {
+        get_block_at(conn, &block.id, expected_version)?;
+    }
+    get_block(conn, &block.id)
+}
+
+/// Adds one block, giving it an id if it has none. It starts at version 1.
+pub fn create_block(conn: &mut Connection, block: &DayBlock) -> Result<DayBlock, DbError> {
+    let mut block = block.clone();
+    if block.id.is_empty() {
+        block.id = Uuid::new_v4().to_string();
+    }
+    check_block(conn, &block)?;
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM day_blocks WHERE id = ?1)",
+        params![block.id],
+        |r| r.get(0),
+    )?;
+    if exists {
+        return Err(DbError::Invalid(format!(
+            "A block with id {} already exists",
+            block.id
+        )));
+    }
+
+    let sp = conn.savepoint()?;
+    sp.execute(
+        "INSERT INTO day_blocks (id, task_id, date, start_min, length_min, kind, version)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1)",
+        params![
+            block.id,
+            block.task_id,
+            block.date,
+            block.start_min,
+            block.length_min,
+            block.kind
+        ],
+    )?;
+    for (position, item) in block.items.iter().enumerate() {
+        sp.execute(
+            "INSERT INTO block_items (block_id, position, task_id, est_minutes)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![block.id, position as i64, item.task_id, item.est_minutes],
+        )?;
+    }
+    sp.commit()?;
+    get_block(conn, &block.id)
+}
+
+/// Moves a block to `start_min` on `date`, keeping its length.
+pub fn move_block(
+    conn: &mut Connection,
+    id: &str,
+    expected_version: i64,
+    date: &str,
+    start_min: i32,
+) -> Result<DayBlock, DbError> {
+    let mut block = get_block_at(conn, id, expected_version)?;
+    block.date = date.to_string();
+    block.start_min = start_min;
+    update_block(conn, &block, expected_version)
+}
+
+/// Changes a block's length, keeping its start.
+pub fn resize_block(
+    conn: &mut Connection,
+    id: &str,
+    expected_version: i64,
+    length_min: i32,
+) -> Result<DayBlock, DbError> {
+    let mut block = get_block_at(conn, id, expected_version)?;
+    block.length_min = length_min;
+    update_block(conn, &block, expected_version)
+}
+
+/// Deletes a block and its items, returning it as it was.
+pub fn delete_block(
+    conn: &mut Connection,
+    id: &str,
+    expected_version: i64,
+) -> Result<DayBlock, DbError> {
+    let block = get_block_at(conn, id, expected_version)?;
+    // block_items rows go with their blocks via ON DELETE CASCADE.
+    conn.execute(
+        "DELETE FROM day_blocks WHERE id = ?1 AND version = ?2",
+        params![id, expected_version],
+    )?;
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations::run_migrations;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO tasks (id, title) VALUES ('t1', 'Write report');
+            INSERT INTO tasks (id, title) VALUES ('t2', 'Reply to email');
+            INSERT INTO tasks (id, title) VALUES ('t3', 'Book flights');
+            ",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn atomic(id: &str, task_id: &str, start_min: i32, length_min: i32) -> DayBlock {
+        DayBlock {
+            id: id.to_string(),
+            task_id: Some(task_id.to_string()),
+            date: "2024-05-01".to_string(),
+            start_min,
+            length_min,
+            kind: "atomic".to_string(),
+            items: vec![],
+            version: 0,
+        }
+    }
+
+    fn work(id: &str, start_min: i32, length_min: i32, items: &[(&str, i32)]) -> DayBlock {
+        DayBlock {
+            id: id.to_string(),
+            task_id: None,
+            date: "2024-05-01".to_string(),
+            start_min,
+            length_min,
+            kind: "work".to_string(),
+            items: items
+                .iter()
+                .map(|(task_id, est_minutes)| WorkItem {
+                    task_id: task_id.to_string(),
+                    est_minutes: *est_minutes,
+                })
+                .collect(),
+            version: 0,
+        }
+    }
+
+    fn item_count(conn: &Connection) -> i64 {
+        conn.query_row("SELECT COUNT(*) FROM block_items", [], |r| r.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_work_block_items_round_trip() {
+        let mut conn = setup();
+        let blocks = vec![
+            atomic("a", "t1", 540, 60),
+            work("w", 600, 30, &[("t3", 10), ("t2", 5)]),
+        ];
+        replace_blocks_for_date(&mut conn, "2024-05-01", &blocks).unwrap();
+
+        let loaded = list_blocks_for_date(&conn, "2024-05-01").unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded[0].kind, "atomic");
+        assert_eq!(loaded[0].task_id.as_deref(), Some("t1"));
+        assert!(loaded[0].items.is_empty());
+        assert_eq!(loaded[1].kind, "work");
+        assert_eq!(loaded[1].task_id, None);
+        assert_eq!(loaded[1].items, blocks[1].items);
+    }
+
+    #[test]
+    fn test_minute_precision_round_trip() {
+        let mut conn = setup();
+        let blocks = vec![
+            atomic("a", "t1", 9 * 60 + 15, 45),
+            atomic("b", "t2", 10 * 60, 15),
+        ];
+        replace_blocks_for_date(&mut conn, "2024-05-01", &blocks).unwrap();
+
+        let loaded = list_blocks_for_date(&conn, "2024-05-01").unwrap();
+        assert_eq!((loaded[0].start_min, loaded[0].length_min), (555, 45));
+        assert_eq!((loaded[1].start_min, loaded[1].length_min), (600, 15));
+    }
+
+    #[test]
+    fn test_replace_drops_old_items() {
+        let mut conn = setup();
+        replace_blocks_for_date(
+            &mut conn,
+            "2024-05-01",
+            &[work("w", 600, 30, &[("t1", 10), ("t2", 5)])],
+        )
+        .unwrap();
+        replace_blocks_for_date(
+            &mut conn,
+            "2024-05-01",
+            &[work("w", 600, 30, &[("t3", 15)])],
+        )
+        .unwrap();
+
+        let loaded = list_blocks_for_date(&conn, "2024-05-01").unwrap();
+        assert_eq!(loaded[0].items.len(), 1);
+        assert_eq!(loaded[0].items[0].task_id, "t3");
+        assert_eq!(item_count(&conn), 1);
+    }
+
+    #[test]
+    fn test_failed_save_rolls_back() {
+        let mut conn = setup();
+        replace_blocks_for_date(&mut conn, "2024-05-01", &[atomic("a", "t1", 540, 60)]).unwrap();
+
+        // The second item references a task that doesn't exist, so the whole save must fail.
+        let result = replace_blocks_for_date(
+            &mut conn,
+            "2024-05-01",
+            &[work("w", 600, 30, &[("t1", 10), ("missing", 5)])],
+        );
+        assert!(result.is_err());
+
+        let loaded = list_blocks_for_date(&conn, "2024-05-01").unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].id, "a");
+        assert_eq!(item_count(&conn), 0);
+    }
+
+    #[test]
+    fn test_finds_conflicts() {
+        let conn = setup();
+        let mut elsewhere = atomic("x", "t2", 900, 30);
+        elsewhere.date = "2024-05-02".to_string();
+        let blocks = vec![
+            atomic("a", "t1", 540, 60),
+            work("w", 570, 60, &[("t2", 10), ("missing", 5)]),
+            atomic("b", "t3", 600, 10),
+            atomic("a", "t3", 700, 30),
+            atomic("empty", "t1", 800, 0),
+            atomic("late", "t1", 1430, 30),
+            elsewhere,
+        ];
+
+        let conflicts = find_conflicts(&conn, "2024-05-01", &blocks).unwrap();
+        assert_eq!(
+            conflicts,
+            vec![
+                BlockConflict::UnknownTask {
+                    block_id: "w".to_string(),
+                    task_id: "missing".to_string()
+                },
+                BlockConflict::DuplicateId {
+                    block_id: "a".to_string()
+                },
+                BlockConflict::InvalidLength {
+                    block_id: "empty".to_string(),
+                    length_min: 0
+                },
+                BlockConflict::OutsideDay {
+                    block_id: "late".to_string()
+                },
+                BlockConflict::WrongDate {
+                    block_id: "x".to_string(),
+                    date: "2024-05-02".to_string()
+                },
+                BlockConflict::Overlap {
+                    block_id: "w".to_string(),
+                    other_block_id: "a".to_string()
+                },
+                // b starts after a ends, but still inside w.
+                BlockConflict::Overlap {
+                    block_id: "b".to_string(),
+                    other_block_id: "w".to_string()
+                },
+            ]
+        );
+        assert!(find_conflicts(&conn, "2024-05-01", &blocks[..1]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_saving_without_conflicts_keeps_what_it_can() {
+        let mut conn = setup();
+        let blocks = vec![
+            atomic("a", "t1", 540, 60),
+            work("w", 570, 60, &[("t2", 10), ("missing", 5)]),
+            atomic("gone", "missing", 700, 30),
+            atomic("a", "t3", 800, 30),
+            atomic("empty", "t1", 900, 0),
+        ];
+        let conflicts = find_conflicts(&conn, "2024-05-01", &blocks).unwrap();
+        let kept = without_conflicts(&blocks, &conflicts);
+        replace_blocks_for_date(&mut conn, "2024-05-01", &kept).unwrap();
+
+        let loaded = list_blocks_for_date(&conn, "2024-05-01").unwrap();
+        let ids: Vec<&str> = loaded.iter().map(|b| b.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "w"]);
+        assert_eq!(loaded[0].task_id.as_deref(), Some("t1"));
+        assert_eq!(loaded[1].items.len(), 1);
+    }
+
+    #[test]
+    fn test_ids_taken_by_blocks_the_save_keeps() {
+        let mut conn = setup();
+        let mut elsewhere = atomic("x", "t1", 540, 30);
+        elsewhere.date = "2024-05-02".to_string();
+        replace_blocks_for_date(&mut conn, "2024-05-02", &[elsewhere]).unwrap();
+        replace_blocks_for_date(&mut conn, "2024-05-01", &[atomic("hidden", "t2", 600, 30)])
+            .unwrap();
+        conn.execute(
+            "UPDATE tasks SET deleted_at = '2024-05-01T12:00:00.000Z' WHERE id = 't2'",
+            [],
+        )
+        .unwrap();
+
+        let blocks = vec![
+            atomic("x", "t1", 540, 30),
+            atomic("hidden", "t3", 700, 30),
+            atomic("a", "t3", 800, 30),
+        ];
+        let conflicts = find_conflicts(&conn, "2024-05-01", &blocks).unwrap();
+        assert_eq!(
+            conflicts,
+            vec![
+                BlockConflict::IdTaken {
+                    block_id: "x".to_string(),
+                    date: "2024-05-02".to_string()
+                },
+                BlockConflict::IdTaken {
+                    block_id: "hidden".to_string(),
+                    date: "2024-05-01".to_string()
+                },
+            ]
+        );
+
+        // A lenient save stores the rest instead of failing on the primary key.
+        let kept = without_conflicts(&blocks, &conflicts);
+        replace_blocks_for_date(&mut conn, "2024-05-01", &kept).unwrap();
+        let ids: Vec<String> = all_blocks_for_date(&conn, "2024-05-01")
+            .unwrap()
+            .into_iter()
+            .map(|b| b.id)
+            .collect();
+        assert_eq!(ids, vec!["hidden", "a"]);
+        assert_eq!(list_blocks_for_date(&conn, "2024-05-02").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_blocks_in_range_groups_by_day() {
+        let mut conn = setup();
+        let mut friday = atomic("f", "t3", 600, 45);
+        friday.date = "2024-05-03".to_string();
+        let mut outside = atomic("o", "t3", 600, 45);
+        outside.date = "2024-05-04".to_string();
+        replace_blocks_for_date(
+            &mut conn,
+            "2024-05-01",
+            &[
+                atomic("b", "t2", 660, 30),
+                work("w", 540, 60, &[("t1", 10)]),
+            ],
+        )
+        .unwrap();
+        replace_blocks_for_date(&mut conn, "2024-05-03", &[friday]).unwrap();
+        replace_blocks_for_date(&mut conn, "2024-05-04", &[outside]).unwrap();
+
+        let days = blocks_in_range(&conn, "2024-05-01", "2024-05-03").unwrap();
+        let summary: Vec<(&str, Vec<&str>, i32)> = days
+            .iter()
+            .map(|d| {
+                let ids = d.blocks.iter().map(|b| b.id.as_str()).collect();
+                (d.date.as_str(), ids, d.planned_min)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("2024-05-01", vec!["w", "b"], 90),
+                ("2024-05-02", vec![], 0),
+                ("2024-05-03", vec!["f"], 45),
+            ]
+        );
+        assert_eq!(days[0].blocks[0].items.len(), 1);
+
+        assert!(blocks_in_range(&conn, "2024-05-03", "2024-05-01").is_err());
+        assert!(blocks_in_range(&conn, "2024-01-01", "2025-06-01").is_err());
+        assert!(blocks_in_range(&conn, "2024-05-01", "soon").is_err());
+    }
+
+    #[test]
+    fn test_block_mutations_check_versions() {
+        let mut conn = setup();
+        let created = create_block(&mut conn, &atomic("", "t1", 540, 30)).unwrap();
+        assert!(!created.id.is_empty());
+        assert_eq!(created.version, 1);
+        let id = created.id.clone();
+
+        let moved = move_block(&mut conn, &id, 1, "2024-05-02", 600).unwrap();
+        assert_eq!((moved.date.as_str(), moved.start_min, moved.version), ("2024-05-02", 600, 2));
+        let resized = resize_block(&mut conn, &id, 2, 45).unwrap();
+        assert_eq!((resized.start_min, resized.length_min, resized.version), (600, 45, 3));
+
+        // An edit based on an older copy is refused and changes nothing.
+        assert!(move_block(&mut conn, &id, 2, "2024-05-02", 660).is_err());
+        assert!(delete_block(&mut conn, &id, 1).is_err());
+        assert!(resize_block(&mut conn, &id, 3, 0).is_err());
+        assert!(move_block(&mut conn, &id, 3, "2024-05-02", 1420).is_err());
+        assert_eq!(get_block(&conn, &id).unwrap(), resized);
+
+        let deleted = delete_block(&mut conn, &id, 3).unwrap();
+        assert_eq!(deleted, resized);
+        assert!(get_block(&conn, &id).is_err());
+        assert!(list_blocks_for_date(&conn, "2024-05-02").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_create_block_rejects_bad_blocks() {
+        let mut conn = setup();
+        create_block(&mut conn, &work("w", 600, 30, &[("t1", 10)])).unwrap();
+        assert_eq!(item_count(&conn), 1);
+
+        let mut bad_date = atomic("x", "t1", 540, 30);
+        bad_date.date = "tomorrow".to_string();
+        for block in [
+            work("w", 700, 30, &[]),
+            atomic("x", "missing", 540, 30),
+            atomic("x", "t1", 540, -5),
+            bad_date,
+        ] {
+            assert!(create_block(&mut conn, &block).is_err());
+        }
+        assert_eq!(list_blocks_for_date(&conn, "2024-05-01").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_whole_day_replace_bumps_versions() {
+        let mut conn = setup();
+        let created = create_block(&mut conn, &atomic("a", "t1", 540, 30)).unwrap();
+        replace_blocks_for_date(&mut conn, "2024-05-01", std::slice::from_ref(&created)).unwrap();
+
+        let loaded = list_blocks_for_date(&conn, "2024-05-01").unwrap();
+        assert_eq!(loaded[0].version, 2);
+        assert!(move_block(&mut conn, "a", created.version, "2024-05-01", 600).is_err());
+    }
+
+    #[test]
+    fn test_deleting_task_cascades_to_items() {
+        let mut conn = setup();
+        replace_blocks_for_date(
+            &mut conn,
+            "2024-05-01",
+            &[work("w", 600, 30, &[("t1", 10), ("t2", 5)])],
+        )
+        .unwrap();
+
+        conn.execute("DELETE FROM tasks WHERE id = 't2'", [])
+            .unwrap();
+
+        let loaded = list_blocks_for_date(&conn, "2024-05-01").unwrap();
+        assert_eq!(loaded[0].items.len(), 1);
+        assert_eq!(loaded[0].items[0].task_id, "t1");
+    }
+
+    #[test]
+    fn test_blocks_hidden_while_task_trashed() {
+        let mut conn = setup();
+        let blocks = vec![
+            atomic("a", "t1", 540, 60),
+            work("w", 600, 30, &[("t2", 10), ("t3", 5)]),
+        ];
+        replace_blocks_for_date(&mut conn, "2024-05-01", &blocks).unwrap();
+        conn.execute(
+            "UPDATE tasks SET deleted_at = '2024-05-01T12:00:00.000Z' WHERE id IN ('t1', 't2')",
+            [],
+        )
+        .unwrap();
+
+        let loaded = list_blocks_for_date(&conn, "2024-05-01").unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(
+            loaded[0].items,
+            vec![WorkItem {
+                task_id: "t3".to_string(),
+                est_minutes: 5
+            }]
+        );
+
+        // Saving what the user sees must not lose the hidden block or item.
+        let mut moved = loaded[0].clone();
+        moved.start_min = 660;
+        replace_blocks_for_date(&mut conn, "2024-05-01", &[moved]).unwrap();
+
+        conn.execute("UPDATE tasks SET deleted_at = NULL", [])
+            .unwrap();
+        let restored = list_blocks_for_date(&conn, "2024-05-01").unwrap();
+        assert_eq!(restored.len(), 2);
+        assert_eq!(restored[0].id, "a");
+        assert_eq!(restored[1].start_min, 660);
+        let item_tasks: Vec<&str> = restored[1]
+            .items
+            .iter()
+            .map(|i| i.task_id.as_str())
+            .collect();
+        assert_eq!(item_tasks, vec!["t3", "t2"]);
+    }
+}
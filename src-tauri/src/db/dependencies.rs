@@ -0,0 +1,170 @@
+use super::DbError;
+use rusqlite::{params, Connection};
+
+/// Records that `task_id` can't start until `depends_on_id` is done.
+///
+/// Rejects self-dependencies and any edge that would close a cycle, i.e. when
+/// `depends_on_id` already (transitively) waits on `task_id`.
+pub fn add_dependency(
+    conn: &Connection,
+    task_id: &str,
+    depends_on_id: &str,
+) -> Result<(), DbError> {
+    if task_id == depends_on_id {
+        return Err(DbError::Invalid(
+            "A task can't depend on itself".to_string(),
+        ));
+    }
+    for id in [task_id, depends_on_id] {
+        let exists: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM tasks WHERE id = ?1 AND deleted_at IS NULL)",
+            params![id],
+            |r| r.get(0),
+        )?;
+        if !exists {
+            return Err(DbError::Invalid(format!("Task not found: {}", id)));
+        }
+    }
+
+    let creates_cycle: bool = conn.query_row(
+        "WITH RECURSIVE prerequisites(id) AS (
+             SELECT ?1
+             UNION
+             SELECT d.depends_on_id FROM task_dependencies d JOIN prerequisites p ON d.task_id = p.id
+         )
+         SELECT EXISTS(SELECT 1 FROM prerequisites WHERE id = ?2)",
+        params![depends_on_id, task_id],
+        |r| r.get(0),
+    )?;
+    if creates_cycle {
+        return Err(DbError::Invalid(format!(
+            "Adding this dependency would create a cycle: {} already waits on {}",
+            depends_on_id, task_id
+        )));
+    }
+
+    conn.execute(
+        "INSERT OR IGNORE INTO task_dependencies (task_id, depends_on_id) VALUES (?1, ?2)",
+        params![task_id, depends_on_id],
+    )?;
+    Ok(())
+}
+
+pub fn remove_dependency(
+    conn: &Connection,
+    task_id: &str,
+    depends_on_id: &str,
+) -> Result<(), DbError> {
+    conn.execute(
+        "DELETE FROM task_dependencies WHERE task_id = ?1 AND depends_on_id = ?2",
+        params![task_id, depends_on_id],
+    )?;
+    Ok(())
+}
+
+/// Direct prerequisites of `task_id`.
+pub fn list_dependencies(conn: &Connection, task_id: &str) -> Result<Vec<String>, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT depends_on_id FROM task_dependencies WHERE task_id = ?1 ORDER BY depends_on_id",
+    )?;
+    let ids = stmt
+        .query_map(params![task_id], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations::run_migrations;
+    use crate::db::tasks::list_tasks;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO tasks (id, title, est_minutes) VALUES ('a', 'Draft spec', 30);
+            INSERT INTO tasks (id, title, est_minutes) VALUES ('b', 'Review spec', 30);
+            INSERT INTO tasks (id, title, est_minutes) VALUES ('c', 'Build feature', 30);
+            ",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn blocked(conn: &Connection, id: &str) -> bool {
+        list_tasks(conn)
+            .unwrap()
+            .into_iter()
+            .find(|t| t.id == id)
+            .unwrap()
+            .blocked
+    }
+
+    #[test]
+    fn test_blocked_until_prerequisite_done() {
+        let conn = setup();
+        add_dependency(&conn, "b", "a").unwrap();
+        assert!(blocked(&conn, "b"));
+        assert!(!blocked(&conn, "a"));
+
+        conn.execute("UPDATE tasks SET done = 1 WHERE id = 'a'", [])
+            .unwrap();
+        assert!(!blocked(&conn, "b"));
+    }
+
+    #[test]
+    fn test_rejects_cycles() {
+        let conn = setup();
+        add_dependency(&conn, "b", "a").unwrap();
+        add_dependency(&conn, "c", "b").unwrap();
+
+        assert!(matches!(
+            add_dependency(&conn, "a", "a"),
+            Err(DbError::Invalid(_))
+        ));
+        assert!(matches!(
+            add_dependency(&conn, "a", "b"),
+            Err(DbError::Invalid(_))
+        ));
+        // Transitive: c -> b -> a, so a can't wait on c.
+        assert!(matches!(
+            add_dependency(&conn, "a", "c"),
+            Err(DbError::Invalid(_))
+        ));
+        // A shortcut along the existing direction is fine.
+        add_dependency(&conn, "c", "a").unwrap();
+        assert_eq!(list_dependencies(&conn, "c").unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_remove_dependency_unblocks() {
+        let conn = setup();
+        add_dependency(&conn, "b", "a").unwrap();
+        remove_dependency(&conn, "b", "a").unwrap();
+
+        assert!(!blocked(&conn, "b"));
+        assert!(list_dependencies(&conn, "b").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_unknown_task_rejected() {
+        let conn = setup();
+        assert!(matches!(
+            add_dependency(&conn, "b", "missing"),
+            Err(DbError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_deleting_task_drops_its_dependencies() {
+        let conn = setup();
+        add_dependency(&conn, "b", "a").unwrap();
+        conn.execute("DELETE FROM tasks WHERE id = 'a'", [])
+            .unwrap();
+
+        assert!(!blocked(&conn, "b"));
+        assert!(list_dependencies(&conn, "b").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,748 @@
+//! Change history behind undo/redo.
+//!
+//! Commands that change tasks, day plans, projects, focus sessions or availability run
+//! through [`record`], which snapshots every entity the command touches before and after it
+//! runs and appends a `task_events` row for each one that actually changed. Rows from one
+//! command share a `change_id`; the only thing ever updated afterwards is their `undone`
+//! flag.
+//!
+//! Undo puts back the `before` snapshots of the newest change that isn't undone. Redo
+//! re-applies the `after` snapshots of the oldest undone change newer than that. Making a
+//! new change after an undo ends the redo path, as in an editor: the undone changes are
+//! marked abandoned (`undone = 2`) and can't be redone any more.
+//!
+//! Settings aren't tracked, since they hold the API key.
+//!
+//! Mutating functions elsewhere in `db` use savepoints rather than transactions so they can
+//! run inside the transaction opened here.
+
+use super::{availability, blocks, projects, sessions, tags, tasks, DbError};
+use crate::models::{DayBlock, HistoryChange, HistoryEvent, Task};
+use rusqlite::{params, Connection, Row, ToSql};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Something whose state is captured in a history event.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Entity {
+    Task(String),
+    Day(String), // every block on a YYYY-MM-DD date
+    Project(String),
+    Session(i64),
+    Availability, // the weekly hours and every blocked period
+}
+
+impl Entity {
+    fn kind(&self) -> &'static str {
+        match self {
+            Entity::Task(_) => "task",
+            Entity::Day(_) => "day",
+            Entity::Project(_) => "project",
+            Entity::Session(_) => "session",
+            Entity::Availability => "availability",
+        }
+    }
+
+    fn key(&self) -> String {
+        match self {
+            Entity::Task(id) | Entity::Day(id) | Entity::Project(id) => id.clone(),
+            Entity::Session(id) => id.to_string(),
+            Entity::Availability => String::new(),
+        }
+    }
+
+    fn parse(kind: &str, key: &str) -> Result<Entity, DbError> {
+        match kind {
+            "task" => Ok(Entity::Task(key.to_string())),
+            "day" => Ok(Entity::Day(key.to_string())),
+            "project" => Ok(Entity::Project(key.to_string())),
+            "availability" => Ok(Entity::Availability),
+            "session" => key
+                .parse()
+                .map(Entity::Session)
+                .map_err(|_| DbError::Invalid(format!("Bad session id in history: {}", key))),
+            other => Err(DbError::Invalid(format!(
+                "Unknown history entity: {}",
+                other
+            ))),
+        }
+    }
+}
+
+/// A task as history stores it: the row with its tags, plus what it waits on.
+#[derive(Serialize, Deserialize)]
+struct TaskSnapshot {
+    #[serde(flatten)]
+    task: Task,
+    depends_on: Vec<String>,
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<serde_json::Value, DbError> {
+    serde_json::to_value(value).map_err(|e| DbError::Invalid(e.to_string()))
+}
+
+fn from_json<T: DeserializeOwned>(value: &serde_json::Value) -> Result<T, DbError> {
+    serde_json::from_value(value.clone())
+        .map_err(|e| DbError::Invalid(format!("Unreadable history snapshot: {}", e)))
+}
+
+fn ids_json(ids: &[String]) -> Result<String, DbError> {
+    serde_json::to_string(ids).map_err(|e| DbError::Invalid(e.to_string()))
+}
+
+/// Runs `f` in one transaction. Undo and redo defer foreign-key checks to the commit, so
+/// rows can be put back in any order.
+fn in_transaction<T>(
+    conn: &mut Connection,
+    defer_foreign_keys: bool,
+    f: impl FnOnce(&mut Connection) -> Result<T, DbError>,
+) -> Result<T, DbError> {
+    conn.execute_batch("BEGIN IMMEDIATE")?;
+    let result = (|| {
+        if defer_foreign_keys {
+            conn.execute_batch("PRAGMA defer_foreign_keys = ON")?;
+        }
+        let value = f(conn)?;
+        conn.execute_batch("COMMIT")?;
+        Ok(value)
+    })();
+    if result.is_err() && !conn.is_autocommit() {
+        let _ = conn.execute_batch("ROLLBACK");
+    }
+    result
+}
+
+/// Current state of each entity, `None` for those that don't exist.
+fn snapshot(
+    conn: &Connection,
+    entities: &[Entity],
+) -> Result<Vec<Option<serde_json::Value>>, DbError> {
+    let task_ids: Vec<String> = entities
+        .iter()
+        .filter_map(|e| match e {
+            Entity::Task(id) => Some(id.clone()),
+            _ => None,
+        })
+        .collect();
+    let mut found = HashMap::new();
+    let mut depends_on: HashMap<String, Vec<String>> = HashMap::new();
+    if !task_ids.is_empty() {
+        found = tasks::tasks_by_ids(conn, &task_ids)?;
+        let mut stmt = conn.prepare(
+            "SELECT task_id, depends_on_id FROM task_dependencies
+             WHERE task_id IN (SELECT value FROM json_each(?1))
+             ORDER BY task_id, depends_on_id",
+        )?;
+        let rows = stmt.query_map(params![ids_json(&task_ids)?], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (task_id, depends_on_id) = row?;
+            depends_on.entry(task_id).or_default().push(depends_on_id);
+        }
+    }
+
+    entities
+        .iter()
+        .map(|entity| match entity {
+            Entity::Task(id) => found
+                .remove(id)
+                .map(|mut task| {
+                    // Derived from other tasks; not part of this one's state.
+                    task.blocked = false;
+                    to_json(&TaskSnapshot {
+                        task,
+                        depends_on: depends_on.remove(id).unwrap_or_default(),
+                    })
+                })
+                .transpose(),
+            Entity::Day(date) => {
+                let blocks = blocks::all_blocks_for_date(conn, date)?;
+                if blocks.is_empty() {
+                    Ok(None)
+                } else {
+                    to_json(&blocks).map(Some)
+                }
+            }
+            Entity::Project(id) => match projects::get_project(conn, id) {
+                Ok(project) => to_json(&project).map(Some),
+                Err(DbError::Invalid(_)) => Ok(None),
+                Err(e) => Err(e),
+            },
+            Entity::Session(id) => sessions::get_session(conn, *id)?
+                .map(|s| to_json(&s))
+                .transpose(),
+            Entity::Availability => to_json(&availability::get_availability(conn)?).map(Some),
+        })
+        .collect()
+}
+
+/// Puts entities into the given states. Rows go first and references second, so a change
+/// can bring back a parent and its subtasks in any order. References to rows that are gone
+/// for good (e.g. purged from the trash since) are dropped.
+fn restore(
+    conn: &Connection,
+    states: &[(Entity, Option<serde_json::Value>)],
+) -> Result<(), DbError> {
+    for (entity, state) in states {
+        match (entity, state) {
+            (Entity::Task(_), Some(state)) => {
+                let snapshot: TaskSnapshot = from_json(state)?;
+                tasks::write_task_row(conn, &snapshot.task)?;
+            }
+            (Entity::Task(id), None) => {
+                conn.execute("DELETE FROM day_blocks WHERE task_id = ?1", params![id])?;
+                conn.execute("DELETE FROM tasks WHERE id = ?1", params![id])?;
+            }
+            (Entity::Project(_), Some(state)) => projects::write_project(conn, &from_json(state)?)?,
+            (Entity::Project(id), None) => projects::delete_project(conn, id)?,
+            (Entity::Session(id), None) => sessions::delete_session(conn, *id)?,
+            (Entity::Availability, Some(state)) => {
+                availability::write_availability(conn, &from_json(state)?)?
+            }
+            (Entity::Day(_), _) | (Entity::Session(_), Some(_)) | (Entity::Availability, None) => {}
+        }
+    }
+
+    for (entity, state) in states {
+        match (entity, state) {
+            (Entity::Task(id), Some(state)) => {
+                let snapshot: TaskSnapshot = from_json(state)?;
+                tags::set_task_tags(conn, id, snapshot.task.tags.as_deref().unwrap_or_default())?;
+                conn.execute(
+                    "DELETE FROM task_dependencies WHERE task_id = ?1",
+                    params![id],
+                )?;
+                for depends_on_id in &snapshot.depends_on {
+                    conn.execute(
+                        "INSERT OR IGNORE INTO task_dependencies (task_id, depends_on_id)
+                         SELECT ?1, ?2 WHERE EXISTS (SELECT 1 FROM tasks WHERE id = ?2)",
+                        params![id, depends_on_id],
+                    )?;
+                }
+                conn.execute(
+                    "UPDATE tasks SET
+                         parent_id = CASE WHEN EXISTS (SELECT 1 FROM tasks p WHERE p.id = tasks.parent_id) THEN parent_id END,
+                         project_id = CASE WHEN EXISTS (SELECT 1 FROM projects p WHERE p.id = tasks.project_id) THEN project_id END
+                     WHERE id = ?1",
+                    params![id],
+                )?;
+            }
+            (Entity::Day(date), state) => {
+                let blocks: Vec<DayBlock> = match state {
+                    Some(state) => from_json(state)?,
+                    None => Vec::new(),
+                };
+                blocks::overwrite_blocks_for_date(conn, date, &blocks)?;
+            }
+            (Entity::Session(_), Some(state)) => sessions::write_session(conn, &from_json(state)?)?,
+            _ => {}
+        }
+    }
+    tags::prune_unused(conn)?;
+    Ok(())
+}
+
+/// Runs `change` and records what it did as one undoable change named after `command`.
+///
+/// `touched` lists the existing entities the change may modify or remove; `created` lists
+/// the ones it added, given its result. Entities that come out unchanged aren't logged.
+pub fn record<T>(
+    conn: &mut Connection,
+    command: &str,
+    touched: impl FnOnce(&Connection) -> Result<Vec<Entity>, DbError>,
+    change: impl FnOnce(&mut Connection) -> Result<T, DbError>,
+    created: impl FnOnce(&T) -> Vec<Entity>,
+) -> Result<T, DbError> {
+    in_transaction(conn, false, |conn| {
+        let mut entities: Vec<Entity> = Vec::new();
+        for entity in touched(conn)? {
+            if !entities.contains(&entity) {
+                entities.push(entity);
+            }
+        }
+        let mut before = snapshot(conn, &entities)?;
+
+        let value = change(conn)?;
+
+        for entity in created(&value) {
+            if !entities.contains(&entity) {
+                entities.push(entity);
+                before.push(None);
+            }
+        }
+        let after = snapshot(conn, &entities)?;
+
+        let change_id: i64 = conn.query_row(
+            "SELECT COALESCE(MAX(change_id), 0) + 1 FROM task_events",
+            [],
+            |r| r.get(0),
+        )?;
+        let at = tasks::now_iso();
+        let mut abandoned = false;
+        for ((entity, before), after) in entities.iter().zip(before).zip(after) {
+            if before == after {
+                continue;
+            }
+            if !abandoned {
+                conn.execute("UPDATE task_events SET undone = 2 WHERE undone = 1", [])?;
+                abandoned = true;
+            }
+            conn.execute(
+                "INSERT INTO task_events (change_id, at, command, entity, entity_id, before, after)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    change_id,
+                    at,
+                    command,
+                    entity.kind(),
+                    entity.key(),
+                    before.map(|v| v.to_string()),
+                    after.map(|v| v.to_string()),
+                ],
+            )?;
+        }
+        Ok(value)
+    })
+}
+
+fn event_from_row(row: &Row) -> rusqlite::Result<HistoryEvent> {
+    let parse = |json: Option<String>| json.and_then(|j| serde_json::from_str(&j).ok());
+    Ok(HistoryEvent {
+        id: row.get(0)?,
+        change_id: row.get(1)?,
+        at: row.get(2)?,
+        command: row.get(3)?,
+        entity: row.get(4)?,
+        entity_id: row.get(5)?,
+        before: parse(row.get(6)?),
+        after: parse(row.get(7)?),
+        undone: row.get(8)?,
+    })
+}
+
+fn load_events(
+    conn: &Connection,
+    filter: &str,
+    order: &str,
+    params: &[&dyn ToSql],
+) -> Result<Vec<HistoryEvent>, DbError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, change_id, at, command, entity, entity_id, before, after, undone
+         FROM task_events WHERE {} ORDER BY {}",
+        filter, order
+    ))?;
+    let events = stmt
+        .query_map(params, event_from_row)?
+        .collect::<Result<_, _>>()?;
+    Ok(events)
+}
+
+fn load_change(conn: &Connection, change_id: i64) -> Result<HistoryChange, DbError> {
+    let events = load_events(conn, "change_id = ?1", "id", &[&change_id])?;
+    let first = events
+        .first()
+        .ok_or_else(|| DbError::Invalid(format!("Change not found: {}", change_id)))?;
+    Ok(HistoryChange {
+        change_id,
+        command: first.command.clone(),
+        at: first.at.clone(),
+        events,
+    })
+}
+
+/// Reverts the newest change that isn't undone yet, returning it, or `None` if there's
+/// nothing left to undo.
+pub fn undo_last(conn: &mut Connection) -> Result<Option<HistoryChange>, DbError> {
+    in_transaction(conn, true, |conn| {
+        let change_id: Option<i64> = conn.query_row(
+            "SELECT MAX(change_id) FROM task_events WHERE undone = 0",
+            [],
+            |r| r.get(0),
+        )?;
+        let Some(change_id) = change_id else {
+            return Ok(None);
+        };
+        let change = load_change(conn, change_id)?;
+        let states = change
+            .events
+            .iter()
+            .rev()
+            .map(|e| Ok((Entity::parse(&e.entity, &e.entity_id)?, e.before.clone())))
+            .collect::<Result<Vec<_>, DbError>>()?;
+        restore(conn, &states)?;
+        conn.execute(
+            "UPDATE task_events SET undone = 1 WHERE change_id = ?1",
+            params![change_id],
+        )?;
+        load_change(conn, change_id).map(Some)
+    })
+}
+
+/// Re-applies the most recently undone change, returning it, or `None` if there's nothing
+/// to redo.
+pub fn redo(conn: &mut Connection) -> Result<Option<HistoryChange>, DbError> {
+    in_transaction(conn, true, |conn| {
+        let change_id: Option<i64> = conn.query_row(
+            "SELECT MIN(change_id) FROM task_events
+             WHERE undone = 1
+               AND change_id > (SELECT COALESCE(MAX(change_id), 0) FROM task_events WHERE undone = 0)",
+            [],
+            |r| r.get(0),
+        )?;
+        let Some(change_id) = change_id else {
+            return Ok(None);
+        };
+        let change = load_change(conn, change_id)?;
+        let states = change
+            .events
+            .iter()
+            .map(|e| Ok((Entity::parse(&e.entity, &e.entity_id)?, e.after.clone())))
+            .collect::<Result<Vec<_>, DbError>>()?;
+        restore(conn, &states)?;
+        conn.execute(
+            "UPDATE task_events SET undone = 0 WHERE change_id = ?1",
+            params![change_id],
+        )?;
+        load_change(conn, change_id).map(Some)
+    })
+}
+
+/// Every recorded event for one task, newest first.
+pub fn task_history(conn: &Connection, task_id: &str) -> Result<Vec<HistoryEvent>, DbError> {
+    load_events(
+        conn,
+        "entity = 'task' AND entity_id = ?1",
+        "id DESC",
+        &[&task_id],
+    )
+}
+
+/// `id` and all of its subtasks, trashed ones included.
+pub fn task_subtree(conn: &Connection, id: &str) -> Result<Vec<Entity>, DbError> {
+    let mut stmt = conn.prepare(
+        "WITH RECURSIVE subtree(id) AS (
+             SELECT id FROM tasks WHERE id = ?1
+             UNION
+             SELECT t.id FROM tasks t JOIN subtree s ON t.parent_id = s.id
+         )
+         SELECT id FROM subtree",
+    )?;
+    let ids = stmt
+        .query_map(params![id], |r| r.get(0))?
+        .map(|id| id.map(Entity::Task))
+        .collect::<Result<_, _>>()?;
+    Ok(ids)
+}
+
+/// Tasks matching a SQL condition on `tasks`.
+pub fn tasks_where(
+    conn: &Connection,
+    condition: &str,
+    params: &[&dyn ToSql],
+) -> Result<Vec<Entity>, DbError> {
+    let mut stmt = conn.prepare(&format!("SELECT id FROM tasks WHERE {}", condition))?;
+    let ids = stmt
+        .query_map(params, |r| r.get(0))?
+        .map(|id| id.map(Entity::Task))
+        .collect::<Result<_, _>>()?;
+    Ok(ids)
+}
+
+/// Days whose plan has a block or work item for any task matching `condition`.
+pub fn days_planning(
+    conn: &Connection,
+    condition: &str,
+    params: &[&dyn ToSql],
+) -> Result<Vec<Entity>, DbError> {
+    let tasks = format!("SELECT id FROM tasks WHERE {}", condition);
+    let mut stmt = conn.prepare(&format!(
+        "SELECT DISTINCT date FROM day_blocks
+         WHERE task_id IN ({tasks})
+            OR id IN (SELECT block_id FROM block_items WHERE task_id IN ({tasks}))
+         ORDER BY date"
+    ))?;
+    let days = stmt
+        .query_map(params, |r| r.get(0))?
+        .map(|date| date.map(Entity::Day))
+        .collect::<Result<_, _>>()?;
+    Ok(days)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations::run_migrations;
+    use crate::db::test_util::task;
+    use crate::models::{BlockedPeriod, ChildPolicy};
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn
+    }
+
+    fn add(conn: &mut Connection, t: Task) {
+        record(
+            conn,
+            "add_task",
+            |_| Ok(Vec::new()),
+            |conn| tasks::insert_task(conn, &t),
+            |_| vec![Entity::Task(t.id.clone())],
+        )
+        .unwrap();
+    }
+
+    fn update(conn: &mut Connection, t: &Task) {
+        record(
+            conn,
+            "update_task",
+            |_| Ok(vec![Entity::Task(t.id.clone())]),
+            |conn| tasks::update_task(conn, t, false),
+            |_| Vec::new(),
+        )
+        .unwrap();
+    }
+
+    fn title(conn: &Connection, id: &str) -> Option<String> {
+        tasks::tasks_by_ids(conn, &[id.to_string()])
+            .unwrap()
+            .remove(id)
+            .map(|t| t.title)
+    }
+
+    #[test]
+    fn test_undo_and_redo_update() {
+        let mut conn = setup();
+        add(&mut conn, task("a"));
+        let mut a = task("a");
+        a.title = "Renamed".to_string();
+        a.tags = Some(vec!["home".to_string()]);
+        update(&mut conn, &a);
+
+        let undone = undo_last(&mut conn).unwrap().unwrap();
+        assert_eq!(undone.command, "update_task");
+        assert!(undone.events.iter().all(|e| e.undone));
+        let restored = tasks::tasks_by_ids(&conn, &["a".to_string()])
+            .unwrap()
+            .remove("a")
+            .unwrap();
+        assert_eq!(restored.title, "Task a");
+        assert_eq!(restored.tags, Some(vec!["work".to_string()]));
+
+        let redone = redo(&mut conn).unwrap().unwrap();
+        assert_eq!(redone.command, "update_task");
+        assert_eq!(title(&conn, "a").as_deref(), Some("Renamed"));
+        assert!(redo(&mut conn).unwrap().is_none());
+
+        let history = task_history(&conn, "a").unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].command, "update_task");
+        assert_eq!(history[0].after.as_ref().unwrap()["title"], "Renamed");
+        assert_eq!(history[1].command, "add_task");
+        assert!(history[1].before.is_none());
+    }
+
+    #[test]
+    fn test_unchanged_entities_not_logged() {
+        let mut conn = setup();
+        add(&mut conn, task("a"));
+        update(&mut conn, &task("a"));
+        assert_eq!(task_history(&conn, "a").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_undo_add_removes_task_and_its_blocks() {
+        let mut conn = setup();
+        add(&mut conn, task("a"));
+        let block = DayBlock {
+            id: "b1".to_string(),
+            task_id: Some("a".to_string()),
+            date: "2024-05-01".to_string(),
+            start_min: 540,
+            length_min: 30,
+            kind: "atomic".to_string(),
+            items: vec![],
+            version: 0,
+        };
+        // Planned outside history, so undoing the add has to clear it.
+        blocks::replace_blocks_for_date(&mut conn, "2024-05-01", &[block]).unwrap();
+
+        undo_last(&mut conn).unwrap().unwrap();
+        assert!(title(&conn, "a").is_none());
+        assert!(blocks::all_blocks_for_date(&conn, "2024-05-01")
+            .unwrap()
+            .is_empty());
+        assert!(undo_last(&mut conn).unwrap().is_none());
+
+        redo(&mut conn).unwrap().unwrap();
+        let redone = tasks::tasks_by_ids(&conn, &["a".to_string()])
+            .unwrap()
+            .remove("a")
+            .unwrap();
+        assert_eq!(redone.tags, Some(vec!["work".to_string()]));
+    }
+
+    #[test]
+    fn test_undo_delete_restores_subtree_and_dependencies() {
+        let mut conn = setup();
+        add(&mut conn, task("a"));
+        let mut child = task("child");
+        child.parent_id = Some("a".to_string());
+        add(&mut conn, child);
+        add(&mut conn, task("b"));
+        crate::db::dependencies::add_dependency(&conn, "b", "child").unwrap();
+        record(
+            &mut conn,
+            "delete_task",
+            |conn| task_subtree(conn, "a"),
+            |conn| tasks::delete_task(conn, "a", ChildPolicy::Cascade),
+            |_| Vec::new(),
+        )
+        .unwrap();
+        record(
+            &mut conn,
+            "empty_trash",
+            |conn| tasks_where(conn, "deleted_at IS NOT NULL", &[]),
+            tasks::empty_trash,
+            |_| Vec::new(),
+        )
+        .unwrap();
+        assert!(title(&conn, "child").is_none());
+
+        undo_last(&mut conn).unwrap().unwrap();
+        let trashed = tasks::list_trash(&conn).unwrap();
+        assert_eq!(trashed.len(), 2);
+        undo_last(&mut conn).unwrap().unwrap();
+        let live = tasks::tasks_by_ids(&conn, &["child".to_string()])
+            .unwrap()
+            .remove("child")
+            .unwrap();
+        assert_eq!(live.parent_id.as_deref(), Some("a"));
+        assert!(live.deleted_at.is_none());
+        // b's edge went with the purge and isn't part of the child's snapshot.
+        assert!(crate::db::dependencies::list_dependencies(&conn, "b")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_new_change_ends_redo_path() {
+        let mut conn = setup();
+        add(&mut conn, task("a"));
+        let mut a = task("a");
+        a.title = "First".to_string();
+        update(&mut conn, &a);
+        undo_last(&mut conn).unwrap().unwrap();
+
+        a.title = "Second".to_string();
+        update(&mut conn, &a);
+        assert!(redo(&mut conn).unwrap().is_none());
+
+        // Undoing the new change makes it, not the abandoned one, the thing to redo.
+        undo_last(&mut conn).unwrap().unwrap();
+        assert_eq!(title(&conn, "a").as_deref(), Some("Task a"));
+        redo(&mut conn).unwrap().unwrap();
+        assert_eq!(title(&conn, "a").as_deref(), Some("Second"));
+        assert!(redo(&mut conn).unwrap().is_none());
+
+        undo_last(&mut conn).unwrap().unwrap();
+        assert_eq!(title(&conn, "a").as_deref(), Some("Task a"));
+        undo_last(&mut conn).unwrap().unwrap();
+        assert!(title(&conn, "a").is_none());
+    }
+
+    #[test]
+    fn test_undo_day_plan_and_project_delete() {
+        let mut conn = setup();
+        let project = projects::create_project(&conn, "Home", None).unwrap();
+        let mut a = task("a");
+        a.project_id = Some(project.id.clone());
+        add(&mut conn, a);
+
+        let block = |start_min| DayBlock {
+            id: format!("b{}", start_min),
+            task_id: Some("a".to_string()),
+            date: "2024-05-01".to_string(),
+            start_min,
+            length_min: 30,
+            kind: "atomic".to_string(),
+            items: vec![],
+            version: 0,
+        };
+        for plan in [vec![block(540)], vec![block(600)]] {
+            record(
+                &mut conn,
+                "save_blocks_for_date",
+                |_| Ok(vec![Entity::Day("2024-05-01".to_string())]),
+                |conn| Ok(blocks::replace_blocks_for_date(conn, "2024-05-01", &plan)?),
+                |_| Vec::new(),
+            )
+            .unwrap();
+        }
+        let id = project.id.clone();
+        record(
+            &mut conn,
+            "delete_project",
+            |conn| {
+                let mut touched = tasks_where(conn, "project_id = ?1", &[&id])?;
+                touched.push(Entity::Project(id.clone()));
+                Ok(touched)
+            },
+            |conn| projects::delete_project(conn, &id),
+            |_| Vec::new(),
+        )
+        .unwrap();
+
+        undo_last(&mut conn).unwrap().unwrap();
+        assert_eq!(
+            projects::get_project(&conn, &project.id).unwrap().name,
+            "Home"
+        );
+        let a = tasks::tasks_by_ids(&conn, &["a".to_string()])
+            .unwrap()
+            .remove("a")
+            .unwrap();
+        assert_eq!(a.project_id, Some(project.id.clone()));
+
+        undo_last(&mut conn).unwrap().unwrap();
+        let plan = blocks::all_blocks_for_date(&conn, "2024-05-01").unwrap();
+        assert_eq!(
+            plan.iter().map(|b| b.start_min).collect::<Vec<_>>(),
+            vec![540]
+        );
+        undo_last(&mut conn).unwrap().unwrap();
+        assert!(blocks::all_blocks_for_date(&conn, "2024-05-01")
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_undo_and_redo_availability() {
+        let mut conn = setup();
+        let before = availability::get_availability(&conn).unwrap();
+        let mut changed = before.clone();
+        changed.days[5].working = true;
+        changed.blocked.push(BlockedPeriod {
+            id: "standup".to_string(),
+            weekday: Some(0),
+            date: None,
+            start_min: 540,
+            end_min: 555,
+            label: None,
+        });
+        let after = record(
+            &mut conn,
+            "set_availability",
+            |_| Ok(vec![Entity::Availability]),
+            |conn| availability::set_availability(conn, &changed),
+            |_| Vec::new(),
+        )
+        .unwrap();
+
+        undo_last(&mut conn).unwrap().unwrap();
+        assert_eq!(availability::get_availability(&conn).unwrap(), before);
+        redo(&mut conn).unwrap().unwrap();
+        assert_eq!(availability::get_availability(&conn).unwrap(), after);
+    }
+}
@@ -1,11 +1,17 @@
+use super::DbError;
+use crate::models::{MigrationDirection, MigrationReport, MigrationStep};
+use rusqlite::backup::Backup;
 use rusqlite::{params, Connection, Transaction};
 use std::collections::HashSet;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub struct Migration {
     pub id: i64,
     pub name: &'static str,
     pub sql: &'static str,
+    /// Undoes `sql`, keeping as much data as the older schema can hold. `None` for
+    /// migrations that can't be rolled back.
+    pub down: Option<&'static str>,
 }
 
 pub const MIGRATIONS: &[Migration] = &[
@@ -13,16 +19,127 @@ pub const MIGRATIONS: &[Migration] = &[
         id: 1,
         name: "init",
         sql: include_str!("../../migrations/0001_init.sql"),
+        down: None,
     },
     Migration {
         id: 2,
         name: "add_is_today",
         sql: include_str!("../../migrations/0002_add_is_today.sql"),
+        down: Some(include_str!("../../migrations/0002_add_is_today.down.sql")),
     },
     Migration {
         id: 3,
         name: "add_due",
         sql: include_str!("../../migrations/0003_add_due.sql"),
+        down: Some(include_str!("../../migrations/0003_add_due.down.sql")),
+    },
+    Migration {
+        id: 4,
+        name: "add_priority",
+        sql: include_str!("../../migrations/0004_add_priority.sql"),
+        down: Some(include_str!("../../migrations/0004_add_priority.down.sql")),
+    },
+    Migration {
+        id: 5,
+        name: "add_block_items",
+        sql: include_str!("../../migrations/0005_add_block_items.sql"),
+        down: Some(include_str!("../../migrations/0005_add_block_items.down.sql")),
+    },
+    Migration {
+        id: 6,
+        name: "block_minutes",
+        sql: include_str!("../../migrations/0006_block_minutes.sql"),
+        down: Some(include_str!("../../migrations/0006_block_minutes.down.sql")),
+    },
+    Migration {
+        id: 7,
+        name: "add_focus_sessions",
+        sql: include_str!("../../migrations/0007_add_focus_sessions.sql"),
+        down: Some(include_str!("../../migrations/0007_add_focus_sessions.down.sql")),
+    },
+    Migration {
+        id: 8,
+        name: "add_recurrence",
+        sql: include_str!("../../migrations/0008_add_recurrence.sql"),
+        down: Some(include_str!("../../migrations/0008_add_recurrence.down.sql")),
+    },
+    Migration {
+        id: 9,
+        name: "add_parent_id",
+        sql: include_str!("../../migrations/0009_add_parent_id.sql"),
+        down: Some(include_str!("../../migrations/0009_add_parent_id.down.sql")),
+    },
+    Migration {
+        id: 10,
+        name: "add_task_dependencies",
+        sql: include_str!("../../migrations/0010_add_task_dependencies.sql"),
+        down: Some(include_str!("../../migrations/0010_add_task_dependencies.down.sql")),
+    },
+    Migration {
+        id: 11,
+        name: "add_projects",
+        sql: include_str!("../../migrations/0011_add_projects.sql"),
+        down: Some(include_str!("../../migrations/0011_add_projects.down.sql")),
+    },
+    Migration {
+        id: 12,
+        name: "add_tags",
+        sql: include_str!("../../migrations/0012_add_tags.sql"),
+        down: Some(include_str!("../../migrations/0012_add_tags.down.sql")),
+    },
+    Migration {
+        id: 13,
+        name: "add_task_search",
+        sql: include_str!("../../migrations/0013_add_task_search.sql"),
+        down: Some(include_str!("../../migrations/0013_add_task_search.down.sql")),
+    },
+    Migration {
+        id: 14,
+        name: "add_trash",
+        sql: include_str!("../../migrations/0014_add_trash.sql"),
+        down: Some(include_str!("../../migrations/0014_add_trash.down.sql")),
+    },
+    Migration {
+        id: 15,
+        name: "add_completed_at",
+        sql: include_str!("../../migrations/0015_add_completed_at.sql"),
+        down: Some(include_str!("../../migrations/0015_add_completed_at.down.sql")),
+    },
+    Migration {
+        id: 16,
+        name: "add_task_events",
+        sql: include_str!("../../migrations/0016_add_task_events.sql"),
+        down: Some(include_str!("../../migrations/0016_add_task_events.down.sql")),
+    },
+    Migration {
+        id: 17,
+        name: "add_audit_log",
+        sql: include_str!("../../migrations/0017_add_audit_log.sql"),
+        down: Some(include_str!("../../migrations/0017_add_audit_log.down.sql")),
+    },
+    Migration {
+        id: 18,
+        name: "add_availability",
+        sql: include_str!("../../migrations/0018_add_availability.sql"),
+        down: Some(include_str!("../../migrations/0018_add_availability.down.sql")),
+    },
+    Migration {
+        id: 19,
+        name: "add_day_blocks_date_index",
+        sql: include_str!("../../migrations/0019_add_day_blocks_date_index.sql"),
+        down: Some(include_str!("../../migrations/0019_add_day_blocks_date_index.down.sql")),
+    },
+    Migration {
+        id: 20,
+        name: "add_block_version",
+        sql: include_str!("../../migrations/0020_add_block_version.sql"),
+        down: Some(include_str!("../../migrations/0020_add_block_version.down.sql")),
+    },
+    Migration {
+        id: 21,
+        name: "add_availability_history",
+        sql: include_str!("../../migrations/0021_add_availability_history.sql"),
+        down: Some(include_str!("../../migrations/0021_add_availability_history.down.sql")),
     },
 ];
 
@@ -73,7 +190,68 @@ fn baseline_if_needed(tx: &Transaction) -> rusqlite::Result<()> {
     Ok(())
 }
 
-pub fn run_migrations(conn: &mut Connection) -> rusqlite::Result<()> {
+/// FNV-1a of a migration's SQL, ignoring carriage returns so a CRLF checkout hashes the
+/// same. This only needs to notice edits, not resist tampering.
+pub fn checksum(sql: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in sql.bytes().filter(|&b| b != b'\r') {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// Refuses databases whose applied migrations don't match this build: ones it has never
+/// heard of (the database was opened by a newer build) or ones whose SQL has changed since
+/// they ran. Rows recorded before checksums existed have none and are skipped.
+fn check_drift(tx: &Transaction) -> Result<(), DbError> {
+    let mut stmt =
+        tx.prepare("SELECT id, name, checksum FROM schema_migrations ORDER BY id")?;
+    let applied = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let unknown: Vec<String> = applied
+        .iter()
+        .filter(|(id, _, _)| !MIGRATIONS.iter().any(|m| m.id == *id))
+        .map(|(id, name, _)| format!("{} ({})", id, name))
+        .collect();
+    if !unknown.is_empty() {
+        return Err(DbError::Invalid(format!(
+            "The database has migrations this version of the app doesn't know about: {}. \
+             It was probably last opened by a newer version.",
+            unknown.join(", ")
+        )));
+    }
+
+    for (id, name, recorded) in &applied {
+        let Some(migration) = MIGRATIONS.iter().find(|m| m.id == *id) else {
+            continue;
+        };
+        if let Some(recorded) = recorded {
+            if *recorded != checksum(migration.sql) {
+                return Err(DbError::Invalid(format!(
+                    "Migration {} ({}) has changed since it was applied to this database \
+                     (checksum {} was recorded, this build has {}).",
+                    id,
+                    name,
+                    recorded,
+                    checksum(migration.sql)
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Pragmas and bookkeeping every migration run needs, outside any transaction.
+fn prepare(conn: &Connection) -> Result<(), DbError> {
     // Hardening pragmas for desktop sqlite
     conn.execute_batch(
         r#"
@@ -92,34 +270,172 @@ pub fn run_migrations(conn: &mut Connection) -> rusqlite::Result<()> {
       );
     "#,
     )?;
+    let has_checksum: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM pragma_table_info('schema_migrations') WHERE name = 'checksum')",
+        [],
+        |r| r.get(0),
+    )?;
+    if !has_checksum {
+        conn.execute_batch("ALTER TABLE schema_migrations ADD COLUMN checksum TEXT")?;
+    }
+    Ok(())
+}
 
-    let tx = conn.transaction()?;
-
-    baseline_if_needed(&tx)?;
+/// Highest migration id this build knows.
+pub fn latest_version() -> i64 {
+    MIGRATIONS.iter().map(|m| m.id).max().unwrap_or(0)
+}
 
+fn applied_ids(tx: &Transaction) -> rusqlite::Result<HashSet<i64>> {
     let mut stmt = tx.prepare("SELECT id FROM schema_migrations")?;
-    let applied: HashSet<i64> = stmt
+    let applied = stmt
         .query_map([], |row| row.get::<_, i64>(0))?
         .collect::<Result<_, _>>()?;
+    Ok(applied)
+}
 
-    drop(stmt);
+/// The migrations taking a database with `applied` to `target`, in the order they run.
+fn plan(applied: &HashSet<i64>, target: i64) -> Result<Vec<MigrationStep>, DbError> {
+    if target < 1 || target > latest_version() {
+        return Err(DbError::Invalid(format!(
+            "Can't migrate to version {}; this build knows versions 1 to {}",
+            target,
+            latest_version()
+        )));
+    }
+    let mut steps: Vec<MigrationStep> = MIGRATIONS
+        .iter()
+        .filter(|m| m.id > target && applied.contains(&m.id))
+        .rev()
+        .map(|m| MigrationStep {
+            id: m.id,
+            name: m.name.to_string(),
+            direction: MigrationDirection::Down,
+        })
+        .collect();
+    steps.extend(
+        MIGRATIONS
+            .iter()
+            .filter(|m| m.id <= target && !applied.contains(&m.id))
+            .map(|m| MigrationStep {
+                id: m.id,
+                name: m.name.to_string(),
+                direction: MigrationDirection::Up,
+            }),
+    );
+    Ok(steps)
+}
 
-    for m in MIGRATIONS {
-        if applied.contains(&m.id) {
-            continue;
+fn apply(tx: &Transaction, step: &MigrationStep) -> Result<(), DbError> {
+    let m = MIGRATIONS.iter().find(|m| m.id == step.id).unwrap();
+    match step.direction {
+        MigrationDirection::Up => {
+            tx.execute_batch(m.sql)?;
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64;
+            tx.execute(
+                "INSERT INTO schema_migrations(id, name, applied_at, checksum) VALUES(?1,?2,?3,?4)",
+                params![m.id, m.name, now, checksum(m.sql)],
+            )?;
         }
-        tx.execute_batch(m.sql)?;
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs() as i64;
+        MigrationDirection::Down => {
+            let down = m.down.ok_or_else(|| {
+                DbError::Invalid(format!(
+                    "Migration {} ({}) can't be rolled back",
+                    m.id, m.name
+                ))
+            })?;
+            tx.execute_batch(down)?;
+            tx.execute("DELETE FROM schema_migrations WHERE id = ?1", params![m.id])?;
+        }
+    }
+    Ok(())
+}
+
+/// Brings the schema to migration `target`, applying missing migrations up to it and rolling
+/// back applied ones above it, all in one transaction.
+///
+/// Rollbacks run with foreign keys off, since some rebuild tables; `PRAGMA
+/// foreign_key_check` has to come back clean before anything is committed.
+pub fn migrate_to(conn: &mut Connection, target: i64) -> Result<MigrationReport, DbError> {
+    prepare(conn)?;
+    let rolls_back: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM schema_migrations WHERE id > ?1)",
+        params![target],
+        |r| r.get(0),
+    )?;
+    if rolls_back {
+        conn.execute_batch("PRAGMA foreign_keys = OFF")?;
+    }
+    let result = migrate_in_transaction(conn, target, rolls_back);
+    if rolls_back {
+        conn.execute_batch("PRAGMA foreign_keys = ON")?;
+    }
+    result
+}
+
+fn migrate_in_transaction(
+    conn: &mut Connection,
+    target: i64,
+    check_foreign_keys: bool,
+) -> Result<MigrationReport, DbError> {
+    let tx = conn.transaction()?;
+
+    baseline_if_needed(&tx)?;
+    check_drift(&tx)?;
+
+    let applied = applied_ids(&tx)?;
+    let from = applied.iter().copied().max().unwrap_or(0);
+    let steps = plan(&applied, target)?;
+    for step in &steps {
+        apply(&tx, step)?;
+    }
+
+    // Migrations applied before checksums were recorded (or baselined) can't be verified;
+    // record what this build has so later edits are caught.
+    for m in MIGRATIONS {
         tx.execute(
-            "INSERT INTO schema_migrations(id, name, applied_at) VALUES(?1,?2,?3)",
-            params![m.id, m.name, now],
+            "UPDATE schema_migrations SET checksum = ?2 WHERE id = ?1 AND checksum IS NULL",
+            params![m.id, checksum(m.sql)],
         )?;
     }
 
+    if check_foreign_keys {
+        let dangling: i64 =
+            tx.query_row("SELECT count(*) FROM pragma_foreign_key_check", [], |r| r.get(0))?;
+        if dangling > 0 {
+            return Err(DbError::Invalid(format!(
+                "Rolling back would leave {} rows pointing at missing rows",
+                dangling
+            )));
+        }
+    }
+
     tx.commit()?;
+    Ok(MigrationReport {
+        from,
+        to: target,
+        steps,
+        dry_run: false,
+    })
+}
+
+/// Reports what `migrate_to(target)` would do by running it on an in-memory copy of the
+/// database, so SQL that fails on this data fails here too. The database itself isn't
+/// touched.
+pub fn dry_run(conn: &Connection, target: i64) -> Result<MigrationReport, DbError> {
+    let mut copy = Connection::open_in_memory()?;
+    Backup::new(conn, &mut copy)?.run_to_completion(1000, Duration::ZERO, None)?;
+    let mut report = migrate_to(&mut copy, target)?;
+    report.dry_run = true;
+    Ok(report)
+}
+
+/// Applies every migration this build knows that hasn't run yet.
+pub fn run_migrations(conn: &mut Connection) -> Result<(), DbError> {
+    migrate_to(conn, latest_version())?;
     Ok(())
 }
 
@@ -153,6 +469,16 @@ mod tests {
         assert!(tables.contains(&"tasks".to_string()));
         assert!(tables.contains(&"day_blocks".to_string()));
         assert!(tables.contains(&"settings".to_string()));
+        assert!(tables.contains(&"block_items".to_string()));
+        assert!(tables.contains(&"focus_sessions".to_string()));
+        assert!(tables.contains(&"session_tasks".to_string()));
+        assert!(tables.contains(&"task_dependencies".to_string()));
+        assert!(tables.contains(&"projects".to_string()));
+        assert!(tables.contains(&"tags".to_string()));
+        assert!(tables.contains(&"task_tags".to_string()));
+        assert!(tables.contains(&"tasks_fts".to_string()));
+        assert!(tables.contains(&"task_events".to_string()));
+        assert!(tables.contains(&"audit_log".to_string()));
         assert!(tables.contains(&"schema_migrations".to_string()));
 
         // Check if all migrations were applied
@@ -165,6 +491,18 @@ mod tests {
         // Check if columns from migrations were added
         assert!(has_column(&conn, "tasks", "is_today"));
         assert!(has_column(&conn, "tasks", "due"));
+        assert!(has_column(&conn, "tasks", "priority"));
+        assert!(has_column(&conn, "tasks", "recurrence"));
+        assert!(has_column(&conn, "tasks", "parent_id"));
+        assert!(has_column(&conn, "tasks", "project_id"));
+        assert!(has_column(&conn, "tasks", "deleted_at"));
+        assert!(has_column(&conn, "tasks", "completed_at"));
+        assert!(has_column(&conn, "tasks", "archived_at"));
+        assert!(!has_column(&conn, "tasks", "project"));
+        assert!(!has_column(&conn, "tasks", "tags"));
+        assert!(has_column(&conn, "day_blocks", "kind"));
+        assert!(has_column(&conn, "day_blocks", "start_min"));
+        assert!(!has_column(&conn, "day_blocks", "start_slot"));
     }
 
     #[test]
@@ -187,8 +525,8 @@ mod tests {
         let mut conn = Connection::open_in_memory().unwrap();
         conn.execute_batch(
             "
-            CREATE TABLE tasks (id TEXT PRIMARY KEY, title TEXT);
-            CREATE TABLE day_blocks (id TEXT PRIMARY KEY);
+            CREATE TABLE tasks (id TEXT PRIMARY KEY, title TEXT NOT NULL, done INTEGER NOT NULL DEFAULT 0, est_minutes INTEGER, notes TEXT, project TEXT, tags TEXT, created_at TEXT);
+            CREATE TABLE day_blocks (id TEXT PRIMARY KEY, task_id TEXT, date TEXT NOT NULL, start_slot INTEGER NOT NULL, end_slot INTEGER NOT NULL);
             CREATE TABLE settings (key TEXT PRIMARY KEY);
             ",
         )
@@ -217,7 +555,7 @@ mod tests {
         let mut conn = Connection::open_in_memory().unwrap();
         conn.execute_batch(
             "
-            CREATE TABLE tasks (id TEXT PRIMARY KEY, title TEXT, is_today INTEGER);
+            CREATE TABLE tasks (id TEXT PRIMARY KEY, title TEXT NOT NULL, done INTEGER NOT NULL DEFAULT 0, est_minutes INTEGER, notes TEXT, project TEXT, tags TEXT, created_at TEXT, is_today INTEGER);
             ",
         )
         .unwrap();
@@ -240,4 +578,350 @@ mod tests {
         let m2_applied: i64 = conn.query_row("SELECT count(*) FROM schema_migrations WHERE id=2", [], |r| r.get(0)).unwrap();
         assert_eq!(m2_applied, 1);
     }
+
+    #[test]
+    fn test_block_slots_converted_to_minutes() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            CREATE TABLE schema_migrations (id INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at INTEGER NOT NULL);
+            ",
+        )
+        .unwrap();
+        // Bring the database up to just before the slot -> minute conversion.
+        for m in MIGRATIONS.iter().filter(|m| m.id < 6) {
+            conn.execute_batch(m.sql).unwrap();
+            conn.execute(
+                "INSERT INTO schema_migrations (id, name, applied_at) VALUES (?1, ?2, 0)",
+                params![m.id, m.name],
+            )
+            .unwrap();
+        }
+        conn.execute_batch(
+            "
+            INSERT INTO tasks (id, title) VALUES ('t1', 'Write report');
+            INSERT INTO day_blocks (id, task_id, date, start_slot, end_slot) VALUES ('b1', 't1', '2024-05-01', 36, 39);
+            ",
+        )
+        .unwrap();
+
+        run_migrations(&mut conn).unwrap();
+
+        let (start_min, length_min): (i64, i64) = conn
+            .query_row(
+                "SELECT start_min, length_min FROM day_blocks WHERE id = 'b1'",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(start_min, 9 * 60);
+        assert_eq!(length_min, 45);
+    }
+
+    #[test]
+    fn test_project_names_become_project_rows() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            CREATE TABLE schema_migrations (id INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at INTEGER NOT NULL);
+            ",
+        )
+        .unwrap();
+        for m in MIGRATIONS.iter().filter(|m| m.id < 11) {
+            conn.execute_batch(m.sql).unwrap();
+            conn.execute(
+                "INSERT INTO schema_migrations (id, name, applied_at) VALUES (?1, ?2, 0)",
+                params![m.id, m.name],
+            )
+            .unwrap();
+        }
+        conn.execute_batch(
+            "
+            INSERT INTO tasks (id, title, project) VALUES ('t1', 'Spec', 'Website');
+            INSERT INTO tasks (id, title, project) VALUES ('t2', 'Deploy', ' website ');
+            INSERT INTO tasks (id, title, project) VALUES ('t3', 'Taxes', 'Home');
+            INSERT INTO tasks (id, title, project) VALUES ('t4', 'Misc', '');
+            INSERT INTO tasks (id, title, project) VALUES ('t5', 'Loose', NULL);
+            ",
+        )
+        .unwrap();
+
+        run_migrations(&mut conn).unwrap();
+
+        let mut stmt = conn
+            .prepare("SELECT name, sort_order FROM projects ORDER BY sort_order")
+            .unwrap();
+        let projects: Vec<(String, i64)> = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(
+            projects,
+            vec![("Home".to_string(), 0), ("Website".to_string(), 1)]
+        );
+
+        let project_of = |task_id: &str| -> Option<String> {
+            conn.query_row(
+                "SELECT p.name FROM tasks t LEFT JOIN projects p ON p.id = t.project_id WHERE t.id = ?1",
+                params![task_id],
+                |r| r.get(0),
+            )
+            .unwrap()
+        };
+        assert_eq!(project_of("t1").as_deref(), Some("Website"));
+        assert_eq!(project_of("t2").as_deref(), Some("Website"));
+        assert_eq!(project_of("t3").as_deref(), Some("Home"));
+        assert_eq!(project_of("t4"), None);
+        assert_eq!(project_of("t5"), None);
+    }
+
+    #[test]
+    fn test_tag_json_becomes_tag_rows() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            CREATE TABLE schema_migrations (id INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at INTEGER NOT NULL);
+            ",
+        )
+        .unwrap();
+        for m in MIGRATIONS.iter().filter(|m| m.id < 12) {
+            conn.execute_batch(m.sql).unwrap();
+            conn.execute(
+                "INSERT INTO schema_migrations (id, name, applied_at) VALUES (?1, ?2, 0)",
+                params![m.id, m.name],
+            )
+            .unwrap();
+        }
+        conn.execute_batch(
+            r#"
+            INSERT INTO tasks (id, title, tags) VALUES ('t1', 'Spec', '["work", "Writing", "work"]');
+            INSERT INTO tasks (id, title, tags) VALUES ('t2', 'Deploy', '["WORK", null, "  "]');
+            INSERT INTO tasks (id, title, tags) VALUES ('t3', 'Taxes', '["home", "admin"');
+            INSERT INTO tasks (id, title, tags) VALUES ('t4', 'Misc', '"errands"');
+            INSERT INTO tasks (id, title, tags) VALUES ('t5', 'Loose', 'null');
+            INSERT INTO tasks (id, title, tags) VALUES ('t6', 'Empty', NULL);
+            "#,
+        )
+        .unwrap();
+
+        run_migrations(&mut conn).unwrap();
+
+        let tags_of = |task_id: &str| -> Vec<String> {
+            conn.prepare(
+                "SELECT g.name FROM task_tags tt JOIN tags g ON g.id = tt.tag_id WHERE tt.task_id = ?1 ORDER BY tt.position",
+            )
+            .unwrap()
+            .query_map(params![task_id], |r| r.get(0))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect()
+        };
+        assert_eq!(tags_of("t1"), vec!["WORK", "Writing"]);
+        assert_eq!(tags_of("t2"), vec!["WORK"]);
+        // Malformed JSON is kept as-is instead of being dropped.
+        assert_eq!(tags_of("t3"), vec![r#"["home", "admin""#]);
+        assert_eq!(tags_of("t4"), vec!["errands"]);
+        assert!(tags_of("t5").is_empty());
+        assert!(tags_of("t6").is_empty());
+
+        let tag_count: i64 = conn
+            .query_row("SELECT COUNT(*) FROM tags", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(tag_count, 4);
+    }
+
+    #[test]
+    fn test_checksums_recorded_and_backfilled() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        let recorded: String = conn
+            .query_row(
+                "SELECT checksum FROM schema_migrations WHERE id = 12",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(recorded, checksum(MIGRATIONS[11].sql));
+        assert_eq!(checksum("a;\r\nb;"), checksum("a;\nb;"));
+
+        // Databases from before checksums get this build's, then verify as usual.
+        conn.execute("UPDATE schema_migrations SET checksum = NULL", []).unwrap();
+        run_migrations(&mut conn).unwrap();
+        let missing: i64 = conn
+            .query_row(
+                "SELECT count(*) FROM schema_migrations WHERE checksum IS NULL",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap();
+        assert_eq!(missing, 0);
+    }
+
+    #[test]
+    fn test_edited_migration_is_refused() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn.execute(
+            "UPDATE schema_migrations SET checksum = '0123456789abcdef' WHERE id = 5",
+            [],
+        )
+        .unwrap();
+
+        let err = run_migrations(&mut conn).unwrap_err().to_string();
+        assert!(err.contains("Migration 5 (add_block_items) has changed"), "{}", err);
+    }
+
+    #[test]
+    fn test_unknown_migration_is_refused() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn.execute(
+            "INSERT INTO schema_migrations (id, name, applied_at, checksum)
+             VALUES (999, 'from_the_future', 0, 'x')",
+            [],
+        )
+        .unwrap();
+
+        let err = run_migrations(&mut conn).unwrap_err().to_string();
+        assert!(err.contains("999 (from_the_future)"), "{}", err);
+        assert!(err.contains("newer version"), "{}", err);
+    }
+
+    /// Tables with their columns (sorted, since rollbacks can reorder them), plus every
+    /// index, trigger and view by name.
+    fn schema(conn: &Connection) -> Vec<String> {
+        let mut stmt = conn
+            .prepare(
+                "SELECT type, name FROM sqlite_master
+                 WHERE name NOT LIKE 'sqlite_%' AND name <> 'schema_migrations'
+                 ORDER BY type, name",
+            )
+            .unwrap();
+        let objects: Vec<(String, String)> = stmt
+            .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        objects
+            .into_iter()
+            .map(|(kind, name)| {
+                if kind != "table" {
+                    return format!("{} {}", kind, name);
+                }
+                let mut stmt = conn
+                    .prepare("SELECT name FROM pragma_table_info(?1) ORDER BY name")
+                    .unwrap();
+                let columns: Vec<String> = stmt
+                    .query_map(params![name], |r| r.get(0))
+                    .unwrap()
+                    .map(|r| r.unwrap())
+                    .collect();
+                format!("table {} ({})", name, columns.join(", "))
+            })
+            .collect()
+    }
+
+    fn fresh_at(version: i64) -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate_to(&mut conn, version).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_round_trip_every_version() {
+        let latest = schema(&fresh_at(latest_version()));
+        for version in (1..latest_version()).rev() {
+            let mut conn = fresh_at(latest_version());
+            let report = migrate_to(&mut conn, version).unwrap();
+            assert_eq!(report.from, latest_version());
+            assert!(report
+                .steps
+                .iter()
+                .all(|s| s.direction == MigrationDirection::Down));
+            assert_eq!(schema(&conn), schema(&fresh_at(version)), "down to {}", version);
+
+            migrate_to(&mut conn, latest_version()).unwrap();
+            assert_eq!(schema(&conn), latest, "back up from {}", version);
+        }
+    }
+
+    #[test]
+    fn test_rollback_keeps_data() {
+        let mut conn = fresh_at(latest_version());
+        conn.execute_batch(
+            r#"
+            INSERT INTO projects (id, name) VALUES ('p1', 'Home');
+            INSERT INTO tasks (id, title, project_id) VALUES ('a', 'Paint', 'p1');
+            INSERT INTO tasks (id, title, parent_id) VALUES ('b', 'Sand', 'a');
+            INSERT INTO tasks (id, title, deleted_at) VALUES ('gone', 'Trashed', '2024-05-01T00:00:00Z');
+            INSERT INTO tags (id, name) VALUES ('g1', 'diy'), ('g2', 'Weekend');
+            INSERT INTO task_tags (task_id, tag_id, position) VALUES ('a', 'g2', 0), ('a', 'g1', 1), ('gone', 'g1', 0);
+            INSERT INTO task_dependencies (task_id, depends_on_id) VALUES ('gone', 'a');
+            INSERT INTO day_blocks (id, task_id, date, start_min, length_min) VALUES ('d1', 'a', '2024-05-01', 545, 50);
+            INSERT INTO day_blocks (id, task_id, date, start_min, length_min) VALUES ('d2', 'gone', '2024-05-01', 600, 30);
+            "#,
+        )
+        .unwrap();
+
+        migrate_to(&mut conn, 10).unwrap();
+        let (project, tags): (String, String) = conn
+            .query_row("SELECT project, tags FROM tasks WHERE id = 'a'", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((project.as_str(), tags.as_str()), ("Home", r#"["Weekend","diy"]"#));
+        let ids: Vec<String> = conn
+            .prepare("SELECT id FROM tasks ORDER BY id")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(ids, vec!["a", "b"]);
+
+        // Forward again, the data comes back in the new shape.
+        run_migrations(&mut conn).unwrap();
+        let (project, parent): (String, String) = conn
+            .query_row(
+                "SELECT p.name, b.parent_id FROM tasks a JOIN projects p ON p.id = a.project_id, tasks b
+                 WHERE a.id = 'a' AND b.id = 'b'",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((project.as_str(), parent.as_str()), ("Home", "a"));
+        let found: i64 = conn
+            .query_row("SELECT count(*) FROM tasks_fts WHERE tasks_fts MATCH 'weekend'", [], |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(found, 1);
+
+        migrate_to(&mut conn, 5).unwrap();
+        let (start_slot, end_slot): (i64, i64) = conn
+            .query_row("SELECT start_slot, end_slot FROM day_blocks WHERE id = 'd1'", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })
+            .unwrap();
+        assert_eq!((start_slot, end_slot), (36, 40));
+    }
+
+    #[test]
+    fn test_dry_run_leaves_database_alone() {
+        let mut conn = fresh_at(latest_version());
+        conn.execute("INSERT INTO tasks (id, title) VALUES ('a', 'Paint')", [])
+            .unwrap();
+
+        let report = dry_run(&conn, 14).unwrap();
+        assert!(report.dry_run);
+        let ids: Vec<i64> = report.steps.iter().map(|s| s.id).collect();
+        assert_eq!(ids, (15..=latest_version()).rev().collect::<Vec<_>>());
+        assert_eq!(schema(&conn), schema(&fresh_at(latest_version())));
+
+        assert!(dry_run(&conn, 0).is_err());
+        assert!(dry_run(&conn, latest_version() + 1).is_err());
+        assert!(migrate_to(&mut conn, latest_version()).unwrap().steps.is_empty());
+    }
 }
+
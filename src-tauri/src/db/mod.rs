@@ -1,37 +1,112 @@
+pub mod audit;
+pub mod availability;
+pub mod backup;
+pub mod blocks;
+pub mod dependencies;
+pub mod history;
 pub mod migrations;
+pub mod projects;
+pub mod purge;
+pub mod recovery;
+pub mod search;
+pub mod sessions;
+pub mod tags;
+pub mod tasks;
+#[cfg(test)]
+pub(crate) mod test_util;
 
+use crate::models::RecoveryReport;
 use rusqlite::Connection;
 use std::{fs, sync::Mutex};
 use tauri::{AppHandle, Manager};
-use chrono::Local;
+use chrono::Utc;
 
 pub struct Database(pub Mutex<Connection>);
 
-fn backup_db(db_path: &std::path::Path) -> Result<(), std::io::Error> {
-    if db_path.exists() {
-        let timestamp = Local::now().format("%Y%m%d%H%M");
-        let backup_path = db_path.with_extension(format!("backup.{}.db", timestamp));
-        fs::copy(db_path, backup_path)?;
-    }
-    Ok(())
+#[derive(Debug, thiserror::Error)]
+pub enum DbError {
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("{0}")]
+    Invalid(String),
 }
 
-pub fn init_db(handle: &AppHandle) -> Result<Database, rusqlite::Error> {
-    let app_data_dir = handle
+/// Set when startup checks fail; the app then runs in recovery mode until it's cleared.
+#[derive(Default)]
+pub struct Recovery(pub Mutex<Option<RecoveryReport>>);
+
+/// Opens, checks, backs up and migrates the app's database.
+///
+/// Nothing here panics on a bad database: if a check or step fails the app still gets a
+/// connection (in memory if the file can't be opened at all), along with a recovery report
+/// describing what went wrong. While that report is set, commands other than the recovery,
+/// backup and export ones refuse to touch the connection.
+pub fn init_db(handle: &AppHandle) -> (Database, Recovery) {
+    let opened = handle
         .path()
         .app_data_dir()
-        .expect("failed to get app data dir");
-    if !app_data_dir.exists() {
-        fs::create_dir_all(&app_data_dir).expect("failed to create app data dir");
+        .map_err(|e| format!("Couldn't find the app data directory: {}", e))
+        .and_then(|dir| {
+            fs::create_dir_all(&dir)
+                .map_err(|e| format!("Couldn't create {}: {}", dir.display(), e))?;
+            let db_path = dir.join("cadence.db");
+            let existed = db_path.exists();
+            Connection::open(&db_path)
+                .map(|conn| (conn, existed))
+                .map_err(|e| format!("Couldn't open {}: {}", db_path.display(), e))
+        });
+    let (conn, problems) = match opened {
+        Ok((mut conn, existed)) => {
+            let problems = prepare_db(&mut conn, existed);
+            (conn, problems)
+        }
+        Err(problem) => (
+            Connection::open_in_memory().expect("failed to open in-memory database"),
+            vec![problem],
+        ),
+    };
+
+    let recovery = if problems.is_empty() {
+        None
+    } else {
+        eprintln!("database needs recovery: {}", problems.join("; "));
+        Some(RecoveryReport { problems })
+    };
+    (Database(Mutex::new(conn)), Recovery(Mutex::new(recovery)))
+}
+
+/// Runs the startup checks and steps, stopping at the first that fails. Returns its
+/// problems, or nothing if the database is ready to use.
+fn prepare_db(conn: &mut Connection, existed: bool) -> Vec<String> {
+    let problems = recovery::integrity_problems(conn);
+    if !problems.is_empty() {
+        return problems;
     }
-    let db_path = app_data_dir.join("cadence.db");
 
     // Backup database before running migrations
-    backup_db(&db_path).expect("failed to backup database");
+    if existed {
+        if let Err(e) = backup::create_backup(conn, backup::LAUNCH_BACKUP) {
+            return vec![format!("Couldn't back up the database before migrating it: {}", e)];
+        }
+    }
 
-    let mut conn = Connection::open(&db_path)?;
+    if let Err(e) = migrations::run_migrations(conn) {
+        return vec![format!("Couldn't migrate the database: {}", e)];
+    }
 
-    migrations::run_migrations(&mut conn)?;
+    // Checked after migrating, since migrations clean up rows older versions left dangling.
+    let problems = recovery::foreign_key_problems(conn);
+    if !problems.is_empty() {
+        return problems;
+    }
 
-    Ok(Database(Mutex::new(conn)))
+    if let Err(e) = backup::prune_backups(conn) {
+        eprintln!("failed to prune old backups: {}", e);
+    }
+
+    // A bad retention setting shouldn't keep the app from starting.
+    if let Err(e) = tasks::purge_expired_trash(conn, Utc::now()) {
+        eprintln!("failed to purge expired trash: {}", e);
+    }
+    Vec::new()
 }
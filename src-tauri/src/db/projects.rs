@@ -0,0 +1,257 @@
+use super::DbError;
+use crate::models::Project;
+use rusqlite::{params, Connection, OptionalExtension};
+use uuid::Uuid;
+
+fn normalize_name(name: &str) -> Result<String, DbError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(DbError::Invalid("Project name can't be empty".to_string()));
+    }
+    Ok(name.to_string())
+}
+
+/// Names are unique ignoring case; `except_id` lets a project keep its own name.
+fn ensure_name_free(conn: &Connection, name: &str, except_id: Option<&str>) -> Result<(), DbError> {
+    let taken: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM projects WHERE name = ?1 COLLATE NOCASE AND id IS NOT ?2)",
+        params![name, except_id],
+        |r| r.get(0),
+    )?;
+    if taken {
+        return Err(DbError::Invalid(format!(
+            "A project named \"{}\" already exists",
+            name
+        )));
+    }
+    Ok(())
+}
+
+pub fn get_project(conn: &Connection, id: &str) -> Result<Project, DbError> {
+    conn.query_row(
+        "SELECT id, name, color, archived, sort_order FROM projects WHERE id = ?1",
+        params![id],
+        |row| {
+            Ok(Project {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                color: row.get(2)?,
+                archived: row.get(3)?,
+                sort_order: row.get(4)?,
+            })
+        },
+    )
+    .optional()?
+    .ok_or_else(|| DbError::Invalid(format!("Project not found: {}", id)))
+}
+
+pub fn list_projects(conn: &Connection, include_archived: bool) -> Result<Vec<Project>, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name, color, archived, sort_order FROM projects
+         WHERE ?1 OR archived = 0
+         ORDER BY sort_order, name COLLATE NOCASE",
+    )?;
+    let project_iter = stmt.query_map(params![include_archived], |row| {
+        Ok(Project {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            color: row.get(2)?,
+            archived: row.get(3)?,
+            sort_order: row.get(4)?,
+        })
+    })?;
+
+    let mut projects = Vec::new();
+    for project in project_iter {
+        projects.push(project?);
+    }
+    Ok(projects)
+}
+
+/// Creates a project at the end of the sort order.
+pub fn create_project(
+    conn: &Connection,
+    name: &str,
+    color: Option<String>,
+) -> Result<Project, DbError> {
+    let name = normalize_name(name)?;
+    ensure_name_free(conn, &name, None)?;
+    let sort_order: i32 = conn.query_row(
+        "SELECT COALESCE(MAX(sort_order) + 1, 0) FROM projects",
+        [],
+        |r| r.get(0),
+    )?;
+    let project = Project {
+        id: Uuid::new_v4().to_string(),
+        name,
+        color,
+        archived: false,
+        sort_order,
+    };
+    conn.execute(
+        "INSERT INTO projects (id, name, color, archived, sort_order) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            project.id,
+            project.name,
+            project.color,
+            project.archived,
+            project.sort_order
+        ],
+    )?;
+    Ok(project)
+}
+
+pub fn update_project(conn: &Connection, project: &Project) -> Result<Project, DbError> {
+    let name = normalize_name(&project.name)?;
+    ensure_name_free(conn, &name, Some(&project.id))?;
+    let changed = conn.execute(
+        "UPDATE projects SET name = ?2, color = ?3, archived = ?4, sort_order = ?5 WHERE id = ?1",
+        params![
+            project.id,
+            name,
+            project.color,
+            project.archived,
+            project.sort_order
+        ],
+    )?;
+    if changed == 0 {
+        return Err(DbError::Invalid(format!(
+            "Project not found: {}",
+            project.id
+        )));
+    }
+    get_project(conn, &project.id)
+}
+
+/// Writes a project back as-is, e.g. when undoing a change to it.
+pub fn write_project(conn: &Connection, project: &Project) -> Result<(), DbError> {
+    conn.execute(
+        "INSERT INTO projects (id, name, color, archived, sort_order) VALUES (?1, ?2, ?3, ?4, ?5)
+         ON CONFLICT (id) DO UPDATE SET
+             name = excluded.name, color = excluded.color,
+             archived = excluded.archived, sort_order = excluded.sort_order",
+        params![
+            project.id,
+            project.name,
+            project.color,
+            project.archived,
+            project.sort_order
+        ],
+    )?;
+    Ok(())
+}
+
+/// Tasks hold the project id, so a rename shows up on every task immediately.
+pub fn rename_project(conn: &Connection, id: &str, name: &str) -> Result<Project, DbError> {
+    let project = get_project(conn, id)?;
+    update_project(
+        conn,
+        &Project {
+            name: name.to_string(),
+            ..project
+        },
+    )
+}
+
+/// Deletes a project; its tasks are kept and become unassigned.
+pub fn delete_project(conn: &Connection, id: &str) -> Result<(), DbError> {
+    conn.execute("DELETE FROM projects WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations::run_migrations;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_create_and_list_projects() {
+        let conn = setup();
+        let home = create_project(&conn, "  Home ", Some("#22c55e".to_string())).unwrap();
+        let work = create_project(&conn, "Work", None).unwrap();
+        assert_eq!(home.name, "Home");
+        assert_eq!((home.sort_order, work.sort_order), (0, 1));
+
+        update_project(
+            &conn,
+            &Project {
+                archived: true,
+                ..work.clone()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(list_projects(&conn, false).unwrap(), vec![home.clone()]);
+        assert_eq!(list_projects(&conn, true).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_names_unique_ignoring_case() {
+        let conn = setup();
+        let work = create_project(&conn, "Work", None).unwrap();
+        assert!(matches!(
+            create_project(&conn, "work", None),
+            Err(DbError::Invalid(_))
+        ));
+        assert!(matches!(
+            create_project(&conn, "   ", None),
+            Err(DbError::Invalid(_))
+        ));
+        // Changing only the case of its own name is fine.
+        assert_eq!(
+            rename_project(&conn, &work.id, "WORK").unwrap().name,
+            "WORK"
+        );
+    }
+
+    #[test]
+    fn test_rename_applies_to_all_tasks() {
+        let conn = setup();
+        let project = create_project(&conn, "Websit", None).unwrap();
+        for id in ["t1", "t2"] {
+            conn.execute(
+                "INSERT INTO tasks (id, title, est_minutes, project_id) VALUES (?1, 'Task', 30, ?2)",
+                params![id, project.id],
+            )
+            .unwrap();
+        }
+
+        rename_project(&conn, &project.id, "Website").unwrap();
+
+        let names: Vec<String> = conn
+            .prepare("SELECT p.name FROM tasks t JOIN projects p ON p.id = t.project_id")
+            .unwrap()
+            .query_map([], |r| r.get(0))
+            .unwrap()
+            .map(|r| r.unwrap())
+            .collect();
+        assert_eq!(names, vec!["Website", "Website"]);
+    }
+
+    #[test]
+    fn test_delete_project_unassigns_tasks() {
+        let conn = setup();
+        let project = create_project(&conn, "Home", None).unwrap();
+        conn.execute(
+            "INSERT INTO tasks (id, title, est_minutes, project_id) VALUES ('t1', 'Task', 30, ?1)",
+            params![project.id],
+        )
+        .unwrap();
+
+        delete_project(&conn, &project.id).unwrap();
+
+        let project_id: Option<String> = conn
+            .query_row("SELECT project_id FROM tasks WHERE id = 't1'", [], |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert_eq!(project_id, None);
+        assert!(get_project(&conn, &project.id).is_err());
+    }
+}
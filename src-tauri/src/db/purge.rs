@@ -0,0 +1,122 @@
+//! Wiping all user data.
+
+use super::{audit, availability, backup, DbError};
+use crate::models::PurgeSummary;
+use rusqlite::Connection;
+
+/// Deletes every task, plan, project, tag, focus session, blocked period and history event,
+/// and puts the weekly hours back to their defaults. The schema, the settings and the audit
+/// log stay.
+///
+/// A copy of the database is written and verified first; if that fails nothing is deleted.
+/// The returned summary is also recorded in the audit log, in the same transaction.
+pub fn purge_all_data(conn: &mut Connection) -> Result<PurgeSummary, DbError> {
+    let backup = backup::create_backup(conn, "purge")?;
+
+    let tx = conn.transaction()?;
+    // day_blocks.task_id has no ON DELETE action, so plans go before tasks. Everything
+    // hanging off tasks and sessions (items, tags, dependencies, links) cascades.
+    let day_blocks = tx.execute("DELETE FROM day_blocks", [])?;
+    let focus_sessions = tx.execute("DELETE FROM focus_sessions", [])?;
+    let tasks = tx.execute("DELETE FROM tasks", [])?;
+    let tags = tx.execute("DELETE FROM tags", [])?;
+    let projects = tx.execute("DELETE FROM projects", [])?;
+    let blocked_periods = availability::reset_availability(&tx)?;
+    // Snapshots in the history would bring the data back on undo.
+    let history_events = tx.execute("DELETE FROM task_events", [])?;
+
+    let summary = PurgeSummary {
+        backup_id: backup.id,
+        tasks,
+        day_blocks,
+        projects,
+        tags,
+        focus_sessions,
+        blocked_periods,
+        history_events,
+    };
+    audit::record(&tx, "purge_all_data", &summary)?;
+    tx.commit()?;
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations::run_migrations;
+    use crate::db::test_util::{task, temp_dir};
+    use crate::db::{projects, tasks};
+
+    fn count(conn: &Connection, table: &str) -> i64 {
+        conn.query_row(&format!("SELECT count(*) FROM {}", table), [], |r| r.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_purge_backs_up_then_empties_tables() {
+        let dir = temp_dir("purge");
+        let mut conn = Connection::open(dir.join("cadence.db")).unwrap();
+        run_migrations(&mut conn).unwrap();
+        let project = projects::create_project(&conn, "Home", None).unwrap();
+        let mut a = task("a");
+        a.project_id = Some(project.id);
+        tasks::insert_task(&conn, &a).unwrap();
+        tasks::insert_task(&conn, &task("b")).unwrap();
+        conn.execute(
+            "INSERT INTO settings (key, value) VALUES ('apiKey', 'k')",
+            [],
+        )
+        .unwrap();
+        let defaults = availability::get_availability(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO blocked_periods (id, weekday, start_min, end_min)
+             VALUES ('p', 0, 600, 660)",
+            [],
+        )
+        .unwrap();
+        conn.execute("UPDATE availability_days SET working = 1", []).unwrap();
+
+        let summary = purge_all_data(&mut conn).unwrap();
+        assert_eq!((summary.tasks, summary.projects, summary.tags), (2, 1, 1));
+        assert_eq!(summary.blocked_periods, 1);
+        assert_eq!(availability::get_availability(&conn).unwrap(), defaults);
+
+        for table in [
+            "tasks",
+            "projects",
+            "tags",
+            "task_tags",
+            "tasks_fts",
+            "day_blocks",
+        ] {
+            assert_eq!(count(&conn, table), 0, "{}", table);
+        }
+        assert_eq!(count(&conn, "settings"), 1);
+        // The schema is intact, so the app keeps working without a restart.
+        tasks::insert_task(&conn, &task("c")).unwrap();
+
+        let backup = Connection::open(dir.join(&summary.backup_id)).unwrap();
+        assert_eq!(count(&backup, "tasks"), 2);
+
+        let log = audit::list_entries(&conn, 10).unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].action, "purge_all_data");
+        let logged: PurgeSummary = serde_json::from_value(log[0].detail.clone().unwrap()).unwrap();
+        assert_eq!(logged, summary);
+
+        drop(backup);
+        drop(conn);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_purge_refuses_without_backup() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        tasks::insert_task(&conn, &task("a")).unwrap();
+
+        assert!(purge_all_data(&mut conn).is_err());
+        assert_eq!(count(&conn, "tasks"), 1);
+        assert_eq!(count(&conn, "audit_log"), 0);
+    }
+}
@@ -0,0 +1,287 @@
+//! Startup health checks and the recovery mode the app falls back to when they fail.
+//!
+//! In recovery mode the database is left as found. The user can either restore the newest
+//! backup that passes the same checks, or export whatever rows can still be read.
+
+use super::{audit, backup, migrations, DbError};
+use crate::models::{BackupInfo, SalvageReport, SalvagedTable};
+use chrono::Local;
+use rusqlite::types::ValueRef;
+use rusqlite::Connection;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Tables holding the user's data, in the order `export_salvage` writes them. Settings are
+/// left out since they hold the API key.
+const SALVAGE_TABLES: &[&str] = &[
+    "projects",
+    "tasks",
+    "tags",
+    "task_tags",
+    "task_dependencies",
+    "day_blocks",
+    "block_items",
+    "focus_sessions",
+    "session_tasks",
+    "availability_days",
+    "blocked_periods",
+];
+
+fn pragma_rows(conn: &Connection, pragma: &str) -> rusqlite::Result<Vec<Vec<String>>> {
+    let mut stmt = conn.prepare(pragma)?;
+    let columns = stmt.column_count();
+    let rows = stmt
+        .query_map([], |row| {
+            (0..columns)
+                .map(|i| {
+                    Ok(match row.get_ref(i)? {
+                        ValueRef::Null => String::new(),
+                        ValueRef::Integer(n) => n.to_string(),
+                        ValueRef::Real(n) => n.to_string(),
+                        ValueRef::Text(t) | ValueRef::Blob(t) => {
+                            String::from_utf8_lossy(t).into_owned()
+                        }
+                    })
+                })
+                .collect()
+        })?
+        .collect::<Result<_, _>>()?;
+    Ok(rows)
+}
+
+/// What `PRAGMA integrity_check` reports, or why it couldn't run. Empty when healthy.
+pub fn integrity_problems(conn: &Connection) -> Vec<String> {
+    match pragma_rows(conn, "PRAGMA integrity_check") {
+        Ok(rows) => rows
+            .into_iter()
+            .filter_map(|row| row.into_iter().next())
+            .filter(|message| message != "ok")
+            .collect(),
+        Err(e) => vec![format!("Integrity check failed to run: {}", e)],
+    }
+}
+
+/// Rows `PRAGMA foreign_key_check` finds pointing at rows that don't exist. Empty when
+/// healthy.
+pub fn foreign_key_problems(conn: &Connection) -> Vec<String> {
+    match pragma_rows(conn, "PRAGMA foreign_key_check") {
+        Ok(rows) => rows
+            .into_iter()
+            .map(|row| {
+                format!(
+                    "{} row {} points at a missing {} row",
+                    row[0], row[1], row[2]
+                )
+            })
+            .collect(),
+        Err(e) => vec![format!("Foreign key check failed to run: {}", e)],
+    }
+}
+
+/// Whether the file at `path` is a Cadence database that passes both checks. They run on a
+/// scratch copy so the backup itself isn't touched.
+fn is_good(path: &Path) -> bool {
+    let Ok(scratch) = backup::ScratchCopy::new(path) else {
+        return false;
+    };
+    let Ok(conn) = Connection::open(scratch.path()) else {
+        return false;
+    };
+    let has_tasks = conn
+        .query_row(
+            "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'tasks')",
+            [],
+            |r| r.get::<_, bool>(0),
+        )
+        .unwrap_or(false);
+    has_tasks && integrity_problems(&conn).is_empty() && foreign_key_problems(&conn).is_empty()
+}
+
+/// Moves `path` and its WAL and shared-memory files to `to`, keeping the suffixes.
+fn move_database(path: &Path, to: &Path) -> Result<(), DbError> {
+    for suffix in ["", "-wal", "-shm"] {
+        let from = PathBuf::from(format!("{}{}", path.display(), suffix));
+        if from.exists() {
+            let dest = PathBuf::from(format!("{}{}", to.display(), suffix));
+            fs::rename(&from, &dest).map_err(|e| DbError::Invalid(e.to_string()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Puts the newest good backup in place of the open database and reopens it, migrated to
+/// the current schema. The damaged files are kept as a backup of kind `corrupt`.
+pub fn restore_latest_backup(conn: &mut Connection) -> Result<BackupInfo, DbError> {
+    let db_path = backup::database_path(conn)?;
+    let latest = backup::list_backups(conn)?
+        .into_iter()
+        .filter(|b| b.kind != "corrupt")
+        .find(|b| is_good(&db_path.with_file_name(&b.id)))
+        .ok_or_else(|| DbError::Invalid("No backup passes the integrity check".to_string()))?;
+
+    // Close the damaged database before moving its files.
+    drop(std::mem::replace(conn, Connection::open_in_memory()?));
+    let corrupt = backup::new_backup_path(&db_path, "corrupt");
+    move_database(&db_path, &corrupt)?;
+    fs::copy(db_path.with_file_name(&latest.id), &db_path)
+        .map_err(|e| DbError::Invalid(e.to_string()))?;
+
+    *conn = Connection::open(&db_path)?;
+    migrations::run_migrations(conn)?;
+    audit::record(
+        conn,
+        "restore_backup",
+        &serde_json::json!({
+            "restored": latest.id,
+            "corrupt": corrupt.file_name().map(|n| n.to_string_lossy()),
+        }),
+    )?;
+    Ok(latest)
+}
+
+/// Reads rows from `table` until the end or the first error.
+fn salvage_table(
+    conn: &Connection,
+    table: &str,
+) -> (
+    Vec<serde_json::Map<String, serde_json::Value>>,
+    Option<String>,
+) {
+    let mut rows = Vec::new();
+    let result = (|| {
+        let mut stmt = conn.prepare(&format!("SELECT * FROM {}", table))?;
+        let columns: Vec<String> = stmt.column_names().into_iter().map(String::from).collect();
+        let mut cursor = stmt.query([])?;
+        while let Some(row) = cursor.next()? {
+            let mut object = serde_json::Map::new();
+            for (i, column) in columns.iter().enumerate() {
+                let value = match row.get_ref(i)? {
+                    ValueRef::Null => serde_json::Value::Null,
+                    ValueRef::Integer(n) => n.into(),
+                    ValueRef::Real(n) => n.into(),
+                    ValueRef::Text(t) => String::from_utf8_lossy(t).into(),
+                    ValueRef::Blob(b) => b.to_vec().into(),
+                };
+                object.insert(column.clone(), value);
+            }
+            rows.push(object);
+        }
+        Ok::<_, rusqlite::Error>(())
+    })();
+    (rows, result.err().map(|e| e.to_string()))
+}
+
+/// Writes every row that can still be read from the user's tables to a JSON file next to
+/// the database, `cadence.salvage.<timestamp>.json`, keyed by table name.
+pub fn export_salvage(conn: &Connection) -> Result<SalvageReport, DbError> {
+    let db_path = backup::database_path(conn)?;
+    let path = db_path.with_extension(format!(
+        "salvage.{}.json",
+        Local::now().format("%Y%m%d%H%M%S%3f")
+    ));
+
+    let mut data = serde_json::Map::new();
+    let mut tables = Vec::new();
+    for table in SALVAGE_TABLES {
+        let (rows, error) = salvage_table(conn, table);
+        tables.push(SalvagedTable {
+            name: table.to_string(),
+            rows: rows.len(),
+            error,
+        });
+        data.insert(
+            table.to_string(),
+            rows.into_iter().map(serde_json::Value::Object).collect(),
+        );
+    }
+    let json = serde_json::to_string_pretty(&data).map_err(|e| DbError::Invalid(e.to_string()))?;
+    fs::write(&path, json).map_err(|e| DbError::Invalid(e.to_string()))?;
+    Ok(SalvageReport {
+        path: path.display().to_string(),
+        tables,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_util::{add_project, temp_db};
+
+    #[test]
+    fn test_checks_pass_on_healthy_db() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::run_migrations(&mut conn).unwrap();
+        assert!(integrity_problems(&conn).is_empty());
+        assert!(foreign_key_problems(&conn).is_empty());
+    }
+
+    #[test]
+    fn test_foreign_key_problems_reported() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrations::run_migrations(&mut conn).unwrap();
+        conn.execute_batch(
+            "PRAGMA foreign_keys = OFF;
+             INSERT INTO task_dependencies (task_id, depends_on_id) VALUES ('a', 'b');",
+        )
+        .unwrap();
+        let problems = foreign_key_problems(&conn);
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("task_dependencies row"));
+    }
+
+    #[test]
+    fn test_restore_latest_good_backup() {
+        let (dir, conn) = temp_db("recovery");
+        add_project(&conn, "Home");
+        let good = backup::create_backup(&conn, "manual").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        // A newer backup that's damaged is passed over.
+        fs::write(
+            dir.join("cadence.manual.29990101000000000.db"),
+            b"not a database",
+        )
+        .unwrap();
+
+        drop(conn);
+        fs::write(dir.join("cadence.db"), vec![7u8; 8192]).unwrap();
+        let _ = fs::remove_file(dir.join("cadence.db-wal"));
+        let mut conn = Connection::open(dir.join("cadence.db")).unwrap();
+        assert!(!integrity_problems(&conn).is_empty());
+
+        let restored = restore_latest_backup(&mut conn).unwrap();
+        assert_eq!(restored.id, good.id);
+        let name: String = conn
+            .query_row("SELECT name FROM projects", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(name, "Home");
+        assert!(backup::list_backups(&conn)
+            .unwrap()
+            .iter()
+            .any(|b| b.kind == "corrupt"));
+
+        drop(conn);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_export_salvage() {
+        let (dir, conn) = temp_db("recovery");
+        add_project(&conn, "Home");
+        conn.execute(
+            "INSERT INTO settings (key, value) VALUES ('apiKey', 'secret')",
+            [],
+        )
+        .unwrap();
+
+        let report = export_salvage(&conn).unwrap();
+        let projects = report.tables.iter().find(|t| t.name == "projects").unwrap();
+        assert_eq!((projects.rows, projects.error.as_deref()), (1, None));
+        let json = fs::read_to_string(&report.path).unwrap();
+        let data: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(data["projects"][0]["name"], "Home");
+        assert!(!json.contains("secret"));
+
+        drop(conn);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+use super::{tasks, DbError};
+use crate::models::SearchResult;
+use rusqlite::{params, Connection};
+
+pub const DEFAULT_LIMIT: u32 = 20;
+const MAX_LIMIT: u32 = 100;
+
+/// Turns free text into an FTS5 query: every word must match, as a prefix, anywhere in
+/// the indexed columns. Quoting each word keeps FTS5 syntax (`-`, `:`, `NEAR`, ...) in
+/// user input from being interpreted or rejected.
+pub fn fts_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|word| word.replace('"', ""))
+        .filter(|word| !word.is_empty())
+        .map(|word| format!("\"{}\"*", word))
+        .collect();
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Tasks matching `query`, best match first. Title hits weigh most, then tags and project,
+/// then notes.
+pub fn search_tasks(
+    conn: &Connection,
+    query: &str,
+    limit: u32,
+) -> Result<Vec<SearchResult>, DbError> {
+    let Some(fts_query) = fts_query(query) else {
+        return Ok(Vec::new());
+    };
+    let limit = limit.clamp(1, MAX_LIMIT);
+
+    let mut stmt = conn.prepare(
+        "SELECT task_id,
+                snippet(tasks_fts, -1, '<mark>', '</mark>', '…', 12),
+                -bm25(tasks_fts, 0.0, 10.0, 1.0, 3.0, 5.0) AS score
+         FROM tasks_fts
+         WHERE tasks_fts MATCH ?1
+           AND task_id IN (SELECT id FROM tasks WHERE deleted_at IS NULL)
+         ORDER BY score DESC, task_id
+         LIMIT ?2",
+    )?;
+    let hits = stmt
+        .query_map(params![fts_query, limit], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, f64>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let ids: Vec<String> = hits.iter().map(|(id, _, _)| id.clone()).collect();
+    let mut found = tasks::tasks_by_ids(conn, &ids)?;
+    Ok(hits
+        .into_iter()
+        .filter_map(|(id, snippet, score)| {
+            found.remove(&id).map(|task| SearchResult {
+                task,
+                snippet,
+                score,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations::run_migrations;
+    use crate::db::{projects, tags};
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO tasks (id, title, est_minutes, notes) VALUES ('t1', 'Write quarterly report', 60, 'Include the budget numbers');
+            INSERT INTO tasks (id, title, est_minutes, notes) VALUES ('t2', 'Email Sam about the budget', 10, NULL);
+            INSERT INTO tasks (id, title, est_minutes, notes) VALUES ('t3', 'Book dentist', 5, 'Ask about the report');
+            ",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn ids(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(|r| r.task.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_title_matches_rank_first() {
+        let conn = setup();
+        let results = search_tasks(&conn, "budget", DEFAULT_LIMIT).unwrap();
+        assert_eq!(ids(&results), vec!["t2", "t1"]);
+        assert!(results[0].score > results[1].score);
+        assert_eq!(
+            results[0].snippet,
+            "Email Sam about the <mark>budget</mark>"
+        );
+    }
+
+    #[test]
+    fn test_prefix_and_all_words() {
+        let conn = setup();
+        assert_eq!(
+            ids(&search_tasks(&conn, "quart rep", DEFAULT_LIMIT).unwrap()),
+            vec!["t1"]
+        );
+        assert!(search_tasks(&conn, "quarterly dentist", DEFAULT_LIMIT)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_fts_syntax_in_input_is_literal() {
+        let conn = setup();
+        assert!(search_tasks(&conn, "   ", DEFAULT_LIMIT)
+            .unwrap()
+            .is_empty());
+        assert!(search_tasks(&conn, "\"", DEFAULT_LIMIT).unwrap().is_empty());
+        assert!(search_tasks(&conn, "title: NEAR( -report", DEFAULT_LIMIT).is_ok());
+    }
+
+    #[test]
+    fn test_index_follows_edits_tags_and_projects() {
+        let conn = setup();
+        tags::set_task_tags(&conn, "t3", &["health".to_string()]).unwrap();
+        let project = projects::create_project(&conn, "Errands", None).unwrap();
+        conn.execute(
+            "UPDATE tasks SET project_id = ?1 WHERE id = 't3'",
+            params![project.id],
+        )
+        .unwrap();
+
+        assert_eq!(ids(&search_tasks(&conn, "health", 5).unwrap()), vec!["t3"]);
+        assert_eq!(ids(&search_tasks(&conn, "errands", 5).unwrap()), vec!["t3"]);
+
+        projects::rename_project(&conn, &project.id, "Chores").unwrap();
+        assert!(search_tasks(&conn, "errands", 5).unwrap().is_empty());
+        assert_eq!(ids(&search_tasks(&conn, "chores", 5).unwrap()), vec!["t3"]);
+
+        conn.execute(
+            "UPDATE tasks SET title = 'Book hygienist' WHERE id = 't3'",
+            [],
+        )
+        .unwrap();
+        assert!(search_tasks(&conn, "dentist", 5).unwrap().is_empty());
+
+        conn.execute("DELETE FROM tasks WHERE id = 't3'", [])
+            .unwrap();
+        assert!(search_tasks(&conn, "hygienist", 5).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_limit() {
+        let conn = setup();
+        assert_eq!(search_tasks(&conn, "the", 1).unwrap().len(), 1);
+    }
+}
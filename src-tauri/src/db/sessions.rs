@@ -0,0 +1,308 @@
+use super::DbError;
+use crate::models::{FocusSession, NewFocusSession, SessionRange};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+
+/// Parses an RFC 3339 timestamp and re-renders it in UTC so stored values compare as strings.
+fn normalize_timestamp(value: &str) -> Result<String, DbError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| {
+            t.with_timezone(&Utc)
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+        })
+        .map_err(|_| DbError::Invalid(format!("invalid timestamp: {}", value)))
+}
+
+pub fn insert_session(
+    conn: &mut Connection,
+    session: &NewFocusSession,
+) -> Result<FocusSession, DbError> {
+    if session.kind != "focus" && session.kind != "break" {
+        return Err(DbError::Invalid(format!(
+            "unknown session kind: {}",
+            session.kind
+        )));
+    }
+    let started_at = normalize_timestamp(&session.started_at)?;
+    let ended_at = normalize_timestamp(&session.ended_at)?;
+    if ended_at < started_at {
+        return Err(DbError::Invalid(
+            "session ends before it starts".to_string(),
+        ));
+    }
+
+    let tx = conn.savepoint()?;
+    tx.execute(
+        "INSERT INTO focus_sessions (kind, started_at, ended_at, minutes, completed) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![session.kind, started_at, ended_at, session.minutes, session.completed],
+    )?;
+    let id = tx.last_insert_rowid();
+    for task_id in &session.task_ids {
+        tx.execute(
+            "INSERT OR IGNORE INTO session_tasks (session_id, task_id) VALUES (?1, ?2)",
+            params![id, task_id],
+        )?;
+    }
+    tx.commit()?;
+
+    Ok(FocusSession {
+        id,
+        kind: session.kind.clone(),
+        started_at,
+        ended_at,
+        minutes: session.minutes,
+        completed: session.completed,
+        task_ids: session.task_ids.clone(),
+    })
+}
+
+/// Sessions that started within `[range.start, range.end)`, oldest first.
+pub fn list_sessions(
+    conn: &Connection,
+    range: &SessionRange,
+) -> Result<Vec<FocusSession>, DbError> {
+    let start = normalize_timestamp(&range.start)?;
+    let end = normalize_timestamp(&range.end)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT st.session_id, st.task_id
+         FROM session_tasks st
+         JOIN focus_sessions s ON s.id = st.session_id
+         WHERE s.started_at >= ?1 AND s.started_at < ?2",
+    )?;
+    let link_iter = stmt.query_map(params![start, end], |row| {
+        Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+    })?;
+    let mut task_ids: HashMap<i64, Vec<String>> = HashMap::new();
+    for link in link_iter {
+        let (session_id, task_id) = link?;
+        task_ids.entry(session_id).or_default().push(task_id);
+    }
+
+    let mut stmt = conn.prepare(
+        "SELECT id, kind, started_at, ended_at, minutes, completed
+         FROM focus_sessions
+         WHERE started_at >= ?1 AND started_at < ?2
+         ORDER BY started_at, id",
+    )?;
+    let session_iter = stmt.query_map(params![start, end], |row| {
+        Ok(FocusSession {
+            id: row.get(0)?,
+            kind: row.get(1)?,
+            started_at: row.get(2)?,
+            ended_at: row.get(3)?,
+            minutes: row.get(4)?,
+            completed: row.get(5)?,
+            task_ids: Vec::new(),
+        })
+    })?;
+
+    let mut sessions = Vec::new();
+    for session in session_iter {
+        let mut session = session?;
+        session.task_ids = task_ids.remove(&session.id).unwrap_or_default();
+        sessions.push(session);
+    }
+    Ok(sessions)
+}
+
+pub fn get_session(conn: &Connection, id: i64) -> Result<Option<FocusSession>, DbError> {
+    let session = conn
+        .query_row(
+            "SELECT id, kind, started_at, ended_at, minutes, completed FROM focus_sessions WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok(FocusSession {
+                    id: row.get(0)?,
+                    kind: row.get(1)?,
+                    started_at: row.get(2)?,
+                    ended_at: row.get(3)?,
+                    minutes: row.get(4)?,
+                    completed: row.get(5)?,
+                    task_ids: Vec::new(),
+                })
+            },
+        )
+        .optional()?;
+    let Some(mut session) = session else {
+        return Ok(None);
+    };
+    let mut stmt =
+        conn.prepare("SELECT task_id FROM session_tasks WHERE session_id = ?1 ORDER BY rowid")?;
+    session.task_ids = stmt
+        .query_map(params![id], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+    Ok(Some(session))
+}
+
+/// Writes a session back with its original id, e.g. when undoing its deletion. Links to
+/// tasks that no longer exist are dropped.
+pub fn write_session(conn: &Connection, session: &FocusSession) -> Result<(), DbError> {
+    conn.execute(
+        "INSERT OR REPLACE INTO focus_sessions (id, kind, started_at, ended_at, minutes, completed) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            session.id,
+            session.kind,
+            session.started_at,
+            session.ended_at,
+            session.minutes,
+            session.completed
+        ],
+    )?;
+    conn.execute(
+        "DELETE FROM session_tasks WHERE session_id = ?1",
+        params![session.id],
+    )?;
+    for task_id in &session.task_ids {
+        conn.execute(
+            "INSERT OR IGNORE INTO session_tasks (session_id, task_id)
+             SELECT ?1, ?2 WHERE EXISTS (SELECT 1 FROM tasks WHERE id = ?2)",
+            params![session.id, task_id],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn delete_session(conn: &Connection, id: i64) -> Result<(), DbError> {
+    conn.execute("DELETE FROM focus_sessions WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations::run_migrations;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO tasks (id, title) VALUES ('t1', 'Write report');
+            INSERT INTO tasks (id, title) VALUES ('t2', 'Reply to email');
+            ",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn session(started_at: &str, ended_at: &str, task_ids: &[&str]) -> NewFocusSession {
+        NewFocusSession {
+            kind: "focus".to_string(),
+            started_at: started_at.to_string(),
+            ended_at: ended_at.to_string(),
+            minutes: 25,
+            completed: true,
+            task_ids: task_ids.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn day(date: &str) -> SessionRange {
+        SessionRange {
+            start: format!("{}T00:00:00Z", date),
+            end: format!("{}T23:59:59.999Z", date),
+        }
+    }
+
+    #[test]
+    fn test_log_and_query_sessions() {
+        let mut conn = setup();
+        insert_session(
+            &mut conn,
+            &session(
+                "2024-05-01T09:00:00Z",
+                "2024-05-01T09:25:00Z",
+                &["t1", "t2"],
+            ),
+        )
+        .unwrap();
+        insert_session(
+            &mut conn,
+            &session("2024-05-02T09:00:00Z", "2024-05-02T09:25:00Z", &[]),
+        )
+        .unwrap();
+
+        let sessions = list_sessions(&conn, &day("2024-05-01")).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].started_at, "2024-05-01T09:00:00.000Z");
+        assert_eq!(sessions[0].ended_at, "2024-05-01T09:25:00.000Z");
+        let mut task_ids = sessions[0].task_ids.clone();
+        task_ids.sort();
+        assert_eq!(task_ids, vec!["t1", "t2"]);
+    }
+
+    #[test]
+    fn test_timestamps_normalized_to_utc() {
+        let mut conn = setup();
+        let logged = insert_session(
+            &mut conn,
+            &session(
+                "2024-05-01T11:00:00+02:00",
+                "2024-05-01T11:25:00+02:00",
+                &[],
+            ),
+        )
+        .unwrap();
+        assert_eq!(logged.started_at, "2024-05-01T09:00:00.000Z");
+
+        let sessions = list_sessions(&conn, &day("2024-05-01")).unwrap();
+        assert_eq!(sessions.len(), 1);
+    }
+
+    #[test]
+    fn test_rejects_invalid_sessions() {
+        let mut conn = setup();
+        assert!(
+            insert_session(&mut conn, &session("9:00 AM", "2024-05-01T09:25:00Z", &[])).is_err()
+        );
+        assert!(insert_session(
+            &mut conn,
+            &session("2024-05-01T09:25:00Z", "2024-05-01T09:00:00Z", &[])
+        )
+        .is_err());
+
+        let mut wrong_kind = session("2024-05-01T09:00:00Z", "2024-05-01T09:25:00Z", &[]);
+        wrong_kind.kind = "nap".to_string();
+        assert!(insert_session(&mut conn, &wrong_kind).is_err());
+    }
+
+    #[test]
+    fn test_delete_session_removes_task_links() {
+        let mut conn = setup();
+        let logged = insert_session(
+            &mut conn,
+            &session("2024-05-01T09:00:00Z", "2024-05-01T09:25:00Z", &["t1"]),
+        )
+        .unwrap();
+
+        delete_session(&conn, logged.id).unwrap();
+
+        assert!(list_sessions(&conn, &day("2024-05-01")).unwrap().is_empty());
+        let links: i64 = conn
+            .query_row("SELECT COUNT(*) FROM session_tasks", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(links, 0);
+    }
+
+    #[test]
+    fn test_deleting_task_keeps_session() {
+        let mut conn = setup();
+        insert_session(
+            &mut conn,
+            &session(
+                "2024-05-01T09:00:00Z",
+                "2024-05-01T09:25:00Z",
+                &["t1", "t2"],
+            ),
+        )
+        .unwrap();
+
+        conn.execute("DELETE FROM tasks WHERE id = 't1'", [])
+            .unwrap();
+
+        let sessions = list_sessions(&conn, &day("2024-05-01")).unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].task_ids, vec!["t2"]);
+    }
+}
@@ -0,0 +1,303 @@
+use super::DbError;
+use crate::models::Tag;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+fn normalize_name(name: &str) -> Result<String, DbError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(DbError::Invalid("Tag name can't be empty".to_string()));
+    }
+    Ok(name.to_string())
+}
+
+fn tag_id_by_name(conn: &Connection, name: &str) -> rusqlite::Result<Option<String>> {
+    conn.query_row(
+        "SELECT id FROM tags WHERE name = ?1 COLLATE NOCASE",
+        params![name],
+        |r| r.get(0),
+    )
+    .optional()
+}
+
+/// Drops tags no task uses any more.
+pub fn prune_unused(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "DELETE FROM tags WHERE NOT EXISTS (SELECT 1 FROM task_tags tt WHERE tt.tag_id = tags.id)",
+        [],
+    )?;
+    Ok(())
+}
+
+pub fn get_tag(conn: &Connection, id: &str) -> Result<Tag, DbError> {
+    conn.query_row(
+        "SELECT g.id, g.name, COUNT(t.id)
+         FROM tags g
+         LEFT JOIN task_tags tt ON tt.tag_id = g.id
+         LEFT JOIN tasks t ON t.id = tt.task_id AND t.deleted_at IS NULL
+         WHERE g.id = ?1
+         GROUP BY g.id",
+        params![id],
+        |row| {
+            Ok(Tag {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                usage_count: row.get(2)?,
+            })
+        },
+    )
+    .optional()?
+    .ok_or_else(|| DbError::Invalid(format!("Tag not found: {}", id)))
+}
+
+/// Every tag with the number of tasks using it, most used first. Trashed tasks don't count.
+pub fn list_tags(conn: &Connection) -> Result<Vec<Tag>, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT g.id, g.name, COUNT(t.id) AS usage_count
+         FROM tags g
+         LEFT JOIN task_tags tt ON tt.tag_id = g.id
+         LEFT JOIN tasks t ON t.id = tt.task_id AND t.deleted_at IS NULL
+         GROUP BY g.id
+         ORDER BY usage_count DESC, g.name COLLATE NOCASE",
+    )?;
+    let tag_iter = stmt.query_map([], |row| {
+        Ok(Tag {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            usage_count: row.get(2)?,
+        })
+    })?;
+
+    let mut tags = Vec::new();
+    for tag in tag_iter {
+        tags.push(tag?);
+    }
+    Ok(tags)
+}
+
+fn group_by_task(
+    conn: &Connection,
+    filter: &str,
+    params: &[&dyn rusqlite::ToSql],
+) -> rusqlite::Result<HashMap<String, Vec<String>>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT tt.task_id, g.name
+         FROM task_tags tt JOIN tags g ON g.id = tt.tag_id
+         WHERE {}
+         ORDER BY tt.task_id, tt.position",
+        filter
+    ))?;
+    let link_iter = stmt.query_map(params, |row| {
+        Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+    })?;
+
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    for link in link_iter {
+        let (task_id, name) = link?;
+        tags.entry(task_id).or_default().push(name);
+    }
+    Ok(tags)
+}
+
+/// Tag names of every task, in the order they were given.
+pub fn tags_by_task(conn: &Connection) -> rusqlite::Result<HashMap<String, Vec<String>>> {
+    group_by_task(conn, "1", &[])
+}
+
+/// Like `tags_by_task`, limited to `task_ids`.
+pub fn tags_for_tasks(
+    conn: &Connection,
+    task_ids: &[String],
+) -> rusqlite::Result<HashMap<String, Vec<String>>> {
+    let ids_json = serde_json::to_string(task_ids)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    group_by_task(
+        conn,
+        "tt.task_id IN (SELECT value FROM json_each(?1))",
+        &[&ids_json],
+    )
+}
+
+/// Replaces a task's tags. Names matching an existing tag (ignoring case) reuse it, so the
+/// stored spelling wins; blank and repeated names are skipped.
+pub fn set_task_tags(conn: &Connection, task_id: &str, tags: &[String]) -> rusqlite::Result<()> {
+    conn.execute("DELETE FROM task_tags WHERE task_id = ?1", params![task_id])?;
+    let mut position = 0;
+    for name in tags.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        let tag_id = match tag_id_by_name(conn, name)? {
+            Some(id) => id,
+            None => {
+                let id = Uuid::new_v4().to_string();
+                conn.execute(
+                    "INSERT INTO tags (id, name) VALUES (?1, ?2)",
+                    params![id, name],
+                )?;
+                id
+            }
+        };
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO task_tags (task_id, tag_id, position) VALUES (?1, ?2, ?3)",
+            params![task_id, tag_id, position],
+        )?;
+        position += inserted as i64;
+    }
+    prune_unused(conn)
+}
+
+/// Renames a tag everywhere it's used. Use `merge_tags` to fold it into an existing tag.
+pub fn rename_tag(conn: &Connection, id: &str, name: &str) -> Result<Tag, DbError> {
+    let name = normalize_name(name)?;
+    if let Some(other) = tag_id_by_name(conn, &name)? {
+        if other != id {
+            return Err(DbError::Invalid(format!(
+                "A tag named \"{}\" already exists; merge the tags instead",
+                name
+            )));
+        }
+    }
+    let changed = conn.execute("UPDATE tags SET name = ?2 WHERE id = ?1", params![id, name])?;
+    if changed == 0 {
+        return Err(DbError::Invalid(format!("Tag not found: {}", id)));
+    }
+    get_tag(conn, id)
+}
+
+/// Moves every use of `source_ids` onto `target_id` and deletes the source tags.
+/// A task that already had the target keeps a single copy of it.
+pub fn merge_tags(
+    conn: &mut Connection,
+    source_ids: &[String],
+    target_id: &str,
+) -> Result<Tag, DbError> {
+    let tx = conn.savepoint()?;
+    get_tag(&tx, target_id)?;
+    for source_id in source_ids.iter().filter(|id| id.as_str() != target_id) {
+        get_tag(&tx, source_id)?;
+        tx.execute(
+            "INSERT OR IGNORE INTO task_tags (task_id, tag_id, position)
+             SELECT task_id, ?2, position FROM task_tags WHERE tag_id = ?1",
+            params![source_id, target_id],
+        )?;
+        // Links go with the tag via ON DELETE CASCADE.
+        tx.execute("DELETE FROM tags WHERE id = ?1", params![source_id])?;
+    }
+    let tag = get_tag(&tx, target_id)?;
+    tx.commit()?;
+    Ok(tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::migrations::run_migrations;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO tasks (id, title, est_minutes) VALUES ('t1', 'Write report', 30);
+            INSERT INTO tasks (id, title, est_minutes) VALUES ('t2', 'Reply to email', 15);
+            INSERT INTO tasks (id, title, est_minutes) VALUES ('t3', 'Book dentist', 5);
+            ",
+        )
+        .unwrap();
+        conn
+    }
+
+    fn names(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn tag_named(conn: &Connection, name: &str) -> Tag {
+        list_tags(conn)
+            .unwrap()
+            .into_iter()
+            .find(|t| t.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_set_tags_reuses_existing_spelling() {
+        let conn = setup();
+        set_task_tags(&conn, "t1", &names(&["Work", "writing", " ", "work"])).unwrap();
+        set_task_tags(&conn, "t2", &names(&["work"])).unwrap();
+
+        let tags = tags_by_task(&conn).unwrap();
+        assert_eq!(tags["t1"], vec!["Work", "writing"]);
+        assert_eq!(tags["t2"], vec!["Work"]);
+
+        let listed: Vec<(String, i64)> = list_tags(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.name, t.usage_count))
+            .collect();
+        assert_eq!(
+            listed,
+            vec![("Work".to_string(), 2), ("writing".to_string(), 1)]
+        );
+    }
+
+    #[test]
+    fn test_unused_tags_are_pruned() {
+        let conn = setup();
+        set_task_tags(&conn, "t1", &names(&["work"])).unwrap();
+        set_task_tags(&conn, "t1", &names(&["home"])).unwrap();
+
+        let listed: Vec<String> = list_tags(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+        assert_eq!(listed, vec!["home"]);
+    }
+
+    #[test]
+    fn test_rename_tag() {
+        let conn = setup();
+        set_task_tags(&conn, "t1", &names(&["wrk"])).unwrap();
+        set_task_tags(&conn, "t2", &names(&["home"])).unwrap();
+        let wrk = tag_named(&conn, "wrk");
+
+        assert_eq!(rename_tag(&conn, &wrk.id, "work").unwrap().name, "work");
+        assert_eq!(tags_by_task(&conn).unwrap()["t1"], vec!["work"]);
+
+        // Clashing with another tag is an error; changing only the case is not.
+        assert!(matches!(
+            rename_tag(&conn, &wrk.id, "HOME"),
+            Err(DbError::Invalid(_))
+        ));
+        assert_eq!(rename_tag(&conn, &wrk.id, "Work").unwrap().name, "Work");
+    }
+
+    #[test]
+    fn test_merge_tags() {
+        let mut conn = setup();
+        set_task_tags(&conn, "t1", &names(&["work", "job"])).unwrap();
+        set_task_tags(&conn, "t2", &names(&["job"])).unwrap();
+        set_task_tags(&conn, "t3", &names(&["office"])).unwrap();
+        let work = tag_named(&conn, "work");
+        let sources = vec![tag_named(&conn, "job").id, tag_named(&conn, "office").id];
+
+        let merged = merge_tags(&mut conn, &sources, &work.id).unwrap();
+        assert_eq!(merged.usage_count, 3);
+
+        let tags = tags_by_task(&conn).unwrap();
+        assert_eq!(tags["t1"], vec!["work"]);
+        assert_eq!(tags["t2"], vec!["work"]);
+        assert_eq!(tags["t3"], vec!["work"]);
+        assert_eq!(list_tags(&conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_deleting_task_drops_links() {
+        let conn = setup();
+        set_task_tags(&conn, "t1", &names(&["work"])).unwrap();
+        set_task_tags(&conn, "t2", &names(&["work"])).unwrap();
+        conn.execute("DELETE FROM tasks WHERE id = 't1'", [])
+            .unwrap();
+
+        assert_eq!(tag_named(&conn, "work").usage_count, 1);
+    }
+}
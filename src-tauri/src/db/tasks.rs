@@ -0,0 +1,1512 @@
+use super::{audit, search, tags, DbError};
+use crate::models::{
+    ChildPolicy, Occurrence, TagMatch, Task, TaskFilter, TaskNode, TaskPage, TaskSort, TaskSortField,
+};
+use crate::recurrence::Rule;
+use chrono::{DateTime, Duration, Local, NaiveDate, SecondsFormat, Utc};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension, Row};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+const TASK_COLUMNS: &str = "id, title, done, is_today, est_minutes, notes, project_id, due, priority, created_at, recurrence, parent_id, deleted_at, completed_at, archived_at,
+     EXISTS(
+         SELECT 1 FROM task_dependencies d JOIN tasks p ON p.id = d.depends_on_id
+         WHERE d.task_id = tasks.id AND p.done = 0 AND p.deleted_at IS NULL
+     ) AS blocked";
+
+/// Current time in the same ISO 8601 shape the frontend produces with `toISOString()`.
+pub fn now_iso() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn task_from_row(row: &Row) -> rusqlite::Result<Task> {
+    Ok(Task {
+        id: row.get(0)?,
+        title: row.get(1)?,
+        done: row.get(2)?,
+        is_today: row.get(3)?,
+        est_minutes: row.get(4)?,
+        notes: row.get(5)?,
+        project_id: row.get(6)?,
+        tags: Some(vec![]),
+        due: row.get(7)?,
+        priority: row.get(8)?,
+        created_at: row.get(9)?,
+        recurrence: row.get(10)?,
+        parent_id: row.get(11)?,
+        deleted_at: row.get(12)?,
+        completed_at: row.get(13)?,
+        archived_at: row.get(14)?,
+        blocked: row.get(15)?,
+    })
+}
+
+fn select_tasks(
+    conn: &Connection,
+    filter: &str,
+    order: &str,
+    params: &[&dyn rusqlite::ToSql],
+) -> rusqlite::Result<Vec<Task>> {
+    let mut tags_by_task = tags::tags_by_task(conn)?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM tasks WHERE {} ORDER BY {}",
+        TASK_COLUMNS, filter, order
+    ))?;
+    let task_iter = stmt.query_map(params, task_from_row)?;
+
+    let mut tasks = Vec::new();
+    for task in task_iter {
+        let mut task = task?;
+        task.tags = Some(tags_by_task.remove(&task.id).unwrap_or_default());
+        tasks.push(task);
+    }
+    Ok(tasks)
+}
+
+/// All tasks in creation order, minus trashed and archived ones. Rows written before
+/// `created_at` was persisted sort first.
+pub fn list_tasks(conn: &Connection) -> rusqlite::Result<Vec<Task>> {
+    select_tasks(
+        conn,
+        "deleted_at IS NULL AND archived_at IS NULL",
+        "created_at, rowid",
+        &[],
+    )
+}
+
+/// Tasks carrying `tag` (ignoring case), looked up through the `task_tags` index.
+pub fn list_tasks_tagged(conn: &Connection, tag: &str) -> rusqlite::Result<Vec<Task>> {
+    select_tasks(
+        conn,
+        "deleted_at IS NULL AND archived_at IS NULL AND id IN (
+             SELECT tt.task_id FROM task_tags tt JOIN tags g ON g.id = tt.tag_id
+             WHERE g.name = ?1 COLLATE NOCASE
+         )",
+        "created_at, rowid",
+        &[&tag.trim()],
+    )
+}
+
+/// The given tasks keyed by id, trashed ones included; unknown ids are left out.
+pub fn tasks_by_ids(conn: &Connection, ids: &[String]) -> Result<HashMap<String, Task>, DbError> {
+    let ids_json = serde_json::to_string(ids)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    Ok(select_tasks(
+        conn,
+        "id IN (SELECT value FROM json_each(?1))",
+        "created_at, rowid",
+        &[&ids_json],
+    )?
+        .into_iter()
+        .map(|t| (t.id.clone(), t))
+        .collect())
+}
+
+pub const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+/// The SQL expression a sort orders by. Paired with `id` it gives a total order.
+fn sort_key(field: TaskSortField) -> &'static str {
+    match field {
+        TaskSortField::Created => "COALESCE(created_at, '')",
+        // '~' sorts after any date, so undated tasks go last.
+        TaskSortField::Due => "COALESCE(due, '~')",
+        TaskSortField::Priority => "priority",
+        TaskSortField::Title => "title COLLATE NOCASE",
+    }
+}
+
+fn encode_cursor(key: &Value, id: &str) -> String {
+    let key = match key {
+        Value::Integer(n) => serde_json::Value::from(*n),
+        Value::Text(s) => serde_json::Value::from(s.as_str()),
+        _ => serde_json::Value::Null,
+    };
+    serde_json::json!([key, id]).to_string()
+}
+
+fn decode_cursor(cursor: &str) -> Result<(Value, String), DbError> {
+    let invalid = || DbError::Invalid(format!("Invalid cursor: {}", cursor));
+    let (key, id): (serde_json::Value, String) =
+        serde_json::from_str(cursor).map_err(|_| invalid())?;
+    let key = match key {
+        serde_json::Value::Number(n) => Value::Integer(n.as_i64().ok_or_else(invalid)?),
+        serde_json::Value::String(s) => Value::Text(s),
+        _ => return Err(invalid()),
+    };
+    Ok((key, id))
+}
+
+/// Parses an RFC 3339 timestamp into the UTC form stored in the timestamp columns.
+fn normalize_timestamp(value: &str) -> Result<String, DbError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| {
+            t.with_timezone(&Utc)
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+        })
+        .map_err(|_| DbError::Invalid(format!("invalid timestamp: {}", value)))
+}
+
+fn validate_filter_date(value: &str) -> Result<String, DbError> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .map_err(|_| DbError::Invalid(format!("Expected a YYYY-MM-DD date, got: {}", value)))
+}
+
+/// One page of tasks matching `filter`, in `sort` order.
+///
+/// Paging is keyset-based: the cursor carries the sort key and id of the last task on the
+/// previous page, so pages don't skip or repeat rows when tasks are added in between.
+/// A cursor is only meaningful with the filter and sort that produced it.
+pub fn query_tasks(
+    conn: &Connection,
+    filter: &TaskFilter,
+    sort: TaskSort,
+    cursor: Option<&str>,
+    limit: u32,
+) -> Result<TaskPage, DbError> {
+    let mut conditions: Vec<&str> = vec!["deleted_at IS NULL"];
+    let mut values: Vec<Value> = Vec::new();
+
+    if !filter.include_archived {
+        conditions.push("archived_at IS NULL");
+    }
+    if let Some(done) = filter.done {
+        conditions.push("done = ?");
+        values.push(done.into());
+    }
+    if let Some(is_today) = filter.is_today {
+        conditions.push("is_today = ?");
+        values.push(is_today.into());
+    }
+    if let Some(project_id) = &filter.project_id {
+        conditions.push("project_id = ?");
+        values.push(project_id.clone().into());
+    }
+    if let Some(priority) = filter.priority {
+        conditions.push("priority = ?");
+        values.push(priority.into());
+    }
+    if let Some(before) = &filter.due_before {
+        conditions.push("substr(due, 1, 10) < ?");
+        values.push(validate_filter_date(before)?.into());
+    }
+    if let Some(after) = &filter.due_after {
+        conditions.push("substr(due, 1, 10) > ?");
+        values.push(validate_filter_date(after)?.into());
+    }
+    if let Some(before) = &filter.completed_before {
+        conditions.push("completed_at < ?");
+        values.push(normalize_timestamp(before)?.into());
+    }
+    if let Some(after) = &filter.completed_after {
+        conditions.push("completed_at > ?");
+        values.push(normalize_timestamp(after)?.into());
+    }
+
+    let mut tag_names: Vec<String> = Vec::new();
+    for tag in filter.tags.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        if !tag_names.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+            tag_names.push(tag.to_string());
+        }
+    }
+    if !tag_names.is_empty() {
+        let tags_json = serde_json::to_string(&tag_names)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        values.push(tags_json.into());
+        match filter.tag_match {
+            TagMatch::Any => conditions.push(
+                "id IN (
+                     SELECT tt.task_id FROM task_tags tt JOIN tags g ON g.id = tt.tag_id
+                     WHERE g.name IN (SELECT value FROM json_each(?))
+                 )",
+            ),
+            TagMatch::All => {
+                conditions.push(
+                    "id IN (
+                         SELECT tt.task_id FROM task_tags tt JOIN tags g ON g.id = tt.tag_id
+                         WHERE g.name IN (SELECT value FROM json_each(?))
+                         GROUP BY tt.task_id HAVING COUNT(*) = ?
+                     )",
+                );
+                values.push((tag_names.len() as i64).into());
+            }
+        }
+    }
+
+    if let Some(text) = filter.text.as_deref().and_then(search::fts_query) {
+        conditions.push("id IN (SELECT task_id FROM tasks_fts WHERE tasks_fts MATCH ?)");
+        values.push(text.into());
+    }
+
+    let key = sort_key(sort.by);
+    let (cmp, dir) = if sort.descending {
+        ("<", "DESC")
+    } else {
+        (">", "ASC")
+    };
+    let after_cursor = format!("({key} {cmp} ? OR ({key} = ? AND id {cmp} ?))");
+    if let Some(cursor) = cursor {
+        let (last_key, last_id) = decode_cursor(cursor)?;
+        conditions.push(&after_cursor);
+        values.push(last_key.clone());
+        values.push(last_key);
+        values.push(last_id.into());
+    }
+
+    let limit = limit.clamp(1, MAX_PAGE_SIZE);
+    values.push((limit as i64 + 1).into());
+    let where_clause = conditions.join(" AND ");
+
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {TASK_COLUMNS}, {key} FROM tasks WHERE {where_clause} ORDER BY {key} {dir}, id {dir} LIMIT ?"
+    ))?;
+    let mut rows = stmt
+        .query_map(params_from_iter(values), |row| {
+            Ok((task_from_row(row)?, row.get::<_, Value>(16)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let next_cursor = if rows.len() > limit as usize {
+        rows.truncate(limit as usize);
+        rows.last().map(|(task, key)| encode_cursor(key, &task.id))
+    } else {
+        None
+    };
+
+    let ids: Vec<String> = rows.iter().map(|(task, _)| task.id.clone()).collect();
+    let mut tags_by_task = tags::tags_for_tasks(conn, &ids)?;
+    let tasks = rows
+        .into_iter()
+        .map(|(mut task, _)| {
+            task.tags = Some(tags_by_task.remove(&task.id).unwrap_or_default());
+            task
+        })
+        .collect();
+    Ok(TaskPage { tasks, next_cursor })
+}
+
+fn validate_recurrence(task: &Task) -> Result<Option<Rule>, DbError> {
+    task.recurrence
+        .as_deref()
+        .map(|r| r.parse::<Rule>().map_err(DbError::Invalid))
+        .transpose()
+}
+
+/// Rejects a `parent_id` that doesn't exist or would make the task its own ancestor.
+fn validate_parent(conn: &Connection, task: &Task) -> Result<(), DbError> {
+    let Some(parent_id) = task.parent_id.as_deref() else {
+        return Ok(());
+    };
+    if parent_id == task.id {
+        return Err(DbError::Invalid(
+            "A task can't be its own parent".to_string(),
+        ));
+    }
+    let parent_exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM tasks WHERE id = ?1 AND deleted_at IS NULL)",
+        params![parent_id],
+        |r| r.get(0),
+    )?;
+    if !parent_exists {
+        return Err(DbError::Invalid(format!(
+            "Parent task not found: {}",
+            parent_id
+        )));
+    }
+    let creates_cycle: bool = conn.query_row(
+        "WITH RECURSIVE ancestors(id) AS (
+             SELECT parent_id FROM tasks WHERE id = ?1
+             UNION
+             SELECT t.parent_id FROM tasks t JOIN ancestors a ON t.id = a.id
+         )
+         SELECT EXISTS(SELECT 1 FROM ancestors WHERE id = ?2)",
+        params![parent_id, task.id],
+        |r| r.get(0),
+    )?;
+    if creates_cycle {
+        return Err(DbError::Invalid(
+            "A task can't be moved under one of its own subtasks".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// The date part of a `due` value, which may be a bare date or a full ISO timestamp.
+pub fn due_date(due: &str) -> Option<NaiveDate> {
+    due.get(..10)
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+}
+
+/// The next open instance of a recurring task that was just completed on `today`.
+///
+/// The series is anchored at the task's due date (or its creation date). An overdue
+/// task rolls forward to the first occurrence on or after `today` rather than
+/// producing a backlog of missed instances.
+pub fn next_instance(task: &Task, rule: &Rule, today: NaiveDate) -> Option<Task> {
+    let anchor = task
+        .due
+        .as_deref()
+        .and_then(due_date)
+        .or_else(|| task.created_at.as_deref().and_then(due_date))
+        .unwrap_or(today);
+    let after = anchor.max(today.pred_opt()?);
+    let next_due = rule.next_after(anchor, after)?;
+
+    Some(Task {
+        id: Uuid::new_v4().to_string(),
+        done: false,
+        is_today: false,
+        due: Some(next_due.format("%Y-%m-%d").to_string()),
+        created_at: Some(now_iso()),
+        deleted_at: None,
+        completed_at: None,
+        archived_at: None,
+        ..task.clone()
+    })
+}
+
+/// Inserts a task, stamping `created_at` with the current time when the caller didn't supply one,
+/// and `completed_at` likewise for a task that starts out done.
+pub fn insert_task(conn: &Connection, task: &Task) -> Result<(), DbError> {
+    validate_recurrence(task)?;
+    validate_parent(conn, task)?;
+    let created_at = task.created_at.clone().unwrap_or_else(now_iso);
+    let completed_at = if task.done {
+        Some(task.completed_at.clone().unwrap_or_else(now_iso))
+    } else {
+        None
+    };
+    conn.execute(
+        "INSERT INTO tasks (id, title, done, is_today, est_minutes, notes, project_id, due, priority, created_at, recurrence, parent_id, completed_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+        params![
+            task.id,
+            task.title,
+            task.done,
+            task.is_today,
+            task.est_minutes,
+            task.notes,
+            task.project_id,
+            task.due,
+            task.priority,
+            created_at,
+            task.recurrence,
+            task.parent_id,
+            completed_at,
+        ],
+    )?;
+    tags::set_task_tags(conn, &task.id, task.tags.as_deref().unwrap_or_default())?;
+    Ok(())
+}
+
+/// Writes a task row back exactly as given, backend-owned columns included, e.g. when
+/// undoing a change. Tags and dependencies are left to the caller.
+pub fn write_task_row(conn: &Connection, task: &Task) -> rusqlite::Result<()> {
+    conn.execute(
+        "INSERT INTO tasks (id, title, done, is_today, est_minutes, notes, project_id, due, priority, created_at, recurrence, parent_id, deleted_at, completed_at, archived_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+         ON CONFLICT (id) DO UPDATE SET
+             title = excluded.title, done = excluded.done, is_today = excluded.is_today,
+             est_minutes = excluded.est_minutes, notes = excluded.notes, project_id = excluded.project_id,
+             due = excluded.due, priority = excluded.priority, created_at = excluded.created_at,
+             recurrence = excluded.recurrence, parent_id = excluded.parent_id,
+             deleted_at = excluded.deleted_at, completed_at = excluded.completed_at,
+             archived_at = excluded.archived_at",
+        params![
+            task.id,
+            task.title,
+            task.done,
+            task.is_today,
+            task.est_minutes,
+            task.notes,
+            task.project_id,
+            task.due,
+            task.priority,
+            task.created_at,
+            task.recurrence,
+            task.parent_id,
+            task.deleted_at,
+            task.completed_at,
+            task.archived_at,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Updates a task in place. `created_at` is kept as stored unless the caller sends one.
+///
+/// Completing a recurring task creates its next instance, which is returned. The rule
+/// moves to the new instance so reopening and re-completing the old one can't fork the series.
+/// With `complete_children`, completing a task also completes all of its subtasks.
+///
+/// `completed_at` and `archived_at` are owned by the backend: completing stamps the time,
+/// reopening clears both.
+pub fn update_task(
+    conn: &mut Connection,
+    task: &Task,
+    complete_children: bool,
+) -> Result<Option<Task>, DbError> {
+    let rule = validate_recurrence(task)?;
+
+    let tx = conn.savepoint()?;
+    validate_parent(&tx, task)?;
+    let was_done: bool = tx
+        .query_row(
+            "SELECT done FROM tasks WHERE id = ?1",
+            params![task.id],
+            |r| r.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::Invalid(format!("Task not found: {}", task.id)))?;
+
+    let next = match rule {
+        Some(rule) if task.done && !was_done => {
+            next_instance(task, &rule, Local::now().date_naive())
+        }
+        _ => None,
+    };
+    let recurrence = if next.is_some() {
+        None
+    } else {
+        task.recurrence.clone()
+    };
+
+    tx.execute(
+        "UPDATE tasks SET title = ?2, done = ?3, is_today = ?4, est_minutes = ?5, notes = ?6, project_id = ?7, due = ?8, priority = ?9, created_at = COALESCE(?10, created_at), recurrence = ?11, parent_id = ?12,
+             completed_at = CASE WHEN ?3 THEN COALESCE(completed_at, ?13) END,
+             archived_at = CASE WHEN ?3 THEN archived_at END
+         WHERE id = ?1",
+        params![
+            task.id,
+            task.title,
+            task.done,
+            task.is_today,
+            task.est_minutes,
+            task.notes,
+            task.project_id,
+            task.due,
+            task.priority,
+            task.created_at,
+            recurrence,
+            task.parent_id,
+            now_iso(),
+        ],
+    )?;
+    tags::set_task_tags(&tx, &task.id, task.tags.as_deref().unwrap_or_default())?;
+    if task.done && complete_children {
+        // Trashed and archived subtasks are out of sight, so they're left as they are.
+        tx.execute(
+            "WITH RECURSIVE descendants(id) AS (
+                 SELECT id FROM tasks
+                 WHERE parent_id = ?1 AND deleted_at IS NULL AND archived_at IS NULL
+                 UNION
+                 SELECT t.id FROM tasks t JOIN descendants d ON t.parent_id = d.id
+                 WHERE t.deleted_at IS NULL AND t.archived_at IS NULL
+             )
+             UPDATE tasks SET done = 1, completed_at = COALESCE(completed_at, ?2)
+             WHERE id IN (SELECT id FROM descendants)",
+            params![task.id, now_iso()],
+        )?;
+    }
+    if let Some(next) = &next {
+        insert_task(&tx, next)?;
+    }
+    tx.commit()?;
+    Ok(next)
+}
+
+/// Archives done tasks completed before `older_than` (RFC 3339) and returns how many were
+/// archived. Tasks marked done before `completed_at` was tracked count as old. Archived tasks
+/// drop out of the default lists but stay queryable with `include_archived`.
+pub fn archive_completed(conn: &Connection, older_than: &str) -> Result<usize, DbError> {
+    let cutoff = normalize_timestamp(older_than)?;
+    let count = conn.execute(
+        "UPDATE tasks SET archived_at = ?2
+         WHERE done = 1 AND archived_at IS NULL AND deleted_at IS NULL
+           AND COALESCE(completed_at, '') < ?1",
+        params![cutoff, now_iso()],
+    )?;
+    Ok(count)
+}
+
+/// Every occurrence of open recurring tasks within `[start, end]`.
+pub fn expand_occurrences(
+    conn: &Connection,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<Occurrence>, DbError> {
+    let mut stmt = conn.prepare(
+        "SELECT id, recurrence, due, created_at FROM tasks WHERE recurrence IS NOT NULL AND done = 0 AND deleted_at IS NULL",
+    )?;
+    let rows = stmt.query_map(params![], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, Option<String>>(3)?,
+        ))
+    })?;
+
+    let mut occurrences = Vec::new();
+    for row in rows {
+        let (task_id, recurrence, due, created_at) = row?;
+        // A rule that no longer parses shouldn't hide every other task's occurrences.
+        let Ok(rule) = recurrence.parse::<Rule>() else {
+            continue;
+        };
+        let anchor = due
+            .as_deref()
+            .and_then(due_date)
+            .or_else(|| created_at.as_deref().and_then(due_date))
+            .unwrap_or(start);
+        for date in rule.between(anchor, start, end) {
+            occurrences.push(Occurrence {
+                task_id: task_id.clone(),
+                date: date.format("%Y-%m-%d").to_string(),
+            });
+        }
+    }
+    occurrences.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.task_id.cmp(&b.task_id)));
+    Ok(occurrences)
+}
+
+/// Moves a task to the trash. Its subtasks are trashed with it or moved up to its parent
+/// per `children`. Everything trashed together shares one `deleted_at` stamp so it can be
+/// restored together.
+pub fn delete_task(conn: &mut Connection, id: &str, children: ChildPolicy) -> Result<(), DbError> {
+    let tx = conn.savepoint()?;
+    if children == ChildPolicy::Reparent {
+        tx.execute(
+            "UPDATE tasks SET parent_id = (SELECT parent_id FROM tasks WHERE id = ?1)
+             WHERE parent_id = ?1 AND deleted_at IS NULL",
+            params![id],
+        )?;
+    }
+    tx.execute(
+        "WITH RECURSIVE subtree(id) AS (
+             SELECT ?1
+             UNION
+             SELECT t.id FROM tasks t JOIN subtree s ON t.parent_id = s.id WHERE t.deleted_at IS NULL
+         )
+         UPDATE tasks SET deleted_at = ?2 WHERE id IN (SELECT id FROM subtree) AND deleted_at IS NULL",
+        params![id, now_iso()],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Trashed tasks, most recently deleted first.
+pub fn list_trash(conn: &Connection) -> rusqlite::Result<Vec<Task>> {
+    select_tasks(
+        conn,
+        "deleted_at IS NOT NULL",
+        "deleted_at DESC, created_at, rowid",
+        &[],
+    )
+}
+
+/// Takes a task out of the trash along with the subtasks that were trashed with it, and
+/// returns the restored tasks. If its parent is still in the trash it comes back top-level.
+pub fn restore_task(conn: &mut Connection, id: &str) -> Result<Vec<Task>, DbError> {
+    let tx = conn.savepoint()?;
+    let deleted_at: Option<String> = tx
+        .query_row(
+            "SELECT deleted_at FROM tasks WHERE id = ?1",
+            params![id],
+            |r| r.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| DbError::Invalid(format!("Task not found: {}", id)))?;
+    let Some(deleted_at) = deleted_at else {
+        return Err(DbError::Invalid(format!("Task is not in the trash: {}", id)));
+    };
+
+    let mut stmt = tx.prepare(
+        "WITH RECURSIVE subtree(id) AS (
+             SELECT ?1
+             UNION
+             SELECT t.id FROM tasks t JOIN subtree s ON t.parent_id = s.id WHERE t.deleted_at = ?2
+         )
+         SELECT id FROM subtree",
+    )?;
+    let ids: Vec<String> = stmt
+        .query_map(params![id, deleted_at], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+    drop(stmt);
+
+    let ids_json = serde_json::to_string(&ids)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    tx.execute(
+        "UPDATE tasks SET deleted_at = NULL WHERE id IN (SELECT value FROM json_each(?1))",
+        params![ids_json],
+    )?;
+    tx.execute(
+        "UPDATE tasks SET parent_id = NULL
+         WHERE id = ?1 AND parent_id IN (SELECT id FROM tasks WHERE deleted_at IS NOT NULL)",
+        params![id],
+    )?;
+    let restored = select_tasks(
+        &tx,
+        "id IN (SELECT value FROM json_each(?1))",
+        "created_at, rowid",
+        &[&ids_json],
+    )?;
+    tx.commit()?;
+    Ok(restored)
+}
+
+/// Permanently deletes trashed tasks, or only those trashed before `cutoff` (RFC 3339),
+/// together with their blocks. Returns how many tasks were removed. Anything removed is
+/// logged under `action` in the audit log.
+fn purge_trash(
+    conn: &mut Connection,
+    cutoff: Option<&str>,
+    action: &str,
+) -> Result<usize, DbError> {
+    let purged = "SELECT id FROM tasks WHERE deleted_at IS NOT NULL AND (?1 IS NULL OR deleted_at < ?1)";
+    let tx = conn.savepoint()?;
+    let task_ids: Vec<String> = tx
+        .prepare(&format!("{purged} ORDER BY id"))?
+        .query_map(params![cutoff], |r| r.get(0))?
+        .collect::<Result<_, _>>()?;
+    // Live tasks never sit under a trashed parent, but don't let the cascade take one if they do.
+    tx.execute(
+        &format!("UPDATE tasks SET parent_id = NULL WHERE deleted_at IS NULL AND parent_id IN ({purged})"),
+        params![cutoff],
+    )?;
+    // day_blocks.task_id has no ON DELETE action, so clear those first.
+    let day_blocks = tx.execute(
+        &format!("DELETE FROM day_blocks WHERE task_id IN ({purged})"),
+        params![cutoff],
+    )?;
+    let count = tx.execute(
+        "DELETE FROM tasks WHERE deleted_at IS NOT NULL AND (?1 IS NULL OR deleted_at < ?1)",
+        params![cutoff],
+    )?;
+    tags::prune_unused(&tx)?;
+    if count > 0 {
+        audit::record(
+            &tx,
+            action,
+            &serde_json::json!({
+                "tasks": task_ids,
+                "day_blocks": day_blocks,
+                "trashed_before": cutoff,
+            }),
+        )?;
+    }
+    tx.commit()?;
+    Ok(count)
+}
+
+/// Permanently deletes everything in the trash.
+pub fn empty_trash(conn: &mut Connection) -> Result<usize, DbError> {
+    purge_trash(conn, None, "empty_trash")
+}
+
+pub const TRASH_RETENTION_SETTING: &str = "trashRetentionDays";
+const DEFAULT_TRASH_RETENTION_DAYS: i64 = 30;
+
+/// Purges tasks that have been in the trash longer than the `trashRetentionDays` setting
+/// (30 days unless set; 0 keeps them forever).
+pub fn purge_expired_trash(conn: &mut Connection, now: DateTime<Utc>) -> Result<usize, DbError> {
+    let setting: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            params![TRASH_RETENTION_SETTING],
+            |r| r.get(0),
+        )
+        .optional()?
+        .flatten();
+    let days = match setting {
+        Some(value) => value.trim().parse::<i64>().map_err(|_| {
+            DbError::Invalid(format!(
+                "{} must be a whole number of days, got: {}",
+                TRASH_RETENTION_SETTING, value
+            ))
+        })?,
+        None => DEFAULT_TRASH_RETENTION_DAYS,
+    };
+    if days <= 0 {
+        return Ok(0);
+    }
+    let cutoff = (now - Duration::days(days)).to_rfc3339_opts(SecondsFormat::Millis, true);
+    purge_trash(conn, Some(&cutoff), "purge_expired_trash")
+}
+
+/// Tasks arranged by `parent_id`, starting from `root_id` or from every top-level task.
+pub fn task_tree(conn: &Connection, root_id: Option<&str>) -> Result<Vec<TaskNode>, DbError> {
+    let mut children: HashMap<Option<String>, Vec<Task>> = HashMap::new();
+    let mut root = None;
+    for task in list_tasks(conn)? {
+        if root_id == Some(task.id.as_str()) {
+            root = Some(task.clone());
+        }
+        children
+            .entry(task.parent_id.clone())
+            .or_default()
+            .push(task);
+    }
+
+    fn build(task: Task, children: &mut HashMap<Option<String>, Vec<Task>>) -> TaskNode {
+        let kids: Vec<TaskNode> = children
+            .remove(&Some(task.id.clone()))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|child| build(child, children))
+            .collect();
+        let rollup_est_minutes = if kids.is_empty() {
+            task.est_minutes
+        } else {
+            kids.iter().map(|k| k.rollup_est_minutes).sum()
+        };
+        TaskNode {
+            task,
+            rollup_est_minutes,
+            children: kids,
+        }
+    }
+
+    let roots = match root_id {
+        Some(id) => vec![root.ok_or_else(|| DbError::Invalid(format!("Task not found: {}", id)))?],
+        None => {
+            // A subtask whose parent is archived shows up at the top level rather than vanishing.
+            let ids: HashSet<String> = children.values().flatten().map(|t| t.id.clone()).collect();
+            let orphaned: Vec<Option<String>> = children
+                .keys()
+                .filter(|parent| parent.as_ref().is_some_and(|p| !ids.contains(p)))
+                .cloned()
+                .collect();
+            let mut roots = children.remove(&None).unwrap_or_default();
+            for parent in orphaned {
+                roots.extend(children.remove(&parent).unwrap_or_default());
+            }
+            roots.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+            roots
+        }
+    };
+    Ok(roots
+        .into_iter()
+        .map(|task| build(task, &mut children))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_util::task;
+    use crate::db::migrations::run_migrations;
+
+    fn setup() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        run_migrations(&mut conn).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_priority_and_created_at_round_trip() {
+        let mut conn = setup();
+        let mut t = task("a");
+        t.priority = 1;
+        t.created_at = Some("2024-05-01T09:00:00.000Z".to_string());
+        insert_task(&conn, &t).unwrap();
+
+        let tasks = list_tasks(&conn).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].priority, 1);
+        assert_eq!(
+            tasks[0].created_at.as_deref(),
+            Some("2024-05-01T09:00:00.000Z")
+        );
+
+        t.priority = 3;
+        t.created_at = None;
+        update_task(&mut conn, &t, false).unwrap();
+
+        let tasks = list_tasks(&conn).unwrap();
+        assert_eq!(tasks[0].priority, 3);
+        // An update without created_at must not clear the stored value.
+        assert_eq!(
+            tasks[0].created_at.as_deref(),
+            Some("2024-05-01T09:00:00.000Z")
+        );
+    }
+
+    #[test]
+    fn test_insert_stamps_created_at() {
+        let conn = setup();
+        insert_task(&conn, &task("a")).unwrap();
+
+        let tasks = list_tasks(&conn).unwrap();
+        assert!(tasks[0].created_at.is_some());
+    }
+
+    #[test]
+    fn test_list_tasks_in_creation_order() {
+        let conn = setup();
+        let mut late = task("late");
+        late.created_at = Some("2024-05-02T00:00:00.000Z".to_string());
+        let mut early = task("early");
+        early.created_at = Some("2024-05-01T00:00:00.000Z".to_string());
+        insert_task(&conn, &late).unwrap();
+        insert_task(&conn, &early).unwrap();
+
+        let ids: Vec<String> = list_tasks(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|t| t.id)
+            .collect();
+        assert_eq!(ids, vec!["early", "late"]);
+    }
+
+    #[test]
+    fn test_tasks_from_db_without_priority_column() {
+        // A database from before migration 4: no priority column, created_at never written.
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "
+            CREATE TABLE tasks (
+                id TEXT PRIMARY KEY,
+                title TEXT NOT NULL,
+                done INTEGER NOT NULL DEFAULT 0,
+                est_minutes INTEGER,
+                notes TEXT,
+                project TEXT,
+                tags TEXT,
+                created_at TEXT,
+                is_today INTEGER NOT NULL DEFAULT 0,
+                due TEXT
+            );
+            CREATE TABLE day_blocks (id TEXT PRIMARY KEY, task_id TEXT, date TEXT NOT NULL, start_slot INTEGER NOT NULL, end_slot INTEGER NOT NULL);
+            CREATE TABLE settings (key TEXT PRIMARY KEY, value TEXT);
+            CREATE TABLE schema_migrations (id INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at INTEGER NOT NULL);
+            INSERT INTO schema_migrations VALUES (1, 'init', 0), (2, 'add_is_today', 0), (3, 'add_due', 0);
+            INSERT INTO tasks (id, title, done, est_minutes, tags) VALUES ('old', 'Legacy task', 0, 30, '[]');
+            ",
+        )
+        .unwrap();
+
+        run_migrations(&mut conn).unwrap();
+
+        let tasks = list_tasks(&conn).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].priority, 2);
+        assert_eq!(tasks[0].created_at, None);
+
+        // Legacy rows pick up the new fields on their next update.
+        let mut legacy = tasks[0].clone();
+        legacy.priority = 1;
+        legacy.created_at = Some("2024-01-01T00:00:00.000Z".to_string());
+        update_task(&mut conn, &legacy, false).unwrap();
+
+        let tasks = list_tasks(&conn).unwrap();
+        assert_eq!(tasks[0].priority, 1);
+        assert_eq!(
+            tasks[0].created_at.as_deref(),
+            Some("2024-01-01T00:00:00.000Z")
+        );
+    }
+
+    #[test]
+    fn test_tags_round_trip_and_filter() {
+        let mut conn = setup();
+        let mut a = task("a");
+        a.tags = Some(vec!["Work".to_string(), "writing".to_string()]);
+        let mut b = task("b");
+        b.tags = None;
+        insert_task(&conn, &a).unwrap();
+        insert_task(&conn, &b).unwrap();
+
+        let tasks = list_tasks(&conn).unwrap();
+        assert_eq!(tasks[0].tags, Some(vec!["Work".to_string(), "writing".to_string()]));
+        assert_eq!(tasks[1].tags, Some(vec![]));
+
+        let tagged: Vec<String> = list_tasks_tagged(&conn, "work")
+            .unwrap()
+            .into_iter()
+            .map(|t| t.id)
+            .collect();
+        assert_eq!(tagged, vec!["a"]);
+
+        a.tags = Some(vec!["writing".to_string()]);
+        b.tags = Some(vec!["work".to_string()]);
+        update_task(&mut conn, &a, false).unwrap();
+        update_task(&mut conn, &b, false).unwrap();
+        let tagged: Vec<String> = list_tasks_tagged(&conn, "WORK")
+            .unwrap()
+            .into_iter()
+            .map(|t| t.id)
+            .collect();
+        assert_eq!(tagged, vec!["b"]);
+    }
+
+    fn query_ids(conn: &Connection, filter: &TaskFilter, sort: TaskSort) -> Vec<String> {
+        query_tasks(conn, filter, sort, None, DEFAULT_PAGE_SIZE)
+            .unwrap()
+            .tasks
+            .into_iter()
+            .map(|t| t.id)
+            .collect()
+    }
+
+    fn seed_query_tasks(conn: &Connection) {
+        let specs = [
+            ("a", "Write report", 1, Some("2024-05-03"), &["work", "writing"][..]),
+            ("b", "answer email", 2, None, &["work"][..]),
+            ("c", "Book dentist", 3, Some("2024-05-01T09:00:00.000Z"), &["home"][..]),
+            ("d", "Draft blog post", 2, Some("2024-05-10"), &["writing"][..]),
+        ];
+        for (i, (id, title, priority, due, tags)) in specs.into_iter().enumerate() {
+            let mut t = task(id);
+            t.title = title.to_string();
+            t.priority = priority;
+            t.due = due.map(|d| d.to_string());
+            t.tags = Some(tags.iter().map(|s| s.to_string()).collect());
+            t.created_at = Some(format!("2024-04-0{}T00:00:00.000Z", i + 1));
+            insert_task(conn, &t).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_query_filters() {
+        let conn = setup();
+        seed_query_tasks(&conn);
+        let all = TaskFilter::default();
+        let sort = TaskSort::default();
+        assert_eq!(query_ids(&conn, &all, sort), vec!["a", "b", "c", "d"]);
+
+        let any = TaskFilter {
+            tags: vec!["WORK".to_string(), "home".to_string()],
+            ..TaskFilter::default()
+        };
+        assert_eq!(query_ids(&conn, &any, sort), vec!["a", "b", "c"]);
+        let all_tags = TaskFilter {
+            tags: vec!["work".to_string(), "writing".to_string()],
+            tag_match: TagMatch::All,
+            ..TaskFilter::default()
+        };
+        assert_eq!(query_ids(&conn, &all_tags, sort), vec!["a"]);
+
+        let due_window = TaskFilter {
+            due_after: Some("2024-05-01".to_string()),
+            due_before: Some("2024-05-10".to_string()),
+            ..TaskFilter::default()
+        };
+        assert_eq!(query_ids(&conn, &due_window, sort), vec!["a"]);
+
+        let text = TaskFilter {
+            text: Some("dent".to_string()),
+            done: Some(false),
+            ..TaskFilter::default()
+        };
+        assert_eq!(query_ids(&conn, &text, sort), vec!["c"]);
+
+        let priority = TaskFilter {
+            priority: Some(2),
+            ..TaskFilter::default()
+        };
+        assert_eq!(query_ids(&conn, &priority, sort), vec!["b", "d"]);
+
+        let bad_date = TaskFilter {
+            due_before: Some("next week".to_string()),
+            ..TaskFilter::default()
+        };
+        assert!(matches!(
+            query_tasks(&conn, &bad_date, sort, None, 10),
+            Err(DbError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_query_sorts() {
+        let conn = setup();
+        seed_query_tasks(&conn);
+        let all = TaskFilter::default();
+        let by = |by, descending| TaskSort { by, descending };
+
+        assert_eq!(
+            query_ids(&conn, &all, by(TaskSortField::Due, false)),
+            vec!["c", "a", "d", "b"]
+        );
+        assert_eq!(
+            query_ids(&conn, &all, by(TaskSortField::Title, false)),
+            vec!["b", "c", "d", "a"]
+        );
+        assert_eq!(
+            query_ids(&conn, &all, by(TaskSortField::Priority, true)),
+            vec!["c", "d", "b", "a"]
+        );
+    }
+
+    #[test]
+    fn test_query_pages_with_cursor() {
+        let conn = setup();
+        seed_query_tasks(&conn);
+        let all = TaskFilter::default();
+        let sort = TaskSort {
+            by: TaskSortField::Priority,
+            descending: false,
+        };
+
+        let mut seen = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = query_tasks(&conn, &all, sort, cursor.as_deref(), 2).unwrap();
+            assert!(page.tasks.len() <= 2);
+            seen.extend(page.tasks.into_iter().map(|t| t.id));
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        // b and d share a priority; the id breaks the tie across the page boundary.
+        assert_eq!(seen, vec!["a", "b", "d", "c"]);
+
+        let first = query_tasks(&conn, &all, sort, None, 2).unwrap();
+        assert_eq!(first.tasks[0].tags, Some(vec!["work".to_string(), "writing".to_string()]));
+        assert!(matches!(
+            query_tasks(&conn, &all, sort, Some("garbage"), 2),
+            Err(DbError::Invalid(_))
+        ));
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_completing_recurring_task_creates_next_instance() {
+        let mut conn = setup();
+        let mut t = task("rent");
+        t.due = Some("2100-01-31".to_string());
+        t.recurrence = Some("FREQ=MONTHLY;BYMONTHDAY=-1".to_string());
+        insert_task(&conn, &t).unwrap();
+
+        t.done = true;
+        let next = update_task(&mut conn, &t, false).unwrap().unwrap();
+        // 2100 is not a leap year.
+        assert_eq!(next.due.as_deref(), Some("2100-02-28"));
+        assert!(!next.done);
+        assert_ne!(next.id, t.id);
+        assert_eq!(next.recurrence, t.recurrence);
+
+        let tasks = list_tasks(&conn).unwrap();
+        assert_eq!(tasks.len(), 2);
+        let done = tasks.iter().find(|x| x.id == "rent").unwrap();
+        assert_eq!(done.recurrence, None);
+
+        // Saving the completed task again must not spawn another instance.
+        assert!(update_task(&mut conn, &done.clone(), false)
+            .unwrap()
+            .is_none());
+        assert_eq!(list_tasks(&conn).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_completing_plain_task_creates_nothing() {
+        let mut conn = setup();
+        let mut t = task("a");
+        insert_task(&conn, &t).unwrap();
+
+        t.done = true;
+        assert!(update_task(&mut conn, &t, false).unwrap().is_none());
+        assert_eq!(list_tasks(&conn).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_updating_missing_task_fails() {
+        let mut conn = setup();
+        let mut t = task("gone");
+        t.done = true;
+        t.recurrence = Some("FREQ=DAILY".to_string());
+
+        assert!(matches!(
+            update_task(&mut conn, &t, false),
+            Err(DbError::Invalid(_))
+        ));
+        assert!(list_tasks(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_invalid_recurrence_rejected() {
+        let conn = setup();
+        let mut t = task("a");
+        t.recurrence = Some("FREQ=HOURLY".to_string());
+        assert!(matches!(insert_task(&conn, &t), Err(DbError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_next_instance_rolls_overdue_task_forward() {
+        let rule: Rule = "FREQ=WEEKLY;BYDAY=MO".parse().unwrap();
+        let mut t = task("report");
+        t.due = Some("2024-04-29".to_string());
+
+        // Completed early: next Monday after the due date.
+        let next = next_instance(&t, &rule, date("2024-04-25")).unwrap();
+        assert_eq!(next.due.as_deref(), Some("2024-05-06"));
+
+        // Completed two weeks late on a Wednesday: the coming Monday, not the missed ones.
+        let next = next_instance(&t, &rule, date("2024-05-15")).unwrap();
+        assert_eq!(next.due.as_deref(), Some("2024-05-20"));
+
+        // Completed late on a Monday: today's occurrence is still open.
+        let next = next_instance(&t, &rule, date("2024-05-13")).unwrap();
+        assert_eq!(next.due.as_deref(), Some("2024-05-13"));
+    }
+
+    #[test]
+    fn test_expand_occurrences_in_range() {
+        let conn = setup();
+        let mut standup = task("standup");
+        standup.due = Some("2024-02-26".to_string());
+        standup.recurrence = Some("FREQ=WEEKLY;BYDAY=MO,TH".to_string());
+        insert_task(&conn, &standup).unwrap();
+        let mut closed = task("closed");
+        closed.done = true;
+        closed.due = Some("2024-02-26".to_string());
+        closed.recurrence = Some("FREQ=DAILY".to_string());
+        insert_task(&conn, &closed).unwrap();
+        insert_task(&conn, &task("one-off")).unwrap();
+
+        let dates: Vec<String> = expand_occurrences(&conn, date("2024-02-27"), date("2024-03-04"))
+            .unwrap()
+            .into_iter()
+            .map(|o| {
+                assert_eq!(o.task_id, "standup");
+                o.date
+            })
+            .collect();
+        assert_eq!(dates, vec!["2024-02-29", "2024-03-04"]);
+    }
+
+    fn subtask(id: &str, parent_id: &str, est_minutes: i32) -> Task {
+        Task {
+            parent_id: Some(parent_id.to_string()),
+            est_minutes,
+            ..task(id)
+        }
+    }
+
+    /// parent -> (a -> (a1, a2), b)
+    fn setup_tree() -> Connection {
+        let conn = setup();
+        insert_task(&conn, &task("parent")).unwrap();
+        insert_task(&conn, &subtask("a", "parent", 0)).unwrap();
+        insert_task(&conn, &subtask("a1", "a", 20)).unwrap();
+        insert_task(&conn, &subtask("a2", "a", 15)).unwrap();
+        insert_task(&conn, &subtask("b", "parent", 30)).unwrap();
+        conn
+    }
+
+    #[test]
+    fn test_task_tree_rolls_up_estimates() {
+        let conn = setup_tree();
+        insert_task(&conn, &task("loose")).unwrap();
+
+        let tree = task_tree(&conn, None).unwrap();
+        assert_eq!(tree.len(), 2);
+        let parent = tree.iter().find(|n| n.task.id == "parent").unwrap();
+        assert_eq!(parent.rollup_est_minutes, 20 + 15 + 30);
+        assert_eq!(parent.children.len(), 2);
+        assert_eq!(parent.children[0].rollup_est_minutes, 35);
+
+        let subtree = task_tree(&conn, Some("a")).unwrap();
+        assert_eq!(subtree.len(), 1);
+        assert_eq!(subtree[0].children.len(), 2);
+        assert!(task_tree(&conn, Some("missing")).is_err());
+    }
+
+    #[test]
+    fn test_completing_parent_optionally_completes_children() {
+        let mut conn = setup_tree();
+        let mut a = subtask("a", "parent", 0);
+        a.done = true;
+        update_task(&mut conn, &a, false).unwrap();
+        assert!(list_tasks(&conn)
+            .unwrap()
+            .iter()
+            .filter(|t| t.id == "a1" || t.id == "a2")
+            .all(|t| !t.done));
+
+        let mut parent = task("parent");
+        parent.done = true;
+        update_task(&mut conn, &parent, true).unwrap();
+        assert!(list_tasks(&conn).unwrap().iter().all(|t| t.done));
+    }
+
+    #[test]
+    fn test_completing_children_skips_trashed_ones() {
+        let mut conn = setup_tree();
+        delete_task(&mut conn, "a", ChildPolicy::Cascade).unwrap();
+
+        let mut parent = task("parent");
+        parent.done = true;
+        update_task(&mut conn, &parent, true).unwrap();
+        let restored = restore_task(&mut conn, "a").unwrap();
+        assert_eq!(restored.len(), 3);
+        assert!(restored.iter().all(|t| !t.done && t.completed_at.is_none()));
+        let b = list_tasks(&conn)
+            .unwrap()
+            .into_iter()
+            .find(|t| t.id == "b")
+            .unwrap();
+        assert!(b.done);
+    }
+
+    #[test]
+    fn test_rejects_parent_cycles() {
+        let mut conn = setup_tree();
+        let moved = subtask("parent", "a1", 0);
+        assert!(matches!(
+            update_task(&mut conn, &moved, false),
+            Err(DbError::Invalid(_))
+        ));
+        let own = subtask("a", "a", 0);
+        assert!(matches!(
+            update_task(&mut conn, &own, false),
+            Err(DbError::Invalid(_))
+        ));
+        assert!(matches!(
+            insert_task(&conn, &subtask("c", "missing", 0)),
+            Err(DbError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_delete_parent_reparents_children() {
+        let mut conn = setup_tree();
+        delete_task(&mut conn, "a", ChildPolicy::Reparent).unwrap();
+
+        let tasks = list_tasks(&conn).unwrap();
+        assert_eq!(tasks.len(), 4);
+        for id in ["a1", "a2"] {
+            let t = tasks.iter().find(|t| t.id == id).unwrap();
+            assert_eq!(t.parent_id.as_deref(), Some("parent"));
+        }
+    }
+
+    #[test]
+    fn test_delete_parent_cascades_to_descendants() {
+        let mut conn = setup_tree();
+        delete_task(&mut conn, "parent", ChildPolicy::Cascade).unwrap();
+
+        assert!(list_tasks(&conn).unwrap().is_empty());
+    }
+
+    fn trashed_ids(conn: &Connection) -> Vec<String> {
+        list_trash(conn).unwrap().into_iter().map(|t| t.id).collect()
+    }
+
+    #[test]
+    fn test_restore_brings_back_subtree() {
+        let mut conn = setup_tree();
+        delete_task(&mut conn, "a2", ChildPolicy::Reparent).unwrap();
+        conn.execute(
+            "UPDATE tasks SET deleted_at = '2024-05-01T00:00:00.000Z' WHERE id = 'a2'",
+            [],
+        )
+        .unwrap();
+        delete_task(&mut conn, "a", ChildPolicy::Cascade).unwrap();
+        assert_eq!(list_tasks(&conn).unwrap().len(), 2);
+        assert_eq!(trashed_ids(&conn).len(), 3);
+
+        // a2 was trashed on its own earlier, so it stays in the trash.
+        let restored: Vec<String> = restore_task(&mut conn, "a")
+            .unwrap()
+            .into_iter()
+            .map(|t| t.id)
+            .collect();
+        assert_eq!(restored, vec!["a", "a1"]);
+        assert_eq!(trashed_ids(&conn), vec!["a2"]);
+        assert!(restore_task(&mut conn, "a").is_err());
+    }
+
+    #[test]
+    fn test_restore_under_trashed_parent_goes_top_level() {
+        let mut conn = setup_tree();
+        delete_task(&mut conn, "a", ChildPolicy::Cascade).unwrap();
+
+        let restored = restore_task(&mut conn, "a1").unwrap();
+        assert_eq!(restored[0].parent_id, None);
+        assert_eq!(restored[0].deleted_at, None);
+        assert!(validate_parent(&conn, &subtask("x", "a", 5)).is_err());
+    }
+
+    #[test]
+    fn test_empty_trash_removes_rows_and_blocks() {
+        let mut conn = setup();
+        insert_task(&conn, &task("a")).unwrap();
+        insert_task(&conn, &task("b")).unwrap();
+        conn.execute(
+            "INSERT INTO day_blocks (id, task_id, date, start_min, length_min) VALUES ('blk', 'a', '2024-05-01', 540, 30)",
+            [],
+        )
+        .unwrap();
+        delete_task(&mut conn, "a", ChildPolicy::Reparent).unwrap();
+
+        assert_eq!(empty_trash(&mut conn).unwrap(), 1);
+        assert!(list_trash(&conn).unwrap().is_empty());
+        let rows: i64 = conn
+            .query_row("SELECT COUNT(*) FROM tasks", [], |r| r.get(0))
+            .unwrap();
+        let blocks: i64 = conn
+            .query_row("SELECT COUNT(*) FROM day_blocks", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!((rows, blocks), (1, 0));
+        // The shared "work" tag survives because b still uses it.
+        assert_eq!(tags::list_tags(&conn).unwrap()[0].usage_count, 1);
+
+        let log = audit::list_entries(&conn, 10).unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].action, "empty_trash");
+        assert_eq!(
+            log[0].detail,
+            Some(serde_json::json!({ "tasks": ["a"], "day_blocks": 1, "trashed_before": null }))
+        );
+        // Emptying an empty trash isn't logged.
+        assert_eq!(empty_trash(&mut conn).unwrap(), 0);
+        assert_eq!(audit::list_entries(&conn, 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_retention_purges_only_expired_items() {
+        let mut conn = setup();
+        insert_task(&conn, &task("old")).unwrap();
+        insert_task(&conn, &task("recent")).unwrap();
+        conn.execute_batch(
+            "
+            UPDATE tasks SET deleted_at = '2024-04-01T00:00:00.000Z' WHERE id = 'old';
+            UPDATE tasks SET deleted_at = '2024-05-20T00:00:00.000Z' WHERE id = 'recent';
+            ",
+        )
+        .unwrap();
+        let now = DateTime::parse_from_rfc3339("2024-06-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        conn.execute(
+            "INSERT INTO settings (key, value) VALUES (?1, '0')",
+            params![TRASH_RETENTION_SETTING],
+        )
+        .unwrap();
+        assert_eq!(purge_expired_trash(&mut conn, now).unwrap(), 0);
+
+        conn.execute(
+            "UPDATE settings SET value = '30' WHERE key = ?1",
+            params![TRASH_RETENTION_SETTING],
+        )
+        .unwrap();
+        assert_eq!(purge_expired_trash(&mut conn, now).unwrap(), 1);
+        assert_eq!(trashed_ids(&conn), vec!["recent"]);
+        let log = audit::list_entries(&conn, 10).unwrap();
+        assert_eq!(log[0].action, "purge_expired_trash");
+        assert_eq!(log[0].detail.as_ref().unwrap()["tasks"], serde_json::json!(["old"]));
+    }
+
+    #[test]
+    fn test_trashed_tasks_hidden_from_reads() {
+        let mut conn = setup();
+        insert_task(&conn, &task("a")).unwrap();
+        insert_task(&conn, &task("b")).unwrap();
+        delete_task(&mut conn, "a", ChildPolicy::Reparent).unwrap();
+
+        assert_eq!(list_tasks(&conn).unwrap().len(), 1);
+        assert_eq!(list_tasks_tagged(&conn, "work").unwrap().len(), 1);
+        let page = query_tasks(&conn, &TaskFilter::default(), TaskSort::default(), None, 10).unwrap();
+        assert_eq!(page.tasks.len(), 1);
+        assert_eq!(
+            search::search_tasks(&conn, "task", 10).unwrap().len(),
+            1
+        );
+        assert_eq!(tags::list_tags(&conn).unwrap()[0].usage_count, 1);
+    }
+
+    fn stored(conn: &Connection, id: &str) -> Task {
+        tasks_by_ids(conn, &[id.to_string()]).unwrap().remove(id).unwrap()
+    }
+
+    #[test]
+    fn test_completed_at_follows_done() {
+        let mut conn = setup();
+        let mut t = task("a");
+        insert_task(&conn, &t).unwrap();
+        assert_eq!(stored(&conn, "a").completed_at, None);
+
+        t.done = true;
+        update_task(&mut conn, &t, false).unwrap();
+        let completed_at = stored(&conn, "a").completed_at;
+        assert!(completed_at.is_some());
+
+        // Saving an already-done task keeps the original completion time.
+        t.title = "Renamed".to_string();
+        conn.execute(
+            "UPDATE tasks SET completed_at = '2024-05-01T09:00:00.000Z' WHERE id = 'a'",
+            [],
+        )
+        .unwrap();
+        update_task(&mut conn, &t, false).unwrap();
+        assert_eq!(
+            stored(&conn, "a").completed_at.as_deref(),
+            Some("2024-05-01T09:00:00.000Z")
+        );
+
+        t.done = false;
+        update_task(&mut conn, &t, false).unwrap();
+        assert_eq!(stored(&conn, "a").completed_at, None);
+    }
+
+    #[test]
+    fn test_archive_completed() {
+        let mut conn = setup();
+        for id in ["old", "recent", "open"] {
+            insert_task(&conn, &task(id)).unwrap();
+        }
+        conn.execute_batch(
+            "
+            UPDATE tasks SET done = 1, completed_at = '2024-04-01T09:00:00.000Z' WHERE id = 'old';
+            UPDATE tasks SET done = 1, completed_at = '2024-05-20T09:00:00.000Z' WHERE id = 'recent';
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(archive_completed(&conn, "2024-05-01T00:00:00+02:00").unwrap(), 1);
+        let ids: Vec<String> = list_tasks(&conn).unwrap().into_iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec!["recent", "open"]);
+        assert!(archive_completed(&conn, "last month").is_err());
+
+        // Still there for reporting.
+        let report = TaskFilter {
+            done: Some(true),
+            include_archived: true,
+            completed_before: Some("2024-05-01T00:00:00Z".to_string()),
+            ..TaskFilter::default()
+        };
+        let page = query_tasks(&conn, &report, TaskSort::default(), None, 10).unwrap();
+        assert_eq!(page.tasks.len(), 1);
+        assert!(page.tasks[0].archived_at.is_some());
+
+        // Reopening brings it back.
+        let mut old = stored(&conn, "old");
+        old.done = false;
+        update_task(&mut conn, &old, false).unwrap();
+        assert_eq!(stored(&conn, "old").archived_at, None);
+        assert_eq!(list_tasks(&conn).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_tree_lifts_subtasks_of_archived_parent() {
+        let conn = setup_tree();
+        conn.execute(
+            "UPDATE tasks SET done = 1, completed_at = '2024-04-01T09:00:00.000Z' WHERE id = 'a'",
+            [],
+        )
+        .unwrap();
+        archive_completed(&conn, "2024-05-01T00:00:00Z").unwrap();
+
+        let tree = task_tree(&conn, None).unwrap();
+        let mut roots: Vec<&str> = tree.iter().map(|n| n.task.id.as_str()).collect();
+        roots.sort();
+        assert_eq!(roots, vec!["a1", "a2", "parent"]);
+    }
+}
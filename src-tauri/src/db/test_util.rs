@@ -0,0 +1,60 @@
+//! Fixtures shared by the tests in `db` and the modules built on it.
+
+use super::migrations::run_migrations;
+use crate::models::Task;
+use rusqlite::Connection;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A task that isn't done, estimated at 25 minutes and tagged "work".
+pub fn task(id: &str) -> Task {
+    Task {
+        id: id.to_string(),
+        title: format!("Task {}", id),
+        done: false,
+        is_today: false,
+        est_minutes: 25,
+        notes: None,
+        project_id: None,
+        tags: Some(vec!["work".to_string()]),
+        due: None,
+        priority: 2,
+        created_at: None,
+        recurrence: None,
+        parent_id: None,
+        blocked: false,
+        deleted_at: None,
+        completed_at: None,
+        archived_at: None,
+    }
+}
+
+/// A new, empty directory under the system temp dir. `name` keeps tests apart.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    let dir = std::env::temp_dir().join(format!(
+        "cadence-{}-{}-{}",
+        name,
+        std::process::id(),
+        nanos
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A migrated `cadence.db` in a new temp directory, for tests that need a file on disk
+/// (backups are written next to it).
+pub fn temp_db(name: &str) -> (PathBuf, Connection) {
+    let dir = temp_dir(name);
+    let mut conn = Connection::open(dir.join("cadence.db")).unwrap();
+    run_migrations(&mut conn).unwrap();
+    (dir, conn)
+}
+
+pub fn add_project(conn: &Connection, name: &str) {
+    conn.execute("INSERT INTO projects (id, name) VALUES (?1, ?1)", [name])
+        .unwrap();
+}
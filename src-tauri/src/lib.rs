@@ -4,6 +4,9 @@
 mod commands;
 mod db;
 mod models;
+mod recurrence;
+mod scheduler;
+mod timer;
 
 use tauri::Manager;
 
@@ -21,19 +24,76 @@ pub fn run() -> tauri::Result<()> {
     tauri::Builder::default()
         .setup(|app| {
             let handle = app.handle();
-            let db = db::init_db(handle).expect("failed to initialize database");
+            let (db, recovery) = db::init_db(handle);
             app.manage(db);
+            app.manage(recovery);
+            app.manage(timer::TimerService::new());
+
+            let ticker = handle.clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(1));
+                loop {
+                    interval.tick().await;
+                    timer::tick(&ticker);
+                }
+            });
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             greet,
             commands::get_tasks,
+            commands::query_tasks,
+            commands::search_tasks,
             commands::add_task,
             commands::update_task,
             commands::delete_task,
+            commands::archive_completed,
+            commands::list_trash,
+            commands::restore_task,
+            commands::empty_trash,
+            commands::get_task_tree,
+            commands::add_dependency,
+            commands::remove_dependency,
+            commands::get_dependencies,
+            commands::expand_recurrences,
+            commands::get_projects,
+            commands::create_project,
+            commands::update_project,
+            commands::rename_project,
+            commands::delete_project,
+            commands::list_tags,
+            commands::rename_tag,
+            commands::merge_tags,
             commands::get_blocks_for_date,
+            commands::get_blocks_in_range,
             commands::save_blocks_for_date,
+            commands::create_block,
+            commands::move_block,
+            commands::resize_block,
+            commands::delete_block,
+            commands::auto_schedule,
+            commands::log_session,
+            commands::get_sessions,
+            commands::delete_session,
+            commands::undo_last,
+            commands::redo,
+            commands::task_history,
+            commands::timer_start,
+            commands::timer_pause,
+            commands::timer_resume,
+            commands::timer_stop,
+            commands::timer_status,
             commands::purge_all_data,
+            commands::get_audit_log,
+            commands::get_recovery_status,
+            commands::recover_from_backup,
+            commands::export_salvage,
+            commands::list_backups,
+            commands::create_backup,
+            commands::restore_backup,
+            commands::migrate_database,
+            commands::get_availability,
+            commands::set_availability,
             commands::get_settings,
             commands::update_setting,
             commands::llm_enrich,
@@ -8,18 +8,250 @@ pub struct Task {
     pub is_today: bool,
     pub est_minutes: i32,
     pub notes: Option<String>,
-    pub project: Option<String>,
+    pub project_id: Option<String>,
     pub tags: Option<Vec<String>>,
     pub due: Option<String>, // YYYY-MM-DD
+    #[serde(default = "default_priority")]
+    pub priority: i32, // 1 (high) ..= 3 (low)
+    #[serde(rename = "createdAt", default)]
+    pub created_at: Option<String>, // RFC 3339
+    #[serde(default)]
+    pub recurrence: Option<String>, // RRULE, e.g. "FREQ=WEEKLY;BYDAY=MO,WE"
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    #[serde(default)]
+    pub blocked: bool, // computed: some prerequisite isn't done yet
+    #[serde(default)]
+    pub deleted_at: Option<String>, // RFC 3339; set while the task is in the trash
+    #[serde(default)]
+    pub completed_at: Option<String>, // RFC 3339; set by the backend when `done` turns true
+    #[serde(default)]
+    pub archived_at: Option<String>, // RFC 3339; archived tasks are left out of default lists
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct DayBlock {
+pub fn default_priority() -> i32 {
+    2
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Project {
     pub id: String,
+    pub name: String,
+    pub color: Option<String>, // CSS color, e.g. "#4f46e5"
+    pub archived: bool,
+    pub sort_order: i32,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Tag {
+    pub id: String,
+    pub name: String,
+    pub usage_count: i64, // number of tasks carrying the tag
+}
+
+/// How `tags` in a [`TaskFilter`] combine.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TagMatch {
+    #[default]
+    Any,
+    All,
+}
+
+/// Conditions for `query_tasks`; unset fields don't filter.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct TaskFilter {
+    pub done: Option<bool>,
+    pub is_today: Option<bool>,
+    pub project_id: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>, // matched ignoring case
+    #[serde(default)]
+    pub tag_match: TagMatch,
+    pub due_before: Option<String>, // YYYY-MM-DD, exclusive
+    pub due_after: Option<String>,  // YYYY-MM-DD, exclusive
+    pub priority: Option<i32>,
+    pub text: Option<String>, // full-text, same rules as search_tasks
+    pub completed_before: Option<String>, // RFC 3339, exclusive
+    pub completed_after: Option<String>,  // RFC 3339, exclusive
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskSortField {
+    #[default]
+    Created,
+    Due, // tasks without a due date last
+    Priority,
+    Title,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct TaskSort {
+    #[serde(default)]
+    pub by: TaskSortField,
+    #[serde(default)]
+    pub descending: bool,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TaskPage {
+    pub tasks: Vec<Task>,
+    pub next_cursor: Option<String>, // pass back to get the following page; None on the last page
+}
+
+/// A task matching a search, with the best-matching text highlighted.
+#[derive(Serialize, Debug, Clone)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    pub task: Task,
+    pub snippet: String, // matches wrapped in <mark></mark>
+    pub score: f64,      // bm25 relevance, higher is better
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TaskNode {
+    #[serde(flatten)]
+    pub task: Task,
+    pub rollup_est_minutes: i32, // own estimate for leaves, sum of children otherwise
+    pub children: Vec<TaskNode>,
+}
+
+/// What happens to a deleted task's subtasks.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ChildPolicy {
+    Cascade,
+    #[default]
+    Reparent, // move them up to the deleted task's parent
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Occurrence {
     pub task_id: String,
     pub date: String, // YYYY-MM-DD
-    pub start_slot: i32,
-    pub end_slot: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DayBlock {
+    pub id: String,
+    pub task_id: Option<String>, // set for atomic blocks
+    pub date: String, // YYYY-MM-DD
+    pub start_min: i32, // minutes from midnight
+    pub length_min: i32,
+    #[serde(default = "default_block_kind")]
+    pub kind: String, // "atomic" | "work"
+    #[serde(default)]
+    pub items: Vec<WorkItem>, // set for work blocks
+    #[serde(default)]
+    pub version: i64, // bumped on every change
+}
+
+/// One day's blocks and how many minutes they take up.
+#[derive(Serialize, Debug, Clone)]
+pub struct DayPlan {
+    pub date: String, // YYYY-MM-DD
+    pub blocks: Vec<DayBlock>,
+    pub planned_min: i32,
+}
+
+pub fn default_block_kind() -> String {
+    "atomic".to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct WorkItem {
+    pub task_id: String,
+    pub est_minutes: i32,
+}
+
+/// How `save_blocks_for_date` treats conflicts.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SaveMode {
+    Strict, // save nothing if there are any
+    #[default]
+    Lenient, // save what can be saved and report the rest
+}
+
+/// A problem with a day's blocks, found before saving them.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BlockConflict {
+    DuplicateId { block_id: String },
+    IdTaken { block_id: String, date: String }, // by a stored block the save won't replace
+    WrongDate { block_id: String, date: String },
+    InvalidLength { block_id: String, length_min: i32 },
+    OutsideDay { block_id: String },
+    Overlap { block_id: String, other_block_id: String },
+    UnknownTask { block_id: String, task_id: String },
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SaveBlocksReport {
+    pub saved: bool,
+    pub conflicts: Vec<BlockConflict>,
+}
+
+/// Working hours for one day of the week.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DayHours {
+    pub weekday: u32, // 0 = Monday
+    pub working: bool,
+    pub start_min: i32, // minutes from midnight
+    pub end_min: i32,
+    pub lunch_start_min: Option<i32>,
+    #[serde(default)]
+    pub lunch_length_min: i32,
+}
+
+/// Time that can't be planned, either every week or on one date.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlockedPeriod {
+    #[serde(default)]
+    pub id: String, // assigned on save when empty
+    pub weekday: Option<u32>, // 0 = Monday
+    pub date: Option<String>, // YYYY-MM-DD
+    pub start_min: i32,
+    pub end_min: i32,
+    pub label: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Availability {
+    pub days: Vec<DayHours>, // one per weekday, Monday first
+    #[serde(default)]
+    pub blocked: Vec<BlockedPeriod>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NewFocusSession {
+    pub kind: String, // "focus" | "break"
+    pub started_at: String, // RFC 3339
+    pub ended_at: String, // RFC 3339
+    pub minutes: i32, // planned length
+    pub completed: bool,
+    #[serde(default)]
+    pub task_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FocusSession {
+    pub id: i64,
+    pub kind: String,
+    pub started_at: String,
+    pub ended_at: String,
+    pub minutes: i32,
+    pub completed: bool,
+    pub task_ids: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SessionRange {
+    pub start: String, // RFC 3339, inclusive
+    pub end: String, // RFC 3339, exclusive
 }
 
 // --- From frontend `types/composer.ts` ---
@@ -66,3 +298,101 @@ pub struct RefineResponse {
     pub assistant_text: String,
     pub suggestions: Vec<RefineSuggestion>,
 }
+
+/// One entity's state before and after a command, as recorded for undo/redo.
+#[derive(Serialize, Debug, Clone)]
+pub struct HistoryEvent {
+    pub id: i64,
+    pub change_id: i64,
+    pub at: String, // RFC 3339
+    pub command: String,
+    pub entity: String, // "task" | "day" | "project" | "session"
+    pub entity_id: String, // task/project/session id, or YYYY-MM-DD for a day's blocks
+    pub before: Option<serde_json::Value>, // None: didn't exist yet
+    pub after: Option<serde_json::Value>,  // None: removed
+    pub undone: bool,
+}
+
+/// Everything one command changed.
+#[derive(Serialize, Debug, Clone)]
+pub struct HistoryChange {
+    pub change_id: i64,
+    pub command: String,
+    pub at: String,
+    pub events: Vec<HistoryEvent>,
+}
+
+/// A destructive operation recorded in the audit log.
+#[derive(Serialize, Debug, Clone)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub at: String, // RFC 3339
+    pub action: String,
+    pub detail: Option<serde_json::Value>,
+}
+
+/// What `purge_all_data` removed, and the backup taken beforehand. Weekly hours aren't
+/// counted: they're always put back to the defaults.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PurgeSummary {
+    pub backup_id: String, // see `list_backups`
+    pub tasks: usize,
+    pub day_blocks: usize,
+    pub projects: usize,
+    pub tags: usize,
+    pub focus_sessions: usize,
+    pub blocked_periods: usize,
+    pub history_events: usize,
+}
+
+/// A backup file next to the database.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BackupInfo {
+    pub id: String, // file name, e.g. "cadence.manual.20240501093000123.db"
+    pub kind: String, // "backup" (taken at launch) | "manual" | "purge" | "prerestore"
+    pub created_at: String, // RFC 3339, local time
+    pub size_bytes: u64,
+}
+
+/// Why the app started in recovery mode.
+#[derive(Serialize, Debug, Clone)]
+pub struct RecoveryReport {
+    pub problems: Vec<String>,
+}
+
+/// How much of one table `export_salvage` could read.
+#[derive(Serialize, Debug, Clone)]
+pub struct SalvagedTable {
+    pub name: String,
+    pub rows: usize,
+    pub error: Option<String>, // why reading stopped early
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SalvageReport {
+    pub path: String,
+    pub tables: Vec<SalvagedTable>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MigrationDirection {
+    Up,
+    Down,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MigrationStep {
+    pub id: i64,
+    pub name: String,
+    pub direction: MigrationDirection,
+}
+
+/// What `migrate_to` did, or with `dry_run` set, would do.
+#[derive(Serialize, Debug, Clone)]
+pub struct MigrationReport {
+    pub from: i64, // highest applied migration before
+    pub to: i64,
+    pub steps: Vec<MigrationStep>,
+    pub dry_run: bool,
+}
@@ -0,0 +1,311 @@
+//! RRULE-style recurrence rules for tasks.
+//!
+//! Supports the subset of RFC 5545 that the planner needs:
+//!
+//! - `FREQ=DAILY;INTERVAL=2` - every other day
+//! - `FREQ=WEEKLY;BYDAY=MO,WE,FR` - on the listed weekdays
+//! - `FREQ=MONTHLY;BYMONTHDAY=31` - on a day of the month (`-1` for the last day)
+//!
+//! Unlike RFC 5545, a `BYMONTHDAY` past the end of a short month is clamped to that
+//! month's last day instead of skipping the month, so "the 31st" lands on Feb 28/29.
+
+use chrono::{Datelike, Duration, Months, NaiveDate, Weekday};
+use std::str::FromStr;
+
+/// Upper bound on iterations when walking a series, so a bad rule can't hang a command.
+const MAX_STEPS: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub freq: Frequency,
+    pub interval: u32,
+    pub by_weekday: Vec<Weekday>,
+    pub by_month_day: Option<i32>,
+}
+
+fn parse_weekday(code: &str) -> Result<Weekday, String> {
+    match code {
+        "MO" => Ok(Weekday::Mon),
+        "TU" => Ok(Weekday::Tue),
+        "WE" => Ok(Weekday::Wed),
+        "TH" => Ok(Weekday::Thu),
+        "FR" => Ok(Weekday::Fri),
+        "SA" => Ok(Weekday::Sat),
+        "SU" => Ok(Weekday::Sun),
+        _ => Err(format!("unknown weekday in recurrence rule: {}", code)),
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let body = s.trim();
+        let body = body.strip_prefix("RRULE:").unwrap_or(body);
+
+        let mut freq = None;
+        let mut interval = 1;
+        let mut by_weekday = Vec::new();
+        let mut by_month_day = None;
+
+        for part in body.split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("malformed recurrence rule part: {}", part))?;
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Frequency::Daily,
+                        "WEEKLY" => Frequency::Weekly,
+                        "MONTHLY" => Frequency::Monthly,
+                        other => {
+                            return Err(format!("unsupported recurrence frequency: {}", other))
+                        }
+                    })
+                }
+                "INTERVAL" => {
+                    interval = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|n| *n > 0)
+                        .ok_or_else(|| format!("invalid recurrence interval: {}", value))?
+                }
+                "BYDAY" => {
+                    for code in value.split(',') {
+                        let day = parse_weekday(&code.trim().to_ascii_uppercase())?;
+                        if !by_weekday.contains(&day) {
+                            by_weekday.push(day);
+                        }
+                    }
+                }
+                "BYMONTHDAY" => {
+                    let day = value
+                        .parse::<i32>()
+                        .ok()
+                        .filter(|d| (1..=31).contains(d) || *d == -1)
+                        .ok_or_else(|| format!("invalid recurrence month day: {}", value))?;
+                    by_month_day = Some(day);
+                }
+                other => return Err(format!("unsupported recurrence rule part: {}", other)),
+            }
+        }
+
+        let freq = freq.ok_or("recurrence rule is missing FREQ")?;
+        if !by_weekday.is_empty() && freq != Frequency::Weekly {
+            return Err("BYDAY is only supported with FREQ=WEEKLY".to_string());
+        }
+        if by_month_day.is_some() && freq != Frequency::Monthly {
+            return Err("BYMONTHDAY is only supported with FREQ=MONTHLY".to_string());
+        }
+        by_weekday.sort_by_key(|d| d.num_days_from_monday());
+
+        Ok(Rule {
+            freq,
+            interval,
+            by_weekday,
+            by_month_day,
+        })
+    }
+}
+
+fn last_day_of_month(year: i32, month: u32) -> u32 {
+    let first_of_next = if month == 12 {
+        NaiveDate::from_ymd_opt(year + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd_opt(year, month + 1, 1)
+    };
+    first_of_next.unwrap().pred_opt().unwrap().day()
+}
+
+fn month_day(year: i32, month: u32, wanted: i32) -> NaiveDate {
+    let last = last_day_of_month(year, month);
+    let day = if wanted == -1 {
+        last
+    } else {
+        (wanted as u32).min(last)
+    };
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+impl Rule {
+    /// Dates in the `step`-th period of the series, or `None` once past chrono's date range.
+    fn period(&self, anchor: NaiveDate, step: i64) -> Option<Vec<NaiveDate>> {
+        let offset = step.checked_mul(self.interval as i64)?;
+        match self.freq {
+            Frequency::Daily => Some(vec![anchor.checked_add_signed(Duration::try_days(offset)?)?]),
+            Frequency::Weekly => {
+                let anchor_weekday = anchor.weekday().num_days_from_monday() as i64;
+                let monday = (anchor - Duration::days(anchor_weekday))
+                    .checked_add_signed(Duration::try_weeks(offset)?)?;
+                if self.by_weekday.is_empty() {
+                    Some(vec![monday + Duration::days(anchor_weekday)])
+                } else {
+                    Some(
+                        self.by_weekday
+                            .iter()
+                            .map(|d| monday + Duration::days(d.num_days_from_monday() as i64))
+                            .collect(),
+                    )
+                }
+            }
+            Frequency::Monthly => {
+                let month = anchor
+                    .with_day(1)?
+                    .checked_add_months(Months::new(u32::try_from(offset).ok()?))?;
+                let wanted = self.by_month_day.unwrap_or(anchor.day() as i32);
+                Some(vec![month_day(month.year(), month.month(), wanted)])
+            }
+        }
+    }
+
+    /// Every date in the series anchored at `anchor`, in order, starting with `anchor` itself
+    /// when it matches the rule.
+    pub fn occurrences(&self, anchor: NaiveDate) -> impl Iterator<Item = NaiveDate> + '_ {
+        (0..MAX_STEPS as i64)
+            .map_while(move |step| self.period(anchor, step))
+            .flatten()
+            .filter(move |d| *d >= anchor)
+    }
+
+    /// The first occurrence strictly after `after`.
+    pub fn next_after(&self, anchor: NaiveDate, after: NaiveDate) -> Option<NaiveDate> {
+        self.occurrences(anchor).find(|d| *d > after)
+    }
+
+    /// Occurrences within `[start, end]`, inclusive on both ends.
+    pub fn between(&self, anchor: NaiveDate, start: NaiveDate, end: NaiveDate) -> Vec<NaiveDate> {
+        self.occurrences(anchor)
+            .skip_while(|d| *d < start)
+            .take_while(|d| *d <= end)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn dates(list: &[&str]) -> Vec<NaiveDate> {
+        list.iter().map(|s| date(s)).collect()
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let rule: Rule = "RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=FR,MO".parse().unwrap();
+        assert_eq!(rule.freq, Frequency::Weekly);
+        assert_eq!(rule.interval, 2);
+        assert_eq!(rule.by_weekday, vec![Weekday::Mon, Weekday::Fri]);
+
+        assert!("FREQ=YEARLY".parse::<Rule>().is_err());
+        assert!("INTERVAL=2".parse::<Rule>().is_err());
+        assert!("FREQ=DAILY;INTERVAL=0".parse::<Rule>().is_err());
+        assert!("FREQ=DAILY;BYDAY=MO".parse::<Rule>().is_err());
+        assert!("FREQ=MONTHLY;BYMONTHDAY=32".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn test_daily_with_interval() {
+        let rule: Rule = "FREQ=DAILY;INTERVAL=3".parse().unwrap();
+        assert_eq!(
+            rule.between(date("2024-01-01"), date("2024-01-01"), date("2024-01-10")),
+            dates(&["2024-01-01", "2024-01-04", "2024-01-07", "2024-01-10"])
+        );
+        assert_eq!(
+            rule.next_after(date("2024-01-01"), date("2024-01-05")),
+            Some(date("2024-01-07"))
+        );
+    }
+
+    #[test]
+    fn test_daily_across_leap_day() {
+        let rule: Rule = "FREQ=DAILY".parse().unwrap();
+        assert_eq!(
+            rule.between(date("2024-02-27"), date("2024-02-28"), date("2024-03-01")),
+            dates(&["2024-02-28", "2024-02-29", "2024-03-01"])
+        );
+        assert_eq!(
+            rule.between(date("2023-02-27"), date("2023-02-28"), date("2023-03-01")),
+            dates(&["2023-02-28", "2023-03-01"])
+        );
+    }
+
+    #[test]
+    fn test_weekly_on_weekdays() {
+        // 2024-05-01 is a Wednesday.
+        let rule: Rule = "FREQ=WEEKLY;BYDAY=MO,WE,FR".parse().unwrap();
+        assert_eq!(
+            rule.between(date("2024-05-01"), date("2024-05-01"), date("2024-05-08")),
+            dates(&["2024-05-01", "2024-05-03", "2024-05-06", "2024-05-08"])
+        );
+    }
+
+    #[test]
+    fn test_biweekly_skips_off_weeks() {
+        let rule: Rule = "FREQ=WEEKLY;INTERVAL=2;BYDAY=TU".parse().unwrap();
+        assert_eq!(
+            rule.between(date("2024-05-01"), date("2024-05-01"), date("2024-05-31")),
+            dates(&["2024-05-14", "2024-05-28"])
+        );
+    }
+
+    #[test]
+    fn test_weekly_defaults_to_anchor_weekday() {
+        let rule: Rule = "FREQ=WEEKLY".parse().unwrap();
+        assert_eq!(
+            rule.next_after(date("2024-05-01"), date("2024-05-01")),
+            Some(date("2024-05-08"))
+        );
+    }
+
+    #[test]
+    fn test_monthly_clamps_to_month_end() {
+        let rule: Rule = "FREQ=MONTHLY;BYMONTHDAY=31".parse().unwrap();
+        assert_eq!(
+            rule.between(date("2024-01-31"), date("2024-01-01"), date("2024-05-31")),
+            dates(&[
+                "2024-01-31",
+                "2024-02-29",
+                "2024-03-31",
+                "2024-04-30",
+                "2024-05-31"
+            ])
+        );
+    }
+
+    #[test]
+    fn test_monthly_last_day_in_leap_and_common_years() {
+        let rule: Rule = "FREQ=MONTHLY;BYMONTHDAY=-1".parse().unwrap();
+        assert_eq!(
+            rule.next_after(date("2024-01-31"), date("2024-01-31")),
+            Some(date("2024-02-29"))
+        );
+        assert_eq!(
+            rule.next_after(date("2023-01-31"), date("2023-01-31")),
+            Some(date("2023-02-28"))
+        );
+        assert_eq!(
+            rule.next_after(date("2100-01-31"), date("2100-01-31")),
+            Some(date("2100-02-28"))
+        );
+    }
+
+    #[test]
+    fn test_monthly_defaults_to_anchor_day_and_wraps_year() {
+        let rule: Rule = "FREQ=MONTHLY;INTERVAL=2".parse().unwrap();
+        assert_eq!(
+            rule.between(date("2024-11-15"), date("2024-11-01"), date("2025-03-31")),
+            dates(&["2024-11-15", "2025-01-15", "2025-03-15"])
+        );
+    }
+}
@@ -0,0 +1,593 @@
+//! Automatic placement of tasks into a day's free time.
+//!
+//! [`plan_day`] is pure: given the tasks, the day's existing blocks, its working hours and
+//! what each task waits on, it proposes atomic blocks without touching the database.
+//! [`auto_schedule`] loads those inputs for a date, taking the hours, lunch break and blocked
+//! periods from the saved availability.
+//!
+//! Tasks are placed most urgent first: anything due on or before the day, then by priority,
+//! due date and longest estimate, with the id as a final tie-break so the same inputs always
+//! give the same plan. Each goes into the earliest gap that fits it, keeping `break_min`
+//! clear on either side of every block and unavailable period. A task that waits on
+//! unfinished tasks can't start until all of their blocks have ended, and isn't placed at
+//! all if one of them isn't planned to finish by the end of the day.
+
+use crate::db::availability::{self, WorkingDay};
+use crate::db::{blocks, tasks, DbError};
+use crate::models::{DayBlock, Task};
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+/// Shortest and longest block a task is given, whatever its estimate.
+const MIN_BLOCK_MIN: i32 = 5;
+const MAX_BLOCK_MIN: i32 = 180;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct ScheduleOptions {
+    pub day_start_min: Option<i32>, // overrides the saved working hours, minutes from midnight
+    pub day_end_min: Option<i32>,
+    pub break_min: i32,              // kept free between any two blocks
+    pub not_before_min: Option<i32>, // e.g. the current time when planning today
+}
+
+impl Default for ScheduleOptions {
+    fn default() -> Self {
+        ScheduleOptions {
+            day_start_min: None,
+            day_end_min: None,
+            break_min: 5,
+            not_before_min: None,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Unscheduled {
+    pub task_id: String,
+    pub reason: String,
+}
+
+/// Proposed blocks, not yet saved, and the tasks that couldn't be placed.
+#[derive(Serialize, Debug, Clone)]
+pub struct Schedule {
+    pub blocks: Vec<DayBlock>,
+    pub unscheduled: Vec<Unscheduled>,
+}
+
+impl ScheduleOptions {
+    /// The saved hours for a day (`None` if it isn't worked) with any overrides applied. An
+    /// override makes a day off workable, from 9 to 5 unless both ends are given.
+    pub fn hours(&self, saved: Option<WorkingDay>) -> Option<WorkingDay> {
+        if self.day_start_min.is_none() && self.day_end_min.is_none() {
+            return saved;
+        }
+        let mut day = saved.unwrap_or_default();
+        day.start_min = self.day_start_min.unwrap_or(day.start_min);
+        day.end_min = self.day_end_min.unwrap_or(day.end_min);
+        Some(day)
+    }
+
+    fn validate(&self, day: &WorkingDay) -> Result<(), String> {
+        if !(0..=24 * 60).contains(&day.start_min)
+            || !(0..=24 * 60).contains(&day.end_min)
+            || day.start_min >= day.end_min
+        {
+            return Err(format!(
+                "Working hours must be within the day and end after they start, got {}..{}",
+                day.start_min, day.end_min
+            ));
+        }
+        if self.break_min < 0 {
+            return Err(format!("Break can't be negative, got {}", self.break_min));
+        }
+        Ok(())
+    }
+}
+
+/// Block length for a task: its estimate rounded to 5 minutes, within 5..=180.
+pub fn block_length(task: &Task) -> i32 {
+    let rounded = (task.est_minutes as f64 / 5.0).round() as i32 * 5;
+    rounded.clamp(MIN_BLOCK_MIN, MAX_BLOCK_MIN)
+}
+
+fn urgency<'a>(task: &'a Task, date: &str) -> impl Ord + 'a {
+    let due = task.due.as_deref().and_then(tasks::due_date);
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok();
+    let due_by_day = matches!((due, date), (Some(due), Some(date)) if due <= date);
+    (
+        !due_by_day,
+        task.priority,
+        due.is_none(),
+        due,
+        Reverse(block_length(task)),
+        &task.id,
+    )
+}
+
+/// Earliest start at or after `earliest` where `length` minutes fit before the end of the
+/// working day, at least `break_min` away from every interval in `busy` (sorted by start).
+fn find_slot(
+    busy: &[(i32, i32)],
+    earliest: i32,
+    length: i32,
+    day: &WorkingDay,
+    options: &ScheduleOptions,
+) -> Option<i32> {
+    let mut start = earliest;
+    for &(busy_start, busy_end) in busy {
+        if start + length + options.break_min <= busy_start {
+            break;
+        }
+        start = start.max(busy_end + options.break_min);
+    }
+    (start + length <= day.end_min).then_some(start)
+}
+
+/// Proposes blocks on `date` for `tasks` within `day`'s hours, around the `existing` blocks
+/// and its unavailable periods.
+///
+/// `waits_on` lists each task's unfinished prerequisites. `prerequisite_ends` says when on
+/// `date` each prerequisite's last block ends (0 if that was on an earlier day); one that's
+/// missing isn't planned to finish by the end of the day. Prerequisites placed in this run
+/// count too.
+pub fn plan_day(
+    date: &str,
+    tasks: &[Task],
+    existing: &[DayBlock],
+    day: &WorkingDay,
+    waits_on: &HashMap<String, Vec<String>>,
+    prerequisite_ends: &HashMap<String, i32>,
+    options: &ScheduleOptions,
+) -> Result<Schedule, String> {
+    options.validate(day)?;
+
+    let mut busy: Vec<(i32, i32)> = existing
+        .iter()
+        .map(|b| (b.start_min, b.start_min + b.length_min))
+        .chain(day.unavailable.iter().copied())
+        .collect();
+    busy.sort();
+    let planned: HashSet<&str> = existing
+        .iter()
+        .flat_map(|b| {
+            b.task_id
+                .as_deref()
+                .into_iter()
+                .chain(b.items.iter().map(|i| i.task_id.as_str()))
+        })
+        .collect();
+    let mut ends = prerequisite_ends.clone();
+    let mut schedule = Schedule {
+        blocks: Vec::new(),
+        unscheduled: Vec::new(),
+    };
+    let skip = |task: &Task, reason: String| Unscheduled {
+        task_id: task.id.clone(),
+        reason,
+    };
+
+    let mut pending: Vec<&Task> = Vec::new();
+    for task in tasks {
+        let reason = if task.done {
+            Some("Already done".to_string())
+        } else if task.deleted_at.is_some() {
+            Some("In the trash".to_string())
+        } else if planned.contains(task.id.as_str()) {
+            Some("Already planned for this day".to_string())
+        } else {
+            None
+        };
+        match reason {
+            Some(reason) => schedule.unscheduled.push(skip(task, reason)),
+            None if !pending.iter().any(|p| p.id == task.id) => pending.push(task),
+            None => {}
+        }
+    }
+    pending.sort_by(|a, b| urgency(a, date).cmp(&urgency(b, date)));
+
+    let no_prerequisites = Vec::new();
+    while !pending.is_empty() {
+        // Most urgent task whose prerequisites in this batch have had their turn.
+        let next = pending
+            .iter()
+            .position(|task| {
+                waits_on
+                    .get(&task.id)
+                    .unwrap_or(&no_prerequisites)
+                    .iter()
+                    .all(|p| !pending.iter().any(|other| other.id == *p))
+            })
+            .unwrap_or(0);
+        let task = pending.remove(next);
+        let prerequisites = waits_on.get(&task.id).unwrap_or(&no_prerequisites);
+
+        if let Some(waiting) = prerequisites.iter().find(|p| !ends.contains_key(*p)) {
+            schedule.unscheduled.push(skip(
+                task,
+                format!(
+                    "Waits on {}, which isn't planned to finish by then",
+                    waiting
+                ),
+            ));
+            continue;
+        }
+        let ready = prerequisites.iter().map(|p| ends[p]).max().unwrap_or(0);
+        let earliest = day
+            .start_min
+            .max(options.not_before_min.unwrap_or(0))
+            .max(ready);
+
+        let length = block_length(task);
+        match find_slot(&busy, earliest, length, day, options) {
+            Some(start) => {
+                let position = busy.partition_point(|&(s, _)| s <= start);
+                busy.insert(position, (start, start + length));
+                ends.insert(task.id.clone(), start + length);
+                schedule.blocks.push(DayBlock {
+                    id: Uuid::new_v4().to_string(),
+                    task_id: Some(task.id.clone()),
+                    date: date.to_string(),
+                    start_min: start,
+                    length_min: length,
+                    kind: "atomic".to_string(),
+                    items: Vec::new(),
+                    version: 0,
+                });
+            }
+            None => schedule.unscheduled.push(skip(
+                task,
+                format!("No free time left that fits {} minutes", length),
+            )),
+        }
+    }
+    schedule.blocks.sort_by_key(|b| b.start_min);
+    Ok(schedule)
+}
+
+/// When the last block of `task_id` ends, relative to `date`: `Some(0)` if on an earlier
+/// day, the minute if on `date`, `None` if it has none or its last is on a later day.
+fn planned_end(conn: &Connection, task_id: &str, date: &str) -> Result<Option<i32>, DbError> {
+    let last: Option<(String, i32)> = conn
+        .query_row(
+            "SELECT date, MAX(start_min + length_min) FROM (
+                 SELECT date, start_min, length_min FROM day_blocks WHERE task_id = ?1
+                 UNION ALL
+                 SELECT d.date, d.start_min, d.length_min
+                 FROM block_items b JOIN day_blocks d ON d.id = b.block_id
+                 WHERE b.task_id = ?1
+             )
+             GROUP BY date ORDER BY date DESC LIMIT 1",
+            params![task_id],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )
+        .optional()?;
+    Ok(match last {
+        Some((day, _)) if day.as_str() < date => Some(0),
+        Some((day, end)) if day == date => Some(end),
+        _ => None,
+    })
+}
+
+/// Proposes blocks on `date` (YYYY-MM-DD) for `task_ids`. Nothing is saved.
+pub fn auto_schedule(
+    conn: &Connection,
+    date: &str,
+    task_ids: &[String],
+    options: &ScheduleOptions,
+) -> Result<Schedule, DbError> {
+    let parsed = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| DbError::Invalid(format!("Invalid date: {}", date)))?;
+
+    let mut found = tasks::tasks_by_ids(conn, task_ids)?;
+    let mut missing = Vec::new();
+    let mut requested = Vec::new();
+    for id in task_ids {
+        match found.remove(id) {
+            Some(task) => requested.push(task),
+            None if !requested.iter().any(|t: &Task| t.id == *id) => missing.push(Unscheduled {
+                task_id: id.clone(),
+                reason: "Task not found".to_string(),
+            }),
+            None => {}
+        }
+    }
+
+    let mut waits_on: HashMap<String, Vec<String>> = HashMap::new();
+    let mut stmt = conn.prepare(
+        "SELECT d.depends_on_id FROM task_dependencies d
+         JOIN tasks p ON p.id = d.depends_on_id
+         WHERE d.task_id = ?1 AND p.done = 0 AND p.deleted_at IS NULL
+         ORDER BY d.depends_on_id",
+    )?;
+    for task in &requested {
+        let prerequisites = stmt
+            .query_map(params![task.id], |r| r.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        if !prerequisites.is_empty() {
+            waits_on.insert(task.id.clone(), prerequisites);
+        }
+    }
+    let mut prerequisite_ends = HashMap::new();
+    for id in waits_on.values().flatten() {
+        if let Some(end) = planned_end(conn, id, date)? {
+            prerequisite_ends.insert(id.clone(), end);
+        }
+    }
+
+    let Some(day) = options.hours(availability::working_day(conn, parsed)?) else {
+        let mut unscheduled: Vec<Unscheduled> = requested
+            .iter()
+            .map(|task| Unscheduled {
+                task_id: task.id.clone(),
+                reason: "Not a working day".to_string(),
+            })
+            .collect();
+        unscheduled.extend(missing);
+        return Ok(Schedule {
+            blocks: Vec::new(),
+            unscheduled,
+        });
+    };
+    let existing = blocks::list_blocks_for_date(conn, date)?;
+    let mut schedule = plan_day(
+        date,
+        &requested,
+        &existing,
+        &day,
+        &waits_on,
+        &prerequisite_ends,
+        options,
+    )
+    .map_err(DbError::Invalid)?;
+    schedule.unscheduled.extend(missing);
+    Ok(schedule)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_util;
+
+    const DATE: &str = "2024-05-01";
+
+    fn task(id: &str, est_minutes: i32) -> Task {
+        Task {
+            est_minutes,
+            ..test_util::task(id)
+        }
+    }
+
+    fn block(task_id: &str, start_min: i32, length_min: i32) -> DayBlock {
+        DayBlock {
+            id: format!("block-{}", task_id),
+            task_id: Some(task_id.to_string()),
+            date: DATE.to_string(),
+            start_min,
+            length_min,
+            kind: "atomic".to_string(),
+            items: Vec::new(),
+            version: 0,
+        }
+    }
+
+    fn placed(schedule: &Schedule) -> Vec<(&str, i32, i32)> {
+        schedule
+            .blocks
+            .iter()
+            .map(|b| (b.task_id.as_deref().unwrap(), b.start_min, b.length_min))
+            .collect()
+    }
+
+    fn plan(tasks: &[Task], existing: &[DayBlock], options: &ScheduleOptions) -> Schedule {
+        plan_day(
+            DATE,
+            tasks,
+            existing,
+            &WorkingDay::default(),
+            &HashMap::new(),
+            &HashMap::new(),
+            options,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_fills_gaps_around_existing_blocks_with_breaks() {
+        let existing = [block("meeting", 600, 60), block("lunch", 720, 60)];
+        let options = ScheduleOptions {
+            break_min: 10,
+            ..Default::default()
+        };
+        let schedule = plan(
+            &[task("a", 50), task("b", 45), task("c", 30)],
+            &existing,
+            &options,
+        );
+        // b doesn't fit between the blocks once both breaks are kept, but the shorter c does.
+        assert_eq!(
+            placed(&schedule),
+            vec![("a", 540, 50), ("c", 670, 30), ("b", 790, 45)]
+        );
+        assert!(schedule.unscheduled.is_empty());
+    }
+
+    #[test]
+    fn test_urgent_tasks_go_first() {
+        let mut low = task("low", 60);
+        low.priority = 3;
+        let mut high = task("high", 30);
+        high.priority = 1;
+        let mut due = task("due", 30);
+        due.priority = 3;
+        due.due = Some("2024-04-30".to_string());
+        let options = ScheduleOptions {
+            break_min: 0,
+            ..Default::default()
+        };
+
+        let schedule = plan(&[low, high, due], &[], &options);
+        assert_eq!(
+            placed(&schedule),
+            vec![("due", 540, 30), ("high", 570, 30), ("low", 600, 60)]
+        );
+    }
+
+    #[test]
+    fn test_respects_working_hours_and_reports_leftovers() {
+        let day = WorkingDay {
+            start_min: 8 * 60,
+            end_min: 11 * 60,
+            unavailable: vec![(9 * 60 + 30, 10 * 60 + 20)],
+        };
+        let options = ScheduleOptions {
+            break_min: 0,
+            not_before_min: Some(8 * 60 + 30),
+            ..Default::default()
+        };
+        let mut done = task("done", 30);
+        done.done = true;
+        let schedule = plan_day(
+            DATE,
+            &[task("a", 60), task("b", 45), task("c", 33), done],
+            &[block("c", 900, 30)],
+            &day,
+            &HashMap::new(),
+            &HashMap::new(),
+            &options,
+        )
+        .unwrap();
+        // b would fit after the unavailable period if it weren't for the end of the day.
+        assert_eq!(placed(&schedule), vec![("a", 510, 60)]);
+        let reasons: Vec<(&str, &str)> = schedule
+            .unscheduled
+            .iter()
+            .map(|u| (u.task_id.as_str(), u.reason.as_str()))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                ("c", "Already planned for this day"),
+                ("done", "Already done"),
+                ("b", "No free time left that fits 45 minutes"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blocked_tasks_wait_for_prerequisites() {
+        let options = ScheduleOptions {
+            break_min: 0,
+            ..Default::default()
+        };
+        let mut urgent = task("urgent", 30);
+        urgent.priority = 1;
+        let waits_on = HashMap::from([
+            ("urgent".to_string(), vec!["prep".to_string()]),
+            ("later".to_string(), vec!["elsewhere".to_string()]),
+            ("stuck".to_string(), vec!["unplanned".to_string()]),
+        ]);
+        // "elsewhere" is already planned to end at 13:00 today.
+        let ends = HashMap::from([("elsewhere".to_string(), 780)]);
+        let tasks = [
+            urgent,
+            task("prep", 60),
+            task("later", 15),
+            task("stuck", 15),
+        ];
+
+        let day = WorkingDay::default();
+        let schedule = plan_day(DATE, &tasks, &[], &day, &waits_on, &ends, &options).unwrap();
+        assert_eq!(
+            placed(&schedule),
+            vec![("prep", 540, 60), ("urgent", 600, 30), ("later", 780, 15)]
+        );
+        assert_eq!(schedule.unscheduled.len(), 1);
+        assert_eq!(schedule.unscheduled[0].task_id, "stuck");
+    }
+
+    #[test]
+    fn test_same_inputs_same_plan() {
+        let tasks: Vec<Task> = ["d", "b", "c", "a"].iter().map(|id| task(id, 30)).collect();
+        let reversed: Vec<Task> = tasks.iter().rev().cloned().collect();
+        let options = ScheduleOptions::default();
+        assert_eq!(
+            placed(&plan(&tasks, &[], &options)),
+            placed(&plan(&reversed, &[], &options))
+        );
+        assert_eq!(block_length(&task("x", 2)), 5);
+        assert_eq!(block_length(&task("x", 23)), 25);
+        assert_eq!(block_length(&task("x", 600)), 180);
+    }
+
+    #[test]
+    fn test_rejects_bad_options() {
+        let options = ScheduleOptions {
+            day_start_min: Some(17 * 60),
+            day_end_min: Some(9 * 60),
+            ..Default::default()
+        };
+        let day = options.hours(None).unwrap();
+        let (waits_on, ends) = (HashMap::new(), HashMap::new());
+        assert!(plan_day(DATE, &[], &[], &day, &waits_on, &ends, &options).is_err());
+    }
+
+    #[test]
+    fn test_auto_schedule_reads_prerequisite_blocks() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        crate::db::migrations::run_migrations(&mut conn).unwrap();
+        conn.execute_batch(
+            "
+            INSERT INTO tasks (id, title, est_minutes) VALUES ('prep', 'Prep', 60);
+            INSERT INTO tasks (id, title, est_minutes) VALUES ('old', 'Old', 30);
+            INSERT INTO tasks (id, title, est_minutes) VALUES ('next', 'Next', 30);
+            INSERT INTO tasks (id, title, est_minutes, done) VALUES ('finished', 'Finished', 30, 1);
+            INSERT INTO tasks (id, title, est_minutes) VALUES ('a', 'After prep', 30);
+            INSERT INTO tasks (id, title, est_minutes) VALUES ('b', 'After old', 30);
+            INSERT INTO tasks (id, title, est_minutes) VALUES ('c', 'After next', 30);
+            INSERT INTO tasks (id, title, est_minutes) VALUES ('d', 'After finished', 30);
+            INSERT INTO task_dependencies (task_id, depends_on_id) VALUES
+                ('a', 'prep'), ('b', 'old'), ('c', 'next'), ('d', 'finished');
+            INSERT INTO day_blocks (id, task_id, date, start_min, length_min, kind) VALUES
+                ('b1', 'prep', '2024-05-01', 540, 60, 'atomic'),
+                ('b2', 'old', '2024-04-30', 600, 30, 'atomic'),
+                ('b3', 'next', '2024-05-02', 600, 30, 'atomic');
+            ",
+        )
+        .unwrap();
+
+        let ids: Vec<String> = ["a", "b", "c", "d", "ghost"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let schedule = auto_schedule(&conn, DATE, &ids, &ScheduleOptions::default()).unwrap();
+        assert_eq!(
+            placed(&schedule),
+            vec![("a", 605, 30), ("b", 640, 30), ("d", 675, 30)]
+        );
+        let skipped: Vec<&str> = schedule
+            .unscheduled
+            .iter()
+            .map(|u| u.task_id.as_str())
+            .collect();
+        assert_eq!(skipped, vec!["c", "ghost"]);
+        assert_eq!(schedule.unscheduled[1].reason, "Task not found");
+
+        assert!(auto_schedule(&conn, "May 1st", &ids, &ScheduleOptions::default()).is_err());
+
+        // 2024-05-04 is a Saturday, off by default unless hours are given.
+        let ids = vec!["prep".to_string()];
+        let saturday = auto_schedule(&conn, "2024-05-04", &ids, &ScheduleOptions::default());
+        assert_eq!(saturday.unwrap().unscheduled[0].reason, "Not a working day");
+        let options = ScheduleOptions {
+            day_start_min: Some(600),
+            ..Default::default()
+        };
+        let saturday = auto_schedule(&conn, "2024-05-04", &ids, &options).unwrap();
+        assert_eq!(placed(&saturday), vec![("prep", 600, 60)]);
+    }
+}
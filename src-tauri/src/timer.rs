@@ -0,0 +1,305 @@
+use crate::commands::{ensure_ready, CommandError};
+use crate::db::history::{self, Entity};
+use crate::db::{sessions, Database, Recovery};
+use crate::models::NewFocusSession;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use serde::Serialize;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager, Runtime};
+
+pub const TICK_EVENT: &str = "timer:tick";
+pub const COMPLETE_EVENT: &str = "timer:complete";
+/// Carries a `CommandError` when a completed session couldn't be saved.
+pub const ERROR_EVENT: &str = "timer:error";
+
+#[derive(Debug, Clone)]
+enum Phase {
+    Idle,
+    Running { resumed_at: DateTime<Utc> },
+    Paused,
+}
+
+/// Focus/break countdown driven by wall-clock timestamps, so a sleeping or reloaded
+/// webview never drifts it: remaining time is always recomputed from `now`.
+#[derive(Debug, Clone)]
+pub struct Timer {
+    phase: Phase,
+    kind: String, // "focus" | "break"
+    duration: Duration,
+    elapsed_before_resume: Duration,
+    started_at: Option<DateTime<Utc>>,
+    task_ids: Vec<String>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct TimerStatus {
+    pub state: String, // "idle" | "running" | "paused"
+    pub kind: String,
+    pub duration_secs: i64,
+    pub remaining_secs: i64,
+    pub started_at: Option<String>,
+    pub task_ids: Vec<String>,
+}
+
+/// A countdown that ran to zero, ready to be recorded as a session.
+#[derive(Serialize, Debug, Clone)]
+pub struct CompletedInterval {
+    pub kind: String,
+    pub minutes: i32,
+    pub started_at: DateTime<Utc>,
+    pub ended_at: DateTime<Utc>,
+    pub task_ids: Vec<String>,
+}
+
+fn to_iso(t: DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Timer {
+            phase: Phase::Idle,
+            kind: "focus".to_string(),
+            duration: Duration::zero(),
+            elapsed_before_resume: Duration::zero(),
+            started_at: None,
+            task_ids: Vec::new(),
+        }
+    }
+}
+
+impl Timer {
+    fn elapsed(&self, now: DateTime<Utc>) -> Duration {
+        match self.phase {
+            Phase::Running { resumed_at } => self.elapsed_before_resume + (now - resumed_at),
+            _ => self.elapsed_before_resume,
+        }
+    }
+
+    fn remaining(&self, now: DateTime<Utc>) -> Duration {
+        (self.duration - self.elapsed(now)).max(Duration::zero())
+    }
+
+    pub fn start(
+        &mut self,
+        now: DateTime<Utc>,
+        kind: &str,
+        minutes: i32,
+        task_ids: Vec<String>,
+    ) -> Result<(), &'static str> {
+        if kind != "focus" && kind != "break" {
+            return Err("Timer kind must be \"focus\" or \"break\"");
+        }
+        if minutes <= 0 {
+            return Err("Timer length must be at least one minute");
+        }
+        *self = Timer {
+            phase: Phase::Running { resumed_at: now },
+            kind: kind.to_string(),
+            duration: Duration::minutes(minutes as i64),
+            elapsed_before_resume: Duration::zero(),
+            started_at: Some(now),
+            task_ids,
+        };
+        Ok(())
+    }
+
+    pub fn pause(&mut self, now: DateTime<Utc>) -> Result<(), &'static str> {
+        match self.phase {
+            Phase::Running { .. } => {
+                self.elapsed_before_resume = self.elapsed(now);
+                self.phase = Phase::Paused;
+                Ok(())
+            }
+            _ => Err("Timer is not running"),
+        }
+    }
+
+    pub fn resume(&mut self, now: DateTime<Utc>) -> Result<(), &'static str> {
+        match self.phase {
+            Phase::Paused => {
+                self.phase = Phase::Running { resumed_at: now };
+                Ok(())
+            }
+            _ => Err("Timer is not paused"),
+        }
+    }
+
+    pub fn stop(&mut self) {
+        *self = Timer {
+            kind: self.kind.clone(),
+            ..Timer::default()
+        };
+    }
+
+    pub fn status(&self, now: DateTime<Utc>) -> TimerStatus {
+        let state = match self.phase {
+            Phase::Idle => "idle",
+            Phase::Running { .. } => "running",
+            Phase::Paused => "paused",
+        };
+        TimerStatus {
+            state: state.to_string(),
+            kind: self.kind.clone(),
+            duration_secs: self.duration.num_seconds(),
+            remaining_secs: self.remaining(now).num_seconds(),
+            started_at: self.started_at.map(to_iso),
+            task_ids: self.task_ids.clone(),
+        }
+    }
+
+    /// Finishes a running countdown whose time is up, returning the interval it covered.
+    pub fn poll_completion(&mut self, now: DateTime<Utc>) -> Option<CompletedInterval> {
+        let resumed_at = match self.phase {
+            Phase::Running { resumed_at } => resumed_at,
+            _ => return None,
+        };
+        if self.remaining(now) > Duration::zero() {
+            return None;
+        }
+
+        // Use the instant the countdown actually hit zero, not when we noticed.
+        let ended_at = resumed_at + (self.duration - self.elapsed_before_resume);
+        let completed = CompletedInterval {
+            kind: self.kind.clone(),
+            minutes: self.duration.num_minutes() as i32,
+            started_at: self.started_at.unwrap_or(resumed_at),
+            ended_at,
+            task_ids: std::mem::take(&mut self.task_ids),
+        };
+        self.stop();
+        Some(completed)
+    }
+}
+
+pub struct TimerService(pub Mutex<Timer>);
+
+impl TimerService {
+    pub fn new() -> Self {
+        TimerService(Mutex::new(Timer::default()))
+    }
+}
+
+/// Saves a session the timer completed the same way `log_session` does, so it can be undone.
+fn record_session<R: Runtime>(
+    app: &AppHandle<R>,
+    session: &NewFocusSession,
+) -> Result<(), CommandError> {
+    ensure_ready(&app.state::<Recovery>())?;
+    let db = app.state::<Database>();
+    let mut conn = db.0.lock().unwrap();
+    history::record(
+        &mut conn,
+        "log_session",
+        |_| Ok(Vec::new()),
+        |conn| sessions::insert_session(conn, session),
+        |logged| vec![Entity::Session(logged.id)],
+    )?;
+    Ok(())
+}
+
+/// Called once a second from the background ticker set up in `run()`.
+pub fn tick<R: Runtime>(app: &AppHandle<R>) {
+    let now = Utc::now();
+    let (status, completed) = {
+        let service = app.state::<TimerService>();
+        let mut timer = service.0.lock().unwrap();
+        let completed = timer.poll_completion(now);
+        (timer.status(now), completed)
+    };
+
+    if let Some(completed) = completed {
+        if completed.kind == "focus" {
+            let session = NewFocusSession {
+                kind: completed.kind.clone(),
+                started_at: to_iso(completed.started_at),
+                ended_at: to_iso(completed.ended_at),
+                minutes: completed.minutes,
+                completed: true,
+                task_ids: completed.task_ids.clone(),
+            };
+            if let Err(e) = record_session(app, &session) {
+                let _ = app.emit(ERROR_EVENT, e);
+            }
+        }
+        let _ = app.emit(COMPLETE_EVENT, completed);
+    } else if status.state == "running" {
+        let _ = app.emit(TICK_EVENT, status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(h: u32, m: u32, s: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, h, m, s).unwrap()
+    }
+
+    #[test]
+    fn test_remaining_follows_wall_clock() {
+        let mut timer = Timer::default();
+        timer.start(at(9, 0, 0), "focus", 25, vec![]).unwrap();
+
+        assert_eq!(timer.status(at(9, 0, 0)).remaining_secs, 25 * 60);
+        assert_eq!(timer.status(at(9, 10, 30)).remaining_secs, 14 * 60 + 30);
+        assert_eq!(timer.status(at(10, 0, 0)).remaining_secs, 0);
+    }
+
+    #[test]
+    fn test_pause_and_resume() {
+        let mut timer = Timer::default();
+        timer.start(at(9, 0, 0), "focus", 25, vec![]).unwrap();
+        timer.pause(at(9, 10, 0)).unwrap();
+
+        // Time spent paused doesn't count.
+        let status = timer.status(at(9, 40, 0));
+        assert_eq!(status.state, "paused");
+        assert_eq!(status.remaining_secs, 15 * 60);
+
+        timer.resume(at(9, 40, 0)).unwrap();
+        assert_eq!(timer.status(at(9, 45, 0)).remaining_secs, 10 * 60);
+        assert!(timer.pause(at(9, 46, 0)).is_ok());
+        assert!(timer.pause(at(9, 47, 0)).is_err());
+    }
+
+    #[test]
+    fn test_completion_reports_actual_end() {
+        let mut timer = Timer::default();
+        timer
+            .start(at(9, 0, 0), "focus", 25, vec!["t1".to_string()])
+            .unwrap();
+        timer.pause(at(9, 10, 0)).unwrap();
+        timer.resume(at(9, 20, 0)).unwrap();
+
+        assert!(timer.poll_completion(at(9, 34, 59)).is_none());
+
+        // Noticed late (e.g. the machine slept), but the interval ended at 9:35.
+        let completed = timer.poll_completion(at(11, 0, 0)).unwrap();
+        assert_eq!(completed.started_at, at(9, 0, 0));
+        assert_eq!(completed.ended_at, at(9, 35, 0));
+        assert_eq!(completed.minutes, 25);
+        assert_eq!(completed.task_ids, vec!["t1"]);
+
+        assert_eq!(timer.status(at(11, 0, 0)).state, "idle");
+        assert!(timer.poll_completion(at(11, 0, 1)).is_none());
+    }
+
+    #[test]
+    fn test_paused_timer_never_completes() {
+        let mut timer = Timer::default();
+        timer.start(at(9, 0, 0), "break", 5, vec![]).unwrap();
+        timer.pause(at(9, 1, 0)).unwrap();
+
+        assert!(timer.poll_completion(at(12, 0, 0)).is_none());
+    }
+
+    #[test]
+    fn test_start_validates_input() {
+        let mut timer = Timer::default();
+        assert!(timer.start(at(9, 0, 0), "nap", 25, vec![]).is_err());
+        assert!(timer.start(at(9, 0, 0), "focus", 0, vec![]).is_err());
+        assert!(timer.resume(at(9, 0, 0)).is_err());
+    }
+}
@@ -2,6 +2,7 @@ import React, { useState } from "react";
 import { useHotkeys, HotkeysProvider } from "react-hotkeys-hook";
 import {
   DndContext,
+  DragEndEvent,
   DragOverlay,
   DragOverEvent,
   DragStartEvent,
@@ -11,8 +12,10 @@ import {
   useSensors,
 } from "@dnd-kit/core";
 import { todayISO } from "@/lib/utils";
+import { SLOT_MIN, roundToSlot } from "@/lib/time";
 import { DayPeek } from "@/components/calendar/DayPeek";
-import { Task } from "./types";
+import { SLOT_PX } from "@/components/calendar/MiniDayRail";
+import { Block, Task } from "./types";
 import usePlanner from "./state/planner";
 import { LeftNav } from "@/components/nav/LeftNav";
 import HomeDashboard from "@/pages/HomeDashboard";
@@ -22,7 +25,9 @@ import NotesPage from "@/pages/NotesPage";
 import FocusPage from "@/pages/FocusPage";
 import InsightsPage from "@/pages/InsightsPage";
 import SettingsPage from "@/pages/SettingsPage";
-import { Toaster } from "sonner";
+import { RecoveryBanner } from "@/components/RecoveryBanner";
+import { Toaster, toast } from "sonner";
+import { listen } from "@tauri-apps/api/event";
 
 function TaskDragOverlay({ task }: { task: Task }) {
   return (
@@ -41,7 +46,7 @@ export default function AppShell() {
   const [activeView, setActiveView] = useState("home");
   const [activeDragTask, setActiveDragTask] = useState<Task | null>(null);
   const [isOverRail, setIsOverRail] = useState(false);
-  const { setPreviewBlock, addBlock, setIsHoveringMiniDayRail } = usePlanner();
+  const { setPreviewBlock, addBlock, resizeBlock, setIsHoveringMiniDayRail } = usePlanner();
 
   const pages: { [key: string]: React.ReactNode } = {
     home: <HomeDashboard />,
@@ -63,11 +68,30 @@ export default function AppShell() {
 
   const fetchTasks = usePlanner(s => s.fetchTasks);
   const fetchBlocks = usePlanner(s => s.fetchBlocks);
+  const fetchAvailability = usePlanner(s => s.fetchAvailability);
+  const fetchRecoveryStatus = usePlanner(s => s.fetchRecoveryStatus);
+  const recovery = usePlanner(s => s.recovery);
+  const inRecovery = recovery !== null;
 
+  // Data commands fail while the database needs recovery, so load only once it's healthy.
   React.useEffect(() => {
-    fetchTasks();
-    fetchBlocks(todayISO());
-  }, [fetchTasks, fetchBlocks]);
+    fetchRecoveryStatus().then(report => {
+      if (report) return;
+      fetchTasks();
+      fetchBlocks(todayISO());
+      fetchAvailability();
+    });
+  }, [inRecovery, fetchRecoveryStatus, fetchTasks, fetchBlocks, fetchAvailability]);
+
+  // Focus sessions the timer completes are saved in the background.
+  React.useEffect(() => {
+    const unlisten = listen<{ message: string }>("timer:error", event => {
+      toast.error(`Couldn't save the focus session: ${event.payload.message}`);
+    });
+    return () => {
+      unlisten.then(stop => stop());
+    };
+  }, []);
 
   const sensors = useSensors(
     useSensor(PointerSensor, {
@@ -111,8 +135,16 @@ export default function AppShell() {
     }
   }
 
-  const handleDragEnd = () => {
+  const handleDragEnd = (event: DragEndEvent) => {
     setActiveDragTask(null);
+    const activeData = event.active.data.current;
+    if (activeData?.type === 'BLOCK_RESIZE') {
+      const block = activeData.block as Block;
+      const lengthMin = Math.max(SLOT_MIN, block.lengthMin + roundToSlot(event.delta.y / SLOT_PX));
+      if (lengthMin !== block.lengthMin) {
+        resizeBlock(block.id, lengthMin);
+      }
+    }
     if (previewBlock && isOverRail) {
       const { id: _, ...newBlock } = previewBlock;
       addBlock(newBlock, todayISO());
@@ -145,7 +177,7 @@ export default function AppShell() {
           <LeftNav active={activeView} setActive={setActiveView} />
 
           <main className="flex-1 bg-zinc-900/80 overflow-auto min-h-0">
-            {pages[activeView]}
+            {recovery ? <RecoveryBanner report={recovery} /> : pages[activeView]}
           </main>
 
           <DayPeek />
@@ -0,0 +1,51 @@
+import { useState } from "react";
+import { AlertTriangle } from "lucide-react";
+import usePlanner from "@/state/planner";
+import { RecoveryReport } from "@/types";
+
+/** Shown instead of the app while the database fails its startup checks. */
+export function RecoveryBanner({ report }: { report: RecoveryReport }) {
+  const recoverFromBackup = usePlanner(s => s.recoverFromBackup);
+  const exportSalvage = usePlanner(s => s.exportSalvage);
+  const [busy, setBusy] = useState(false);
+
+  const run = async (action: () => Promise<void>) => {
+    setBusy(true);
+    await action();
+    setBusy(false);
+  };
+
+  return (
+    <div className="max-w-xl mx-auto mt-16 rounded-2xl border border-red-500/30 bg-red-500/10 p-6">
+      <div className="flex items-center gap-3 text-red-300">
+        <AlertTriangle size={20} />
+        <h2 className="text-lg font-medium">Your database needs recovery</h2>
+      </div>
+      <p className="mt-3 text-zinc-400">
+        Cadence found problems when it opened your data and has left it untouched. Restore the
+        newest good backup, or export whatever can still be read.
+      </p>
+      <ul className="mt-3 list-disc pl-5 text-xs text-zinc-500">
+        {report.problems.map((problem, i) => (
+          <li key={i}>{problem}</li>
+        ))}
+      </ul>
+      <div className="mt-5 flex gap-2">
+        <button
+          disabled={busy}
+          onClick={() => run(recoverFromBackup)}
+          className="rounded-md bg-red-600 px-3 py-1.5 text-white hover:bg-red-700 disabled:opacity-50"
+        >
+          Restore latest backup
+        </button>
+        <button
+          disabled={busy}
+          onClick={() => run(exportSalvage)}
+          className="rounded-md border border-white/10 px-3 py-1.5 text-zinc-200 hover:bg-white/10 disabled:opacity-50"
+        >
+          Export readable data
+        </button>
+      </div>
+    </div>
+  );
+}
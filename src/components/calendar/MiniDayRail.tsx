@@ -1,12 +1,12 @@
-import { useDroppable } from "@dnd-kit/core";
+import { useDraggable, useDroppable } from "@dnd-kit/core";
 import { motion } from "framer-motion";
 import { useEffect, useRef } from "react";
 import usePlanner from "@/state/planner";
 import { DAY_END, DAY_START, SLOT_MIN, minsToHHMM } from "@/lib/time";
-import { Task } from "@/types";
+import { Block, Task } from "@/types";
 import { cn } from "@/lib/utils";
 
-const SLOT_PX = 3; // Corresponds to 5 minute slots if SLOT_MIN is 5
+export const SLOT_PX = 3; // Corresponds to 5 minute slots if SLOT_MIN is 5
 const PREVIEW_SLOT_MIN = 15;
 
 function DroppableSlot({ absMin }: { absMin: number }) {
@@ -28,6 +28,23 @@ function DroppableSlot({ absMin }: { absMin: number }) {
   );
 }
 
+// Dragging it changes the block's length; AppShell saves the result on drop.
+function ResizeHandle({ block }: { block: Block }) {
+  const { attributes, listeners, setNodeRef } = useDraggable({
+    id: `resize-${block.id}`,
+    data: { type: "BLOCK_RESIZE", block },
+  });
+
+  return (
+    <div
+      ref={setNodeRef}
+      {...attributes}
+      {...listeners}
+      className="absolute left-3 right-3 -bottom-1 h-2 cursor-s-resize z-20"
+    />
+  );
+}
+
 export function MiniDayRail() {
   const tasks = usePlanner(s => s.tasks);
   const blocks = usePlanner(s => s.blocks);
@@ -167,7 +184,7 @@ export function MiniDayRail() {
                   {!isPreview && (
                     <>
                       <div className="absolute left-3 right-3 -top-1 h-2 cursor-n-resize" />
-                      <div className="absolute left-3 right-3 -bottom-1 h-2 cursor-s-resize" />
+                      <ResizeHandle block={block} />
                     </>
                   )}
                 </div>
@@ -19,8 +19,8 @@ vi.mock('@dnd-kit/core', () => ({
 }));
 
 const mockTasks: Task[] = [
-  { id: '1', title: 'Test Task 1 (isToday)', done: false, isToday: true, est_minutes: 25, project: null, tags: [], notes: null, createdAt: '', due: null, priority: 1 },
-  { id: '2', title: 'Test Task 2 (backlog)', done: false, isToday: false, est_minutes: 25, project: null, tags: [], notes: null, createdAt: '', due: null, priority: 1 },
+  { id: '1', title: 'Test Task 1 (isToday)', done: false, isToday: true, est_minutes: 25, project_id: null, tags: [], notes: null, createdAt: '', due: null, priority: 1 },
+  { id: '2', title: 'Test Task 2 (backlog)', done: false, isToday: false, est_minutes: 25, project_id: null, tags: [], notes: null, createdAt: '', due: null, priority: 1 },
 ];
 
 const mockPlannerState = {
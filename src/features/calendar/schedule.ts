@@ -1,4 +1,4 @@
-import { Block, Task } from "@/types";
+import { Availability, Block, Task } from "@/types";
 import usePlanner from "@/state/planner";
 
 function workRemaining(block: Block) {
@@ -7,18 +7,35 @@ function workRemaining(block: Block) {
     return block.lengthMin - used;
 }
 
-function findNextGapThatFits(blocks: Block[], dateISO: string, est: number, dayStartMin = 9 * 60, dayEndMin = 17 * 60) {
-    const todayBlocks = blocks
-        .filter(b => b.dateISO === dateISO)
+// Working hours and unavailable time on a date, falling back to 9-17 until availability loads.
+function workingDay(availability: Availability | null, dateISO: string) {
+    if (!availability) return { start: 9 * 60, end: 17 * 60, unavailable: [] as { startMin: number; lengthMin: number }[] };
+    const weekday = (new Date(`${dateISO}T00:00:00`).getDay() + 6) % 7;
+    const hours = availability.days.find(d => d.weekday === weekday);
+    if (!hours || !hours.working) return null;
+    const unavailable = availability.blocked
+        .filter(p => p.weekday === weekday || p.date === dateISO)
+        .map(p => ({ startMin: p.start_min, lengthMin: p.end_min - p.start_min }));
+    if (hours.lunch_start_min !== null && hours.lunch_length_min > 0) {
+        unavailable.push({ startMin: hours.lunch_start_min, lengthMin: hours.lunch_length_min });
+    }
+    return { start: hours.start_min, end: hours.end_min, unavailable };
+}
+
+function findNextGapThatFits(blocks: Block[], dateISO: string, est: number, availability: Availability | null) {
+    const day = workingDay(availability, dateISO);
+    if (!day) return null;
+    const busy = [...blocks.filter(b => b.dateISO === dateISO), ...day.unavailable]
         .sort((a, b) => a.startMin - b.startMin);
+    const dayEndMin = day.end;
 
-    let lastEnd = dayStartMin;
+    let lastEnd = day.start;
 
-    for (const block of todayBlocks) {
+    for (const block of busy) {
         if (block.startMin - lastEnd >= est) {
             return { start: lastEnd };
         }
-        lastEnd = block.startMin + block.lengthMin;
+        lastEnd = Math.max(lastEnd, block.startMin + block.lengthMin);
     }
 
     if (dayEndMin - lastEnd >= est) {
@@ -41,7 +58,7 @@ export function scheduleNextFreeSlot(taskId: string, dateISO: string) {
       }
     }
     // 2) find next available atomic slot
-    const slot = findNextGapThatFits(store.blocks, dateISO, est);
+    const slot = findNextGapThatFits(store.blocks, dateISO, est, store.availability);
     if (!slot) {
         console.warn("No free slot found for task", taskId);
         return;
@@ -53,7 +70,7 @@ export function calculateNextBlock(taskId: string, dateISO:string, tasks: Task[]
   const task = tasks.find(t => t.id === taskId);
   if (!task) return null;
   const est = Math.max(5, Math.min(180, Math.round((task.est_minutes ?? 30) / 5) * 5));
-  const slot = findNextGapThatFits(blocks, dateISO, est);
+  const slot = findNextGapThatFits(blocks, dateISO, est, usePlanner.getState().availability);
   if (!slot) return null;
 
   return {
@@ -5,7 +5,7 @@ import { Task, Block } from '@/types';
 const TODAY_ISO = '2025-09-24';
 const TOMORROW_ISO = '2025-09-25';
 
-const mockTasks: Omit<Task, 'est_minutes' | 'notes' | 'project' | 'tags' | 'priority' | 'createdAt'>[] = [
+const mockTasks: Omit<Task, 'est_minutes' | 'notes' | 'project_id' | 'tags' | 'priority' | 'createdAt'>[] = [
     { id: '1', title: 'Scheduled', done: false, isToday: false, due: null },
     { id: '2', title: 'Pinned', done: false, isToday: true, due: null },
     { id: '3', title: 'Due today', done: false, isToday: false, due: '2025-09-24T10:00:00.000Z' },
@@ -29,7 +29,7 @@ describe('selectTodayTasks', () => {
         ...t,
         est_minutes: 30,
         notes: null,
-        project: null,
+        project_id: null,
         tags: [],
         priority: 2,
         createdAt: new Date().toISOString()
@@ -68,7 +68,7 @@ describe('selectTodayTasks', () => {
     });
 
     it('should return a de-duplicated list of tasks with combined origins', () => {
-        const tasks: Task[] = [{ id: '1', title: 'A', done: false, isToday: true, due: '2025-09-24T12:00:00Z', est_minutes: 30, notes: null, project: null, tags: [], priority: 2, createdAt: '' }];
+        const tasks: Task[] = [{ id: '1', title: 'A', done: false, isToday: true, due: '2025-09-24T12:00:00Z', est_minutes: 30, notes: null, project_id: null, tags: [], priority: 2, createdAt: '' }];
         const blocks: Block[] = [{ id: 'b1', taskId: '1', dateISO: TODAY_ISO, kind: 'atomic', startMin: 0, lengthMin: 30 }];
         const result = selectTodayTasks(tasks, blocks, TODAY_ISO);
         expect(result.length).toBe(1);
@@ -78,9 +78,9 @@ describe('selectTodayTasks', () => {
     
     it('should return a sorted array of task objects based on ID', () => {
         const tasks: Task[] = [
-            { id: 'c', title: 'C', done: false, isToday: true, due: null, est_minutes: 30, notes: null, project: null, tags: [], priority: 2, createdAt: '' },
-            { id: 'a', title: 'A', done: false, isToday: true, due: null, est_minutes: 30, notes: null, project: null, tags: [], priority: 2, createdAt: '' },
-            { id: 'b', title: 'B', done: false, isToday: true, due: null, est_minutes: 30, notes: null, project: null, tags: [], priority: 2, createdAt: '' },
+            { id: 'c', title: 'C', done: false, isToday: true, due: null, est_minutes: 30, notes: null, project_id: null, tags: [], priority: 2, createdAt: '' },
+            { id: 'a', title: 'A', done: false, isToday: true, due: null, est_minutes: 30, notes: null, project_id: null, tags: [], priority: 2, createdAt: '' },
+            { id: 'b', title: 'B', done: false, isToday: true, due: null, est_minutes: 30, notes: null, project_id: null, tags: [], priority: 2, createdAt: '' },
         ];
         const result = selectTodayTasks(tasks, [], TODAY_ISO);
         const ids = result.map(t => t.id);
@@ -95,7 +95,7 @@ describe('selectTodayTasks', () => {
     it('should correctly identify tasks across day boundaries (using isSameDayISO)', () => {
         const dateAtStartOfDay = '2025-09-24T00:00:00.000Z';
         const dateAtEndOfDay = '2025-09-24T23:59:59.999Z';
-        const tasks: Task[] = [{ id: '1', title: 'A', done: false, isToday: false, due: dateAtEndOfDay, est_minutes: 30, notes: null, project: null, tags: [], priority: 2, createdAt: '' }];
+        const tasks: Task[] = [{ id: '1', title: 'A', done: false, isToday: false, due: dateAtEndOfDay, est_minutes: 30, notes: null, project_id: null, tags: [], priority: 2, createdAt: '' }];
         
         const result = selectTodayTasks(tasks, [], dateAtStartOfDay);
         const ids = result.map(t => t.id);
@@ -6,15 +6,19 @@ import {
 import { RefObject, useState } from "react";
 import { Block, Task } from "../types";
 import { snapToGrid, yToSlot } from "../lib/timeGrid";
-import { v4 as uuidv4 } from "uuid";
+import usePlanner from "../state/planner";
 
+// Drops are saved through the planner's single-block commands, which check each block's
+// version and reload the day if it changed underneath the drag.
 export const useCalendarDnD = (
   gridRef: RefObject<HTMLDivElement>,
   _tasks: Task[],
   _blocks: Block[],
-  setBlocks: (blocks: Block[] | ((prev: Block[]) => Block[])) => void,
   slotHeight: number = 24
 ) => {
+  const addBlock = usePlanner(s => s.addBlock);
+  const moveBlock = usePlanner(s => s.moveBlock);
+  const resizeBlock = usePlanner(s => s.resizeBlock);
   const [activeTask, setActiveTask] = useState<Task | null>(null);
   const [activeBlock, setActiveBlock] = useState<Block | null>(null);
   const [newBlock, setNewBlock] = useState<Block | null>(null);
@@ -24,7 +28,7 @@ export const useCalendarDnD = (
     const { active } = event;
     const { type, task, block } = active.data.current ?? {};
     if (type === "TASK") setActiveTask(task);
-    if (type === "BLOCK") setActiveBlock(block);
+    if (type === "BLOCK" || type === "BLOCK_RESIZE") setActiveBlock(block);
   };
 
   const handleDragMove = (event: DragMoveEvent) => {
@@ -58,17 +62,30 @@ export const useCalendarDnD = (
         lengthMin: activeBlock.lengthMin,
       });
     }
+
+    if (type === "BLOCK_RESIZE" && activeBlock) {
+      const lengthOffset = Math.round(snapToGrid(delta.y, slotHeight) / slotHeight);
+      setPreviewBlock({
+        ...activeBlock,
+        lengthMin: Math.max(15, activeBlock.lengthMin + lengthOffset * 15),
+      });
+    }
   };
 
   const handleDragEnd = (event: DragEndEvent) => {
-    const { over } = event;
+    const { over, active } = event;
     if (newBlock && over?.id === "calendar-grid") {
-      setBlocks((prev) => [...prev, { ...newBlock, id: uuidv4() }]);
+      const { id: _, ...block } = newBlock;
+      addBlock(block, newBlock.dateISO);
     }
-    if (previewBlock && over?.id === "calendar-grid") {
-      setBlocks((prev) =>
-        prev.map((b) => (b.id === previewBlock.id ? previewBlock : b))
-      );
+    if (previewBlock && activeBlock && over?.id === "calendar-grid") {
+      if (active.data.current?.type === "BLOCK_RESIZE") {
+        if (previewBlock.lengthMin !== activeBlock.lengthMin) {
+          resizeBlock(previewBlock.id, previewBlock.lengthMin);
+        }
+      } else if (previewBlock.startMin !== activeBlock.startMin) {
+        moveBlock(previewBlock.id, previewBlock.dateISO, previewBlock.startMin);
+      }
     }
     setActiveTask(null);
     setActiveBlock(null);
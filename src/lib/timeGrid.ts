@@ -3,7 +3,7 @@ import { Block, Task } from "../types";
 export const DRAG_DATA_KEY = "application/cadence-dnd";
 
 export type DragData = {
-  type: "TASK" | "BLOCK";
+  type: "TASK" | "BLOCK" | "BLOCK_RESIZE"; // BLOCK_RESIZE drags a block's bottom edge
   task?: Task;
   block?: Block;
 };
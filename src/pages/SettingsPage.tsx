@@ -13,7 +13,8 @@ export default function SettingsPage() {
 
   const handlePurge = async () => {
     try {
-      await invoke("purge_all_data");
+      const summary = await invoke<{ backup_id: string; tasks: number }>("purge_all_data");
+      toast.success(`Removed ${summary.tasks} tasks. A backup was saved as ${summary.backup_id}`);
       // Refetch data or reload the app to reflect the changes
       fetchTasks();
       fetchBlocks(new Date().toISOString().split('T')[0]);
@@ -17,8 +17,8 @@ vi.mock('sonner', () => ({
 const initialState = usePlanner.getState();
 
 const mockTasks: Task[] = [
-  { id: '1', title: 'Task 1', done: false, isToday: false, est_minutes: 30, notes: null, project: null, tags: [], priority: 2, createdAt: '', due: null },
-  { id: '2', title: 'Task 2', done: false, isToday: true, est_minutes: 60, notes: null, project: null, tags: [], priority: 1, createdAt: '', due: null },
+  { id: '1', title: 'Task 1', done: false, isToday: false, est_minutes: 30, notes: null, project_id: null, tags: [], priority: 2, createdAt: '', due: null },
+  { id: '2', title: 'Task 2', done: false, isToday: true, est_minutes: 60, notes: null, project_id: null, tags: [], priority: 1, createdAt: '', due: null },
 ];
 
 describe('planner store: toggleToday', () => {
@@ -1,6 +1,6 @@
 import { create } from "zustand";
 import { devtools } from 'zustand/middleware';
-import { Block, Task, WorkItem } from "../types";
+import { Availability, Block, RecoveryReport, SalvageReport, Task, WorkItem } from "../types";
 import { ParsedTask } from "../types/composer";
 import { invoke } from "@tauri-apps/api/core";
 import { v4 as uuidv4 } from "uuid";
@@ -10,10 +10,29 @@ import { toast } from "sonner";
 type BackendTask = Omit<Task, 'isToday'> & { is_today: boolean };
 type BackendBlock = {
   id: string;
-  task_id: string;
+  task_id: string | null;
   date: string;
-  start_slot: number;
-  end_slot: number;
+  start_min: number;
+  length_min: number;
+  kind: Block['kind'];
+  items: { task_id: string; est_minutes: number }[];
+  version?: number;
+};
+
+const fromBackendBlock = (b: BackendBlock): Block => ({
+  id: b.id,
+  taskId: b.task_id ?? undefined,
+  dateISO: b.date,
+  startMin: b.start_min,
+  lengthMin: b.length_min,
+  kind: b.kind,
+  items: b.items.map(i => ({ taskId: i.task_id, est_minutes: i.est_minutes })),
+  version: b.version,
+});
+
+type SaveBlocksReport = {
+  saved: boolean;
+  conflicts: ({ kind: string; block_id: string } & Record<string, unknown>)[];
 };
 
 export type State = {
@@ -23,16 +42,26 @@ export type State = {
   activeFocus: string[];
   previewBlock: Block | null;
   isHoveringMiniDayRail: boolean;
+  availability: Availability | null;
+  recovery: RecoveryReport | null;
 }
 
 type Actions = {
+  fetchRecoveryStatus: () => Promise<RecoveryReport | null>;
+  recoverFromBackup: () => Promise<void>;
+  exportSalvage: () => Promise<void>;
   fetchTasks: () => Promise<void>;
   fetchBlocks: (date: string) => Promise<void>;
+  fetchAvailability: () => Promise<void>;
+  saveAvailability: (availability: Availability) => Promise<void>;
   addTask: (task: ParsedTask) => Promise<void>;
   updateTask: (id: string, updates: Partial<Task>) => Promise<void>;
   deleteTask: (id: string) => Promise<void>;
   setBlocks: (blocks: Block[] | ((prev: Block[]) => Block[])) => void;
-  addBlock: (block: Omit<Block, 'id'>, date: string) => void;
+  addBlock: (block: Omit<Block, 'id'>, date: string) => Promise<void>;
+  moveBlock: (id: string, dateISO: string, startMin: number) => Promise<void>;
+  resizeBlock: (id: string, lengthMin: number) => Promise<void>;
+  deleteBlock: (id: string) => Promise<void>;
   addAtomicBlock: (block: Omit<Block, 'id' | 'kind'>) => void;
   addWorkItem: (blockId: string, item: WorkItem) => void;
   saveBlocks: (date: string) => void;
@@ -55,6 +84,31 @@ const usePlanner = create<State & Actions>()(
       activeFocus: [],
       previewBlock: null,
       isHoveringMiniDayRail: false,
+      availability: null,
+      recovery: null,
+      fetchRecoveryStatus: async () => {
+        const recovery = await invoke<RecoveryReport | null>("get_recovery_status");
+        set({ recovery });
+        return recovery;
+      },
+      recoverFromBackup: async () => {
+        try {
+          const restored = await invoke<{ id: string }>("recover_from_backup");
+          set({ recovery: null });
+          toast.success(`Restored ${restored.id}`);
+        } catch (error) {
+          toast.error(String(error));
+        }
+      },
+      exportSalvage: async () => {
+        try {
+          const report = await invoke<SalvageReport>("export_salvage");
+          const rows = report.tables.reduce((sum, t) => sum + t.rows, 0);
+          toast.success(`Exported ${rows} rows to ${report.path}`);
+        } catch (error) {
+          toast.error(String(error));
+        }
+      },
       fetchTasks: async () => {
         const backendTasks = await invoke<BackendTask[]>("get_tasks");
         const tasks: Task[] = backendTasks.map(t => ({
@@ -65,15 +119,15 @@ const usePlanner = create<State & Actions>()(
       },
       fetchBlocks: async (date) => {
         const backendBlocks = await invoke<BackendBlock[]>("get_blocks_for_date", { date });
-        const frontendBlocks: Block[] = backendBlocks.map(b => ({
-          id: b.id,
-          taskId: b.task_id,
-          dateISO: b.date,
-          startMin: b.start_slot * 15,
-          lengthMin: (b.end_slot - b.start_slot) * 15,
-          kind: 'atomic', 
-        }));
-        set({ blocks: frontendBlocks });
+        set({ blocks: backendBlocks.map(fromBackendBlock) });
+      },
+      fetchAvailability: async () => {
+        const availability = await invoke<Availability>("get_availability");
+        set({ availability });
+      },
+      saveAvailability: async (availability) => {
+        const saved = await invoke<Availability>("set_availability", { availability });
+        set({ availability: saved });
       },
       addTask: async (task) => {
         const newTask: Task = {
@@ -87,7 +141,7 @@ const usePlanner = create<State & Actions>()(
           createdAt: new Date().toISOString(),
           due: null,
           notes: null,
-          project: null,
+          project_id: null,
         };
 
         const { isToday, ...rest } = newTask;
@@ -104,9 +158,15 @@ const usePlanner = create<State & Actions>()(
         const { isToday, ...rest } = updatedTask;
         const backendTask = { ...rest, is_today: isToday };
 
-        await invoke("update_task", { task: backendTask });
+        // Completing a recurring task returns its next instance.
+        const next = await invoke<BackendTask | null>("update_task", { task: backendTask });
+        // The rule moves to the new instance.
+        const saved = next ? { ...updatedTask, recurrence: null } : updatedTask;
         set((state) => ({
-          tasks: state.tasks.map((t) => (t.id === id ? updatedTask : t)),
+          tasks: [
+            ...state.tasks.map((t) => (t.id === id ? saved : t)),
+            ...(next ? [{ ...next, isToday: next.is_today }] : []),
+          ],
         }));
       },
       deleteTask: async (id) => {
@@ -120,10 +180,63 @@ const usePlanner = create<State & Actions>()(
           set({ blocks });
         }
       },
-      addBlock: (block, date) => {
-        const newBlock = { ...block, id: uuidv4() };
-        set((state) => ({ blocks: [...state.blocks, newBlock] }));
-        get().saveBlocks(date);
+      addBlock: async (block, date) => {
+        try {
+          const created = await invoke<BackendBlock>("create_block", {
+            block: {
+              id: uuidv4(),
+              task_id: block.taskId ?? null,
+              date,
+              start_min: block.startMin,
+              length_min: block.lengthMin,
+              kind: block.kind,
+              items: (block.items ?? []).map(i => ({ task_id: i.taskId, est_minutes: i.est_minutes })),
+            },
+          });
+          set((state) => ({ blocks: [...state.blocks, fromBackendBlock(created)] }));
+        } catch (error) {
+          toast.error(String(error));
+          get().fetchBlocks(date);
+        }
+      },
+      // Single-block edits send the version they were based on; the backend refuses them if
+      // the block changed in the meantime, and the day is reloaded.
+      moveBlock: async (id, dateISO, startMin) => {
+        const block = get().blocks.find(b => b.id === id);
+        if (!block) return;
+        try {
+          const moved = await invoke<BackendBlock>("move_block", {
+            id, version: block.version ?? 0, date: dateISO, startMin,
+          });
+          set((state) => ({ blocks: state.blocks.map(b => (b.id === id ? fromBackendBlock(moved) : b)) }));
+        } catch (error) {
+          toast.error(String(error));
+          get().fetchBlocks(block.dateISO);
+        }
+      },
+      resizeBlock: async (id, lengthMin) => {
+        const block = get().blocks.find(b => b.id === id);
+        if (!block) return;
+        try {
+          const resized = await invoke<BackendBlock>("resize_block", {
+            id, version: block.version ?? 0, lengthMin,
+          });
+          set((state) => ({ blocks: state.blocks.map(b => (b.id === id ? fromBackendBlock(resized) : b)) }));
+        } catch (error) {
+          toast.error(String(error));
+          get().fetchBlocks(block.dateISO);
+        }
+      },
+      deleteBlock: async (id) => {
+        const block = get().blocks.find(b => b.id === id);
+        if (!block) return;
+        try {
+          await invoke<BackendBlock>("delete_block", { id, version: block.version ?? 0 });
+          set((state) => ({ blocks: state.blocks.filter(b => b.id !== id) }));
+        } catch (error) {
+          toast.error(String(error));
+          get().fetchBlocks(block.dateISO);
+        }
       },
       addAtomicBlock: (block) => {
         const newBlock: Block = {
@@ -154,15 +267,26 @@ const usePlanner = create<State & Actions>()(
         }
         const blocksToSave = get().blocks
           .filter(b => b.dateISO === date)
-          .map(b => ({
+          .map((b): BackendBlock => ({
             id: b.id,
-            task_id: b.taskId,
+            task_id: b.taskId ?? null,
             date: b.dateISO,
-            start_slot: Math.floor(b.startMin / 15),
-            end_slot: Math.floor((b.startMin + b.lengthMin) / 15),
+            start_min: b.startMin,
+            length_min: b.lengthMin,
+            kind: b.kind,
+            items: (b.items ?? []).map(i => ({ task_id: i.taskId, est_minutes: i.est_minutes })),
+            version: b.version,
           }));
 
-        invoke("save_blocks_for_date", { date: date, blocks: blocksToSave });
+        invoke<SaveBlocksReport>("save_blocks_for_date", { date: date, blocks: blocksToSave })
+          .then((report) => {
+            if (report.conflicts.length > 0) {
+              console.warn("Saved blocks with conflicts:", report.conflicts);
+              toast.warning(`${report.conflicts.length} scheduling conflict(s) on ${date}`);
+            }
+            // Every block saved gets a new version, and conflicting ones may have been dropped.
+            get().fetchBlocks(date);
+          });
       },
       toggleTask: (id: string) => {
         const task = get().tasks.find((t) => t.id === id);
@@ -7,11 +7,15 @@ export type Task = {
   isToday: boolean;
   est_minutes: number; // minutes
   notes: string | null;
-  project: string | null;
+  project_id: string | null;
   tags: string[] | null;
   priority: Priority;
   createdAt: string; // ISO string
   due: string | null; // ISO string
+  recurrence?: string | null; // RRULE, e.g. "FREQ=WEEKLY;BYDAY=MO,WE"
+  parent_id?: string | null;
+  blocked?: boolean; // set by the backend: a prerequisite isn't done yet
+  completed_at?: string | null; // set by the backend when the task is marked done
 };
 
 export type Session = {
@@ -35,4 +39,37 @@ export type Block = {
   kind: "atomic" | "work";
   taskId?: string; // for atomic blocks
   items?: WorkItem[]; // for work blocks
+  version?: number; // set by the backend, bumped on every change
+};
+
+export type DayHours = {
+  weekday: number; // 0 = Monday
+  working: boolean;
+  start_min: number; // minutes from midnight
+  end_min: number;
+  lunch_start_min: number | null;
+  lunch_length_min: number;
+};
+
+export type BlockedPeriod = {
+  id: string;
+  weekday: number | null; // every week, or
+  date: string | null; // once, YYYY-MM-DD
+  start_min: number;
+  end_min: number;
+  label: string | null;
+};
+
+export type Availability = {
+  days: DayHours[]; // one per weekday, Monday first
+  blocked: BlockedPeriod[];
+};
+
+export type RecoveryReport = {
+  problems: string[]; // why the database failed its startup checks
+};
+
+export type SalvageReport = {
+  path: string;
+  tables: { name: string; rows: number; error: string | null }[];
 };